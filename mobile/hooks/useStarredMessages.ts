@@ -3,6 +3,7 @@ import { useSocket } from '../lib/socket';
 import type { StarMessageRequest } from '@/types/server/StarMessageRequest';
 import type { UnstarMessageRequest } from '@/types/server/UnstarMessageRequest';
 import type { StarredMessagesResponse } from '@/types/server/StarredMessagesResponse';
+import type { GetStarredMessagesRequest } from '@/types/server/GetStarredMessagesRequest';
 
 interface UseStarredMessagesReturn {
     starredMessageIds: Set<string>;
@@ -22,7 +23,7 @@ export function useStarredMessages(roomId?: string): UseStarredMessagesReturn {
         if (!socket) return;
 
         const handleStarredMessagesList = (response: StarredMessagesResponse) => {
-            setStarredMessageIds(new Set(response.starred_message_ids));
+            setStarredMessageIds(new Set(response.items.map((item) => item.message.id)));
             setIsLoading(false);
         };
 
@@ -95,8 +96,14 @@ export function useStarredMessages(roomId?: string): UseStarredMessagesReturn {
         (roomId: string) => {
             if (!socket) return;
 
+            const payload: GetStarredMessagesRequest = {
+                room_id: roomId,
+                offset: 0,
+                limit: 100,
+            };
+
             setIsLoading(true);
-            socket.emit('starred_messages.get', roomId);
+            socket.emit('starred_messages.get', payload);
         },
         [socket]
     );
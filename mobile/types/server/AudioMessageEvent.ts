@@ -0,0 +1,26 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AudioCodec } from "./AudioCodec";
+import type { MessageReply } from "./MessageReply";
+
+/**
+ * A voice note or audio clip. The audio itself is uploaded separately with
+ * `media.upload` and referenced by `blob_id`.
+ */
+export type AudioMessageEvent = { blob_id: string, 
+/**
+ * Checked against the container metadata when the message is sent.
+ */
+duration_ms: number, codec: AudioCodec, 
+/**
+ * Peak levels from 0 to 255 sampled across the clip, for drawing a
+ * waveform without downloading the audio.
+ */
+waveform: Array<number>, 
+/**
+ * Filled in by the server from the stored blob.
+ */
+mime_type: string, 
+/**
+ * Filled in by the server from the stored blob.
+ */
+size: number, deleted: boolean, reply_to: MessageReply | null, expires_at: string | null, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type AuditAction = "RoomCreated" | "UsernameChanged" | "UserBanned" | "RoleChanged" | "MessageDeleted" | "RoomPolicyChanged" | "TopicChanged" | "SlowModeChanged" | "FiltersChanged" | "WebhookCreated" | "WebhookDeleted" | "IncomingWebhookCreated" | "IncomingWebhookDeleted" | "BotCreated" | "BotDeleted";
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuditAction } from "./AuditAction";
+
+export type AuditEntry = { id: string, timestamp: string, action: AuditAction, actor: String, actor_username: string | null, room_id: string | null, 
+/**
+ * The id of the user, message or room the action was applied to.
+ */
+target: string | null, before: string | null, after: string | null, };
@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AuditAction } from "./AuditAction";
+
+/**
+ * Narrows down an audit log query. Every field is optional.
+ */
+export type AuditQuery = { room_id: string | null, action: AuditAction | null, 
+/**
+ * Only entries recorded at or after this time.
+ */
+since: string | null, 
+/**
+ * Only entries recorded before this time, for paging back.
+ */
+until: string | null, 
+/**
+ * At most this many entries, 100 unless given and never more than 1000.
+ */
+limit: number | null, };
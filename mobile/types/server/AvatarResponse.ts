@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type AvatarResponse = { user_id: String, avatar_id: string, mime_type: string, 
+/**
+ * Base64 encoded image.
+ */
+data: string, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A blocked user. Offline users who never picked a name have neither field
+ * set, and can be unblocked once they reconnect.
+ */
+export type BlockedUser = { 
+/**
+ * One of the user's connections, or nothing while they are offline.
+ */
+user_id: String | null, username: string | null, };
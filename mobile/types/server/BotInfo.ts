@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A bot as shown to its owner. The token is only returned when the bot is
+ * created.
+ */
+export type BotInfo = { id: string, name: string, created_at: string, 
+/**
+ * Pass as `bot_token` in the connect handshake to connect as the bot.
+ */
+token: string | null, connected: boolean, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Optional protocol features.
+ */
+export type Capability = "markdown" | "mentions" | "link_previews" | "pins" | "polls" | "audio" | "direct_messages" | "scheduled_messages" | "room_topics";
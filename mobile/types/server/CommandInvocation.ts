@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent to a bot when someone runs one of its commands.
+ */
+export type CommandInvocation = { 
+/**
+ * Pass to `command.respond` to answer.
+ */
+invocation_id: string, room_id: string, command: string, 
+/**
+ * Everything after the command name.
+ */
+args: string, user_id: String, username: string | null, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type CommandRespondPayload = { invocation_id: string, content: string, 
+/**
+ * Post the reply to the room as the bot instead of showing it only to
+ * the user who ran the command.
+ */
+public: boolean, };
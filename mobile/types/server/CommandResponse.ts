@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A reply to a command, visible only to the user who ran it.
+ */
+export type CommandResponse = { room_id: string, command: string, content: string, 
+/**
+ * The bot that answered, if the command was not a built-in.
+ */
+bot_id: String | null, bot_name: string | null, };
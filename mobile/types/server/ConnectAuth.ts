@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Auth payload clients may send with the socket.io handshake.
+ */
+export type ConnectAuth = { 
+/**
+ * Key returned in a previous `session.info`, used to restore per-user data.
+ */
+user_key: string | null, 
+/**
+ * Protocol version the client was built against. Clients that leave it
+ * out are treated as speaking version 1.
+ */
+protocol_version: number | null, 
+/**
+ * Capabilities the client understands. Defaults to everything its
+ * protocol version includes; unknown names are ignored.
+ */
+capabilities: Array<string> | null, 
+/**
+ * Connects as a bot instead of a user.
+ */
+bot_token: string | null, };
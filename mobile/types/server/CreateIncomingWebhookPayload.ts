@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type CreateIncomingWebhookPayload = { room_id: string, 
+/**
+ * Name the integration's messages are shown under.
+ */
+name: string, };
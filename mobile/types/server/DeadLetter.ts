@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { WebhookPayload } from "./WebhookPayload";
+
+/**
+ * A delivery that ran out of attempts.
+ */
+export type DeadLetter = { delivery_id: string, webhook_id: string, payload: WebhookPayload, attempts: number, last_error: string, failed_at: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeliveryOutcome } from "./DeliveryOutcome";
+
+/**
+ * One attempt at delivering an event to a webhook.
+ */
+export type DeliveryAttempt = { delivery_id: string, webhook_id: string, event_id: string, attempt: number, outcome: DeliveryOutcome, status_code: number | null, error: string | null, duration_ms: bigint, timestamp: string, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DirectParticipant } from "./DirectParticipant";
+
+export type DirectConversation = { room_id: string, 
+/**
+ * The other participants' names, as seen by the receiving user.
+ */
+name: string, 
+/**
+ * Everyone in the conversation, including the receiving user.
+ */
+participants: Array<DirectParticipant>, };
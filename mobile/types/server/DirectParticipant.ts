@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type DirectParticipant = { 
+/**
+ * One of the participant's connections, or nothing while they are offline.
+ */
+user_id: String | null, username: string | null, };
@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FormattedBody } from "./FormattedBody";
 
-export type EditMessagePayload = { room: string, message_id: string, new_content: string, };
+export type EditMessagePayload = { room: string, message_id: string, new_content: string, formatted: FormattedBody | null, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type FilterKind = { "Blocklist": { words: Array<string>, } } | { "Pattern": { pattern: string, } } | { "LinkAllowlist": { domains: Array<string>, } } | { "Caps": { max_percent: number, min_letters: number, } } | { "Repeat": { max_repeats: number, } };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FilterAction } from "./FilterAction";
+import type { FilterKind } from "./FilterKind";
+
+export type FilterRule = { filter: FilterKind, action: FilterAction, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FlaggedMessage } from "./FlaggedMessage";
+
+export type FlaggedListResponse = { room_id: string, 
+/**
+ * Oldest first.
+ */
+messages: Array<FlaggedMessage>, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A delivered message that matched a `Flag` rule.
+ */
+export type FlaggedMessage = { id: string, room_id: string, message_id: string, user_id: String, username: string | null, 
+/**
+ * The message as it read when it was flagged.
+ */
+content: string, reasons: Array<string>, flagged_at: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type GetReportsPayload = { room_id: string, 
+/**
+ * Also list reports moderators have already dealt with.
+ */
+include_closed: boolean, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type GetStarredMessagesRequest = { 
+/**
+ * Restrict the results to a single room.
+ */
+room_id: string | null, offset: number, limit: number | null, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type GetWebhookDeliveriesPayload = { room_id: string, 
+/**
+ * Limits the log to one webhook.
+ */
+webhook_id: string | null, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * An incoming webhook as shown to room moderators. The token is only
+ * returned when the webhook is created.
+ */
+export type IncomingWebhookInfo = { id: string, room_id: string, name: string, user_id: String, created_at: string, 
+/**
+ * Post to `/api/hooks/{token}` to send a message.
+ */
+token: string | null, };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type LinkPreview = { url: string, title: string | null, description: string | null, image_url: string | null, site_name: string | null, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type MediaBlobResponse = { room_id: string, message_id: string, mime_type: string, 
+/**
+ * Base64 encoded file contents.
+ */
+data: string, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type MentionNotification = { room_id: string, room_name: string, message_id: string, from: String, from_username: string | null, content_preview: string, 
+/**
+ * Whether the user was reached through `@room` rather than by name.
+ */
+room_mention: boolean, 
+/**
+ * Unread mentions of the user in this room, including this one.
+ */
+unread_count: number, };
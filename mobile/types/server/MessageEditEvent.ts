@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FormattedBody } from "./FormattedBody";
 
-export type MessageEditEvent = { message_id: string, new_content: string, };
+export type MessageEditEvent = { message_id: string, new_content: string, mentions: Array<String>, mentions_room: boolean, formatted: FormattedBody | null, };
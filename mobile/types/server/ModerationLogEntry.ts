@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ModeratorAction } from "./ModeratorAction";
+
+/**
+ * One moderator action, kept for later review.
+ */
+export type ModerationLogEntry = { id: string, room_id: string, report_id: string, message_id: string, action: ModeratorAction, moderator: String, moderator_username: string | null, target: String, target_username: string | null, note: string | null, timestamp: string, };
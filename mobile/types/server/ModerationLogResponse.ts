@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ModerationLogEntry } from "./ModerationLogEntry";
+
+export type ModerationLogResponse = { room_id: string, 
+/**
+ * Oldest first.
+ */
+entries: Array<ModerationLogEntry>, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent to every socket of a user a moderator warned.
+ */
+export type ModerationWarning = { room_id: string, message_id: string, reason: string, };
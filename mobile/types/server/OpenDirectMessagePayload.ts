@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type OpenDirectMessagePayload = { 
+/**
+ * The other users to talk to.
+ */
+user_ids: Array<String>, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RoomEvent } from "./RoomEvent";
+
+export type PinnedMessagesResponse = { room_id: string, messages: Array<RoomEvent>, };
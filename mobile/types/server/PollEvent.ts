@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PollOption } from "./PollOption";
+
+export type PollEvent = { question: string, options: Array<PollOption>, multiple_choice: boolean, 
+/**
+ * Hides who voted for what; only the tallies are shared.
+ */
+anonymous: boolean, closes_at: string | null, closed: boolean, total_voters: number, };
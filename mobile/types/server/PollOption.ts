@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PollOption = { text: string, votes: number, 
+/**
+ * Who voted for this option, left empty for anonymous polls.
+ */
+voters: Array<String>, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PollOption } from "./PollOption";
+
+export type PollUpdateEvent = { poll_id: string, options: Array<PollOption>, total_voters: number, closed: boolean, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The parts of a profile shown next to a member's name.
+ */
+export type ProfileSummary = { display_name: string | null, avatar_id: string | null, };
@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent instead of `error` when a request is refused for being too frequent.
+ */
+export type RateLimitError = { 
+/**
+ * The event that was refused.
+ */
+event: string, message: string, 
+/**
+ * How long to wait before the request will be accepted.
+ */
+retry_after_ms: bigint, room_id: string | null, 
+/**
+ * Refused by the room's slow mode rather than the user's own limit.
+ */
+slow_mode: boolean, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RedeliverWebhookPayload = { room_id: string, 
+/**
+ * A delivery from the dead-letter list.
+ */
+delivery_id: string, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BotCommand } from "./BotCommand";
+
+export type RegisterCommandsPayload = { 
+/**
+ * Replaces any commands registered earlier on this connection.
+ */
+commands: Array<BotCommand>, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Resolution } from "./Resolution";
+import type { RoomEvent } from "./RoomEvent";
+
+export type Report = { id: string, room_id: string, message_id: string, reported_user: String, reported_username: string | null, reporter: String, reporter_username: string | null, reason: string, 
+/**
+ * The message as it was when it was reported.
+ */
+message: RoomEvent, created_at: string, 
+/**
+ * Set once a moderator has dealt with the report.
+ */
+resolution: Resolution | null, };
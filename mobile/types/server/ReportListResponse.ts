@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Report } from "./Report";
+
+export type ReportListResponse = { room_id: string, 
+/**
+ * Oldest first.
+ */
+reports: Array<Report>, };
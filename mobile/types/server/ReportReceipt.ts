@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Confirms a report to the member who filed it.
+ */
+export type ReportReceipt = { report_id: string, room_id: string, message_id: string, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ModeratorAction } from "./ModeratorAction";
+
+export type ResolveReportPayload = { room_id: string, report_id: string, action: ModeratorAction, 
+/**
+ * Shown to the author with a warning or ban, and kept in the log.
+ */
+note: string | null, };
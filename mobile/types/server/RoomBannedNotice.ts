@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent to every socket of a user banned from a room.
+ */
+export type RoomBannedNotice = { room_id: string, reason: string | null, };
@@ -1,13 +1,20 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AudioMessageEvent } from "./AudioMessageEvent";
 import type { ImageMessageEvent } from "./ImageMessageEvent";
+import type { LinkPreviewEvent } from "./LinkPreviewEvent";
 import type { MessageDeleteEvent } from "./MessageDeleteEvent";
 import type { MessageEditEvent } from "./MessageEditEvent";
+import type { MessagePinEvent } from "./MessagePinEvent";
 import type { MessageStarEvent } from "./MessageStarEvent";
+import type { MessageUnpinEvent } from "./MessageUnpinEvent";
 import type { MessageUnstarEvent } from "./MessageUnstarEvent";
+import type { PollEvent } from "./PollEvent";
+import type { PollUpdateEvent } from "./PollUpdateEvent";
 import type { ReactionEvent } from "./ReactionEvent";
 import type { ReactionRemoveEvent } from "./ReactionRemoveEvent";
 import type { TextMessageEvent } from "./TextMessageEvent";
+import type { TopicChangeEvent } from "./TopicChangeEvent";
 import type { UserJoinEvent } from "./UserJoinEvent";
 import type { UserLeaveEvent } from "./UserLeaveEvent";
 
-export type RoomEventData = { "Message": TextMessageEvent } | { "Image": ImageMessageEvent } | { "MessageEdit": MessageEditEvent } | { "MessageDelete": MessageDeleteEvent } | { "Reaction": ReactionEvent } | { "ReactionRemove": ReactionRemoveEvent } | { "UserJoin": UserJoinEvent } | { "UserLeave": UserLeaveEvent } | { "MessageStar": MessageStarEvent } | { "MessageUnstar": MessageUnstarEvent };
+export type RoomEventData = { "Message": TextMessageEvent } | { "Image": ImageMessageEvent } | { "Audio": AudioMessageEvent } | { "MessageEdit": MessageEditEvent } | { "MessageDelete": MessageDeleteEvent } | { "Reaction": ReactionEvent } | { "ReactionRemove": ReactionRemoveEvent } | { "UserJoin": UserJoinEvent } | { "UserLeave": UserLeaveEvent } | { "MessageStar": MessageStarEvent } | { "MessageUnstar": MessageUnstarEvent } | { "MessagePin": MessagePinEvent } | { "MessageUnpin": MessageUnpinEvent } | { "LinkPreview": LinkPreviewEvent } | { "Poll": PollEvent } | { "PollUpdate": PollUpdateEvent } | { "TopicChange": TopicChangeEvent };
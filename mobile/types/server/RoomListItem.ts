@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type RoomListItem = { id: string, name: string, member_count: number, };
+export type RoomListItem = { id: string, name: string, member_count: number, topic: string | null, };
@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ProfileSummary } from "./ProfileSummary";
 
-export type RoomMember = { user_id: String, username: string | null, };
+export type RoomMember = { user_id: String, username: string | null, is_bot: boolean, profile: ProfileSummary | null, };
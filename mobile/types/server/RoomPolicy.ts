@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PinPermission } from "./PinPermission";
+
+export type RoomPolicy = { 
+/**
+ * How long after sending a message its author may still edit it.
+ */
+edit_window_secs: number | null, 
+/**
+ * How long after sending a message its author may still delete it.
+ */
+delete_window_secs: number | null, 
+/**
+ * Events older than this are pruned by the retention task.
+ */
+retention_days: number | null, 
+/**
+ * Only the newest `max_events` events are kept by the retention task.
+ */
+max_events: number | null, 
+/**
+ * Default lifetime of new messages, after which their content is removed.
+ */
+message_ttl_secs: number | null, pin_permission: PinPermission, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SendEventPayload } from "./SendEventPayload";
+
+export type ScheduleMessagePayload = { send_at: string, message: SendEventPayload, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SendEventPayload } from "./SendEventPayload";
+
+export type ScheduledMessage = { id: string, 
+/**
+ * Identifies the author across reconnects and restarts. Only ever sent
+ * to the author themselves.
+ */
+author_key: string, send_at: string, created_at: string, message: SendEventPayload, 
+/**
+ * Why the message could not be sent when it was due. Failed messages
+ * are kept until the author reschedules or cancels them.
+ */
+failure: string | null, };
@@ -1,4 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { RoomEventData } from "./RoomEventData";
 
-export type SendEventPayload = { room: string, payload: RoomEventData, };
+export type SendEventPayload = { room: string, payload: RoomEventData, 
+/**
+ * Seconds until the message disappears, overriding the room default.
+ */
+ttl_secs: number | null, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Capability } from "./Capability";
+
+export type SessionInfo = { user_key: string, 
+/**
+ * The protocol version negotiated for this connection.
+ */
+protocol_version: number, server_version: string, 
+/**
+ * Every capability the server supports, whether or not the client
+ * asked for it.
+ */
+capabilities: Array<Capability>, };
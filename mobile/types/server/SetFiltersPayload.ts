@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ModerationConfig } from "./ModerationConfig";
+
+export type SetFiltersPayload = { room_id: string, 
+/**
+ * Replaces every rule of the room. No rules turns filtering off.
+ */
+config: ModerationConfig, };
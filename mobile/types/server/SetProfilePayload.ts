@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UserProfile } from "./UserProfile";
+
+export type SetProfilePayload = { 
+/**
+ * Replaces the whole profile. Upload a new avatar with `media.upload`
+ * first and pass its blob id.
+ */
+profile: UserProfile, };
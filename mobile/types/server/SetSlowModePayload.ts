@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SetSlowModePayload = { room_id: string, 
+/**
+ * Seconds each member must wait between messages. 0 turns slow mode
+ * off.
+ */
+interval_secs: number, 
+/**
+ * Turn slow mode off again after this many seconds.
+ */
+duration_secs: number | null, };
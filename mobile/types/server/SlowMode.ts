@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Limits every member except moderators to one message per interval.
+ */
+export type SlowMode = { interval_secs: number, 
+/**
+ * Slow mode turns itself off at this time. Without it, slow mode stays
+ * on until a moderator turns it off.
+ */
+until: string | null, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SlowMode } from "./SlowMode";
+
+export type SlowModeResponse = { room_id: string, slow_mode: SlowMode | null, };
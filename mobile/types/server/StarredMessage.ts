@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RoomEvent } from "./RoomEvent";
+
+export type StarredMessage = { room_id: string, room_name: string, starred_at: string, 
+/**
+ * The message as it currently is, including any edits.
+ */
+message: RoomEvent, };
@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { StarredMessage } from "./StarredMessage";
 
-export type StarredMessagesResponse = { starred_message_ids: Array<string>, };
+export type StarredMessagesResponse = { items: Array<StarredMessage>, total: number, next_offset: number | null, };
@@ -1,4 +1,26 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FormattedBody } from "./FormattedBody";
+import type { LinkPreview } from "./LinkPreview";
 import type { MessageReply } from "./MessageReply";
 
-export type TextMessageEvent = { content: string, edited: boolean, deleted: boolean, reply_to: MessageReply | null, };
+export type TextMessageEvent = { content: string, edited: boolean, deleted: boolean, reply_to: MessageReply | null, expires_at: string | null, 
+/**
+ * Users mentioned by `@username`, resolved by the server.
+ */
+mentions: Array<String>, 
+/**
+ * Whether the message mentions `@room`.
+ */
+mentions_room: boolean, 
+/**
+ * Optional rich text version of `content`, which then holds the plain fallback.
+ */
+formatted: FormattedBody | null, 
+/**
+ * Previews for links in `content`, filled in by the server after sending.
+ */
+link_previews: Array<LinkPreview>, 
+/**
+ * Sent with `/me`: `content` describes something the sender does.
+ */
+emote: boolean, };
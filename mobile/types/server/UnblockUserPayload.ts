@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Names the user either way `user.blocked_list` does: by connection while
+ * they are online, or by username, which also works while they are offline.
+ */
+export type UnblockUserPayload = { user_id: String | null, username: string | null, };
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type UserProfile = { 
+/**
+ * Shown instead of the username where there is room for it.
+ */
+display_name: string | null, 
+/**
+ * Blob id of an image uploaded with `media.upload`, fetched with
+ * `user.get_avatar`.
+ */
+avatar_id: string | null, bio: string | null, 
+/**
+ * IANA time zone name, such as `Europe/Berlin`.
+ */
+timezone: string | null, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Sent to a room when one of its members changes their username.
+ */
+export type UserRenamedEvent = { room_id: string, user_id: String, old_username: string | null, username: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type VotePollPayload = { room_id: string, poll_id: string, 
+/**
+ * The chosen option indexes. An empty list retracts the vote.
+ */
+options: Array<number>, };
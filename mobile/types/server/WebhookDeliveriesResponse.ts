@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeadLetter } from "./DeadLetter";
+import type { DeliveryAttempt } from "./DeliveryAttempt";
+
+export type WebhookDeliveriesResponse = { room_id: string, 
+/**
+ * Newest first.
+ */
+deliveries: Array<DeliveryAttempt>, dead_letters: Array<DeadLetter>, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A webhook as shown to room moderators. The secret is only returned when
+ * the webhook is created.
+ */
+export type WebhookInfo = { id: string, room_id: string, url: string, created_at: string, secret: string | null, };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { WebhookInfo } from "./WebhookInfo";
+
+export type WebhookListResponse = { room_id: string, webhooks: Array<WebhookInfo>, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RoomEvent } from "./RoomEvent";
+
+/**
+ * The JSON body posted to a webhook.
+ */
+export type WebhookPayload = { webhook_id: string, room_id: string, event: RoomEvent, };
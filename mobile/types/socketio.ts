@@ -5,6 +5,14 @@ import { LeaveRoomPayload } from './server/LeaveRoomPayload';
 import { CreateRoomPayload } from './server/CreateRoomPayload';
 import { RoomListResponse } from './server/RoomListResponse';
 import { SetUsernamePayload } from './server/SetUsernamePayload';
+import { UserRenamedEvent } from './server/UserRenamedEvent';
+import { SetProfilePayload } from './server/SetProfilePayload';
+import { GetProfilePayload } from './server/GetProfilePayload';
+import { ProfileResponse } from './server/ProfileResponse';
+import { AvatarResponse } from './server/AvatarResponse';
+import { BlockUserPayload } from './server/BlockUserPayload';
+import { UnblockUserPayload } from './server/UnblockUserPayload';
+import { BlockedUsersResponse } from './server/BlockedUsersResponse';
 import { GetMembersPayload } from './server/GetMembersPayload';
 import { RoomMembersResponse } from './server/RoomMembersResponse';
 import { StartTypingPayload } from './server/StartTypingPayload';
@@ -15,15 +23,115 @@ import { DeleteMessagePayload } from './server/DeleteMessagePayload';
 import { StarMessageRequest } from './server/StarMessageRequest';
 import { UnstarMessageRequest } from './server/UnstarMessageRequest';
 import { StarredMessagesResponse } from './server/StarredMessagesResponse';
+import { GetStarredMessagesRequest } from './server/GetStarredMessagesRequest';
+import { SessionInfo } from './server/SessionInfo';
+import { MentionNotification } from './server/MentionNotification';
+import { MentionCountsResponse } from './server/MentionCountsResponse';
+import { ClearMentionsPayload } from './server/ClearMentionsPayload';
+import { SetRoomPolicyPayload } from './server/SetRoomPolicyPayload';
+import { GetRoomPolicyPayload } from './server/GetRoomPolicyPayload';
+import { RoomPolicyResponse } from './server/RoomPolicyResponse';
+import { ScheduleMessagePayload } from './server/ScheduleMessagePayload';
+import { EditScheduledMessagePayload } from './server/EditScheduledMessagePayload';
+import { CancelScheduledMessagePayload } from './server/CancelScheduledMessagePayload';
+import { ScheduledMessagesResponse } from './server/ScheduledMessagesResponse';
+import { PinMessagePayload } from './server/PinMessagePayload';
+import { UnpinMessagePayload } from './server/UnpinMessagePayload';
+import { GetPinnedMessagesPayload } from './server/GetPinnedMessagesPayload';
+import { PinnedMessagesResponse } from './server/PinnedMessagesResponse';
+import { VotePollPayload } from './server/VotePollPayload';
+import { ClosePollPayload } from './server/ClosePollPayload';
+import { OpenDirectMessagePayload } from './server/OpenDirectMessagePayload';
+import { DirectConversation } from './server/DirectConversation';
+import { DirectConversationsResponse } from './server/DirectConversationsResponse';
+import { UploadMediaPayload } from './server/UploadMediaPayload';
+import { MediaUploadedResponse } from './server/MediaUploadedResponse';
+import { GetMediaPayload } from './server/GetMediaPayload';
+import { MediaBlobResponse } from './server/MediaBlobResponse';
+import { CreateWebhookPayload } from './server/CreateWebhookPayload';
+import { DeleteWebhookPayload } from './server/DeleteWebhookPayload';
+import { ListWebhooksPayload } from './server/ListWebhooksPayload';
+import { WebhookListResponse } from './server/WebhookListResponse';
+import { WebhookInfo } from './server/WebhookInfo';
+import { GetWebhookDeliveriesPayload } from './server/GetWebhookDeliveriesPayload';
+import { WebhookDeliveriesResponse } from './server/WebhookDeliveriesResponse';
+import { RedeliverWebhookPayload } from './server/RedeliverWebhookPayload';
+import { CreateIncomingWebhookPayload } from './server/CreateIncomingWebhookPayload';
+import { IncomingWebhookInfo } from './server/IncomingWebhookInfo';
+import { IncomingWebhookListResponse } from './server/IncomingWebhookListResponse';
+import { SetFiltersPayload } from './server/SetFiltersPayload';
+import { ModerationRoomPayload } from './server/ModerationRoomPayload';
+import { FiltersResponse } from './server/FiltersResponse';
+import { FlaggedListResponse } from './server/FlaggedListResponse';
+import { FlaggedMessage } from './server/FlaggedMessage';
+import { ReportMessagePayload } from './server/ReportMessagePayload';
+import { ReportReceipt } from './server/ReportReceipt';
+import { GetReportsPayload } from './server/GetReportsPayload';
+import { Report } from './server/Report';
+import { ReportListResponse } from './server/ReportListResponse';
+import { ResolveReportPayload } from './server/ResolveReportPayload';
+import { ModerationLogResponse } from './server/ModerationLogResponse';
+import { ModerationWarning } from './server/ModerationWarning';
+import { RoomBannedNotice } from './server/RoomBannedNotice';
+import { AuditQuery } from './server/AuditQuery';
+import { AuditLogResponse } from './server/AuditLogResponse';
+import { CreateBotPayload } from './server/CreateBotPayload';
+import { DeleteBotPayload } from './server/DeleteBotPayload';
+import { BotInfo } from './server/BotInfo';
+import { BotListResponse } from './server/BotListResponse';
+import { RegisterCommandsPayload } from './server/RegisterCommandsPayload';
+import { CommandRespondPayload } from './server/CommandRespondPayload';
+import { CommandResponse } from './server/CommandResponse';
+import { CommandInvocation } from './server/CommandInvocation';
+import { SetSlowModePayload } from './server/SetSlowModePayload';
+import { SlowModeResponse } from './server/SlowModeResponse';
+import { RateLimitError } from './server/RateLimitError';
 
 export interface ServerToClientEvents {
     'room.event': (event: RoomEvent) => void;
+    'session.info': (info: SessionInfo) => void;
     'room.list': (response: RoomListResponse) => void;
     'username.set': (username: string) => void;
+    'user.renamed': (event: UserRenamedEvent) => void;
+    'user.profile': (response: ProfileResponse) => void;
+    'user.avatar': (response: AvatarResponse) => void;
+    'user.blocked_list': (response: BlockedUsersResponse) => void;
     'room.members': (response: RoomMembersResponse) => void;
     'typing.start': (indicator: TypingIndicator) => void;
     'typing.stop': (indicator: TypingIndicator) => void;
     'starred_messages.list': (response: StarredMessagesResponse) => void;
+    'room.policy': (response: RoomPolicyResponse) => void;
+    'room.slow_mode': (response: SlowModeResponse) => void;
+    'scheduled_messages.list': (response: ScheduledMessagesResponse) => void;
+    'pinned_messages.list': (response: PinnedMessagesResponse) => void;
+    'dm.opened': (conversation: DirectConversation) => void;
+    'dm.list': (response: DirectConversationsResponse) => void;
+    'media.uploaded': (response: MediaUploadedResponse) => void;
+    'media.blob': (response: MediaBlobResponse) => void;
+    'webhook.created': (webhook: WebhookInfo) => void;
+    'webhook.list': (response: WebhookListResponse) => void;
+    'webhook.deliveries': (response: WebhookDeliveriesResponse) => void;
+    'incoming_webhook.created': (webhook: IncomingWebhookInfo) => void;
+    'incoming_webhook.list': (response: IncomingWebhookListResponse) => void;
+    'moderation.filters': (response: FiltersResponse) => void;
+    'moderation.flagged_list': (response: FlaggedListResponse) => void;
+    'moderation.flagged': (message: FlaggedMessage) => void;
+    'message.reported': (receipt: ReportReceipt) => void;
+    'moderation.report': (report: Report) => void;
+    'moderation.reports': (response: ReportListResponse) => void;
+    'moderation.log': (response: ModerationLogResponse) => void;
+    'moderation.warning': (warning: ModerationWarning) => void;
+    'room.banned': (notice: RoomBannedNotice) => void;
+    'audit.log': (response: AuditLogResponse) => void;
+    'bot.created': (bot: BotInfo) => void;
+    'bot.list': (response: BotListResponse) => void;
+    'bot.commands': (response: RegisterCommandsPayload) => void;
+    'command.invoked': (invocation: CommandInvocation) => void;
+    'command.response': (response: CommandResponse) => void;
+    mention: (notification: MentionNotification) => void;
+    'mentions.counts': (response: MentionCountsResponse) => void;
+    rate_limited: (error: RateLimitError) => void;
+    error: (error: { message: string }) => void;
 }
 
 export interface ClientToServerEvents {
@@ -32,7 +140,16 @@ export interface ClientToServerEvents {
     'room.leave': (payload: LeaveRoomPayload) => void;
     'room.list': () => void;
     'room.create': (payload: CreateRoomPayload) => void;
+    'room.set_policy': (payload: SetRoomPolicyPayload) => void;
+    'room.get_policy': (payload: GetRoomPolicyPayload) => void;
+    'room.set_slow_mode': (payload: SetSlowModePayload) => void;
     'user.set_username': (payload: SetUsernamePayload) => void;
+    'user.set_profile': (payload: SetProfilePayload) => void;
+    'user.get_profile': (payload: GetProfilePayload) => void;
+    'user.get_avatar': (payload: GetProfilePayload) => void;
+    'user.block': (payload: BlockUserPayload) => void;
+    'user.unblock': (payload: UnblockUserPayload) => void;
+    'user.get_blocked': () => void;
     'room.get_members': (payload: GetMembersPayload) => void;
     'typing.start': (payload: StartTypingPayload) => void;
     'typing.stop': (payload: StopTypingPayload) => void;
@@ -40,5 +157,41 @@ export interface ClientToServerEvents {
     'message.delete': (payload: DeleteMessagePayload) => void;
     'message.star': (payload: StarMessageRequest) => void;
     'message.unstar': (payload: UnstarMessageRequest) => void;
-    'starred_messages.get': (room_id: string) => void;
+    'starred_messages.get': (payload: GetStarredMessagesRequest) => void;
+    'message.pin': (payload: PinMessagePayload) => void;
+    'message.unpin': (payload: UnpinMessagePayload) => void;
+    'pinned_messages.get': (payload: GetPinnedMessagesPayload) => void;
+    'poll.vote': (payload: VotePollPayload) => void;
+    'poll.close': (payload: ClosePollPayload) => void;
+    'dm.open': (payload: OpenDirectMessagePayload) => void;
+    'dm.list': () => void;
+    'media.upload': (payload: UploadMediaPayload) => void;
+    'media.get': (payload: GetMediaPayload) => void;
+    'webhook.create': (payload: CreateWebhookPayload) => void;
+    'webhook.delete': (payload: DeleteWebhookPayload) => void;
+    'webhook.list': (payload: ListWebhooksPayload) => void;
+    'webhook.deliveries': (payload: GetWebhookDeliveriesPayload) => void;
+    'webhook.redeliver': (payload: RedeliverWebhookPayload) => void;
+    'incoming_webhook.create': (payload: CreateIncomingWebhookPayload) => void;
+    'incoming_webhook.delete': (payload: DeleteWebhookPayload) => void;
+    'incoming_webhook.list': (payload: ListWebhooksPayload) => void;
+    'moderation.set_filters': (payload: SetFiltersPayload) => void;
+    'moderation.get_filters': (payload: ModerationRoomPayload) => void;
+    'moderation.get_flagged': (payload: ModerationRoomPayload) => void;
+    'message.report': (payload: ReportMessagePayload) => void;
+    'moderation.get_reports': (payload: GetReportsPayload) => void;
+    'moderation.resolve_report': (payload: ResolveReportPayload) => void;
+    'moderation.get_log': (payload: ModerationRoomPayload) => void;
+    'audit.query': (payload: AuditQuery) => void;
+    'bot.create': (payload: CreateBotPayload) => void;
+    'bot.list': () => void;
+    'bot.delete': (payload: DeleteBotPayload) => void;
+    'bot.register_commands': (payload: RegisterCommandsPayload) => void;
+    'command.respond': (payload: CommandRespondPayload) => void;
+    'mentions.get': () => void;
+    'mentions.clear': (payload: ClearMentionsPayload) => void;
+    'message.schedule': (payload: ScheduleMessagePayload) => void;
+    'scheduled_messages.edit': (payload: EditScheduledMessagePayload) => void;
+    'scheduled_messages.cancel': (payload: CancelScheduledMessagePayload) => void;
+    'scheduled_messages.get': () => void;
 }
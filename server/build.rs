@@ -1,4 +1,5 @@
 fn main() {
     println!("cargo:rerun-if-changed=src/");
     println!("cargo:rustc-env=TS_RS_EXPORT_DIR=../mobile/types/server");
+    println!("cargo:rustc-env=SCHEMA_EXPORT_DIR=../protocol");
 }
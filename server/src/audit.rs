@@ -0,0 +1,233 @@
+//! Append-only record of administrative actions.
+//!
+//! The audit log is kept apart from room history: entries cannot be edited or
+//! removed, they outlive the rooms they mention, and only admins can read
+//! them. Only the newest [`MAX_ENTRIES`] are kept. Admins are listed by user key in `ADMIN_USER_KEYS`, separated by
+//! commas.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{PoisonError, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::socket::Sid;
+use tracing::{info, warn};
+use ts_rs::TS;
+use uuid::Uuid;
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1_000;
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum AuditAction {
+    RoomCreated,
+    UsernameChanged,
+    UserBanned,
+    /// A user gained or lost the moderator role in a room.
+    RoleChanged,
+    /// A moderator deleted someone else's message.
+    MessageDeleted,
+    RoomPolicyChanged,
+    TopicChanged,
+    SlowModeChanged,
+    /// A moderator replaced a room's message filters.
+    FiltersChanged,
+    /// An outgoing webhook was added to or removed from a room.
+    WebhookCreated,
+    WebhookDeleted,
+    /// An incoming webhook was added to or removed from a room.
+    IncomingWebhookCreated,
+    IncomingWebhookDeleted,
+    BotCreated,
+    BotDeleted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub actor: Sid,
+    pub actor_username: Option<String>,
+    #[serde(default)]
+    pub room_id: Option<Uuid>,
+    /// The id of the user, message or room the action was applied to.
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub before: Option<String>,
+    #[serde(default)]
+    pub after: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(action: AuditAction, actor: Sid, actor_username: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            action,
+            actor,
+            actor_username,
+            room_id: None,
+            target: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn in_room(self, room_id: Uuid) -> Self {
+        Self {
+            room_id: Some(room_id),
+            ..self
+        }
+    }
+
+    pub fn target(self, target: impl ToString) -> Self {
+        Self {
+            target: Some(target.to_string()),
+            ..self
+        }
+    }
+
+    pub fn change(self, before: Option<String>, after: Option<String>) -> Self {
+        Self {
+            before,
+            after,
+            ..self
+        }
+    }
+}
+
+/// Narrows down an audit log query. Every field is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS, JsonSchema)]
+#[ts(export)]
+pub struct AuditQuery {
+    #[serde(default)]
+    pub room_id: Option<Uuid>,
+    #[serde(default)]
+    pub action: Option<AuditAction>,
+    /// Only entries recorded at or after this time.
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries recorded before this time, for paging back.
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// At most this many entries, 100 unless given and never more than 1000.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+pub struct AuditLog {
+    admins: HashSet<Uuid>,
+    /// Oldest first. Entries are only ever appended, and dropped once there
+    /// are more than [`MAX_ENTRIES`].
+    entries: RwLock<VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn new(admins: impl IntoIterator<Item = Uuid>) -> Self {
+        Self {
+            admins: admins.into_iter().collect(),
+            entries: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let keys = std::env::var("ADMIN_USER_KEYS").unwrap_or_default();
+
+        Self::new(
+            keys.split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .filter_map(|key| match Uuid::parse_str(key) {
+                    Ok(key) => Some(key),
+                    Err(_) => {
+                        warn!("ignoring invalid admin user key {:?}", key);
+                        None
+                    }
+                }),
+        )
+    }
+
+    pub fn is_admin(&self, user_key: Uuid) -> bool {
+        self.admins.contains(&user_key)
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        info!(
+            "audit: {:?} by {} in {:?} on {:?}",
+            entry.action, entry.actor, entry.room_id, entry.target
+        );
+
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        entries.push_back(entry);
+        if entries.len() > MAX_ENTRIES {
+            entries.pop_front();
+        }
+    }
+
+    /// Matching entries, newest first.
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .min(MAX_QUERY_LIMIT);
+
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .rev()
+            .filter(|entry| {
+                query
+                    .room_id
+                    .is_none_or(|room_id| entry.room_id == Some(room_id))
+            })
+            .filter(|entry| query.action.is_none_or(|action| entry.action == action))
+            .filter(|entry| query.since.is_none_or(|since| entry.timestamp >= since))
+            .filter(|entry| query.until.is_none_or(|until| entry.timestamp < until))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_the_oldest_entries_past_the_cap() {
+        let log = AuditLog::new([]);
+        let first = AuditEntry::new(AuditAction::RoomCreated, Sid::new(), None);
+        let first_id = first.id;
+        log.record(first);
+        for _ in 0..MAX_ENTRIES {
+            log.record(AuditEntry::new(AuditAction::TopicChanged, Sid::new(), None));
+        }
+
+        assert_eq!(log.entries.read().unwrap().len(), MAX_ENTRIES);
+        assert!(
+            log.entries
+                .read()
+                .unwrap()
+                .iter()
+                .all(|entry| entry.id != first_id)
+        );
+        assert!(
+            log.query(&AuditQuery {
+                action: Some(AuditAction::RoomCreated),
+                ..AuditQuery::default()
+            })
+            .is_empty()
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Bot accounts, which connect like any other client but authenticate with a
+//! token and can register slash commands.
+//!
+//! A bot's socket is its identity while it is connected, just as for users.
+//! When someone in a room the bot has joined runs one of its commands, the
+//! bot receives a `command.invoked` event and answers with `command.respond`.
+
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::socket::Sid;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::socket::validation;
+
+const MAX_BOTS_PER_OWNER: usize = 10;
+const MAX_NAME_CHARS: usize = 32;
+const MAX_COMMANDS: usize = 25;
+const MAX_COMMAND_CHARS: usize = 32;
+const MAX_DESCRIPTION_CHARS: usize = 100;
+/// How long a bot may keep answering an invocation.
+const INVOCATION_TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+pub struct Bot {
+    pub id: Uuid,
+    pub name: String,
+    pub token: String,
+    /// User key of the user who created the bot.
+    pub owner: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A bot as shown to its owner. The token is only returned when the bot is
+/// created.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct BotInfo {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Pass as `bot_token` in the connect handshake to connect as the bot.
+    #[serde(default)]
+    pub token: Option<String>,
+    pub connected: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct BotCommand {
+    /// Invoked as `/name`.
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// A command that is waiting for its bot to answer.
+#[derive(Debug, Clone)]
+pub struct PendingInvocation {
+    pub room_id: Uuid,
+    pub invoker: Sid,
+    pub bot: Sid,
+    pub command: String,
+    pub started_at: Instant,
+}
+
+#[derive(Debug)]
+pub enum BotError {
+    InvalidName(&'static str),
+    InvalidCommand(String),
+    TooMany,
+    NotFound,
+    NotABot,
+}
+
+impl std::fmt::Display for BotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BotError::InvalidName(reason) => write!(f, "Invalid bot name: {reason}"),
+            BotError::InvalidCommand(reason) => write!(f, "Invalid command: {reason}"),
+            BotError::TooMany => write!(f, "You can have at most {MAX_BOTS_PER_OWNER} bots"),
+            BotError::NotFound => write!(f, "Bot does not exist"),
+            BotError::NotABot => write!(f, "Only bots can do that"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BotRegistry {
+    bots: DashMap<Uuid, Bot>,
+    /// Commands registered by each connected bot socket.
+    sessions: DashMap<Sid, (Uuid, Vec<BotCommand>)>,
+    invocations: DashMap<Uuid, PendingInvocation>,
+}
+
+impl BotRegistry {
+    pub fn create(&self, owner: Uuid, name: &str) -> Result<Bot, BotError> {
+        let name = validation::clean_name(name);
+        if name.is_empty() {
+            return Err(BotError::InvalidName("name is empty"));
+        }
+        if name.chars().count() > MAX_NAME_CHARS {
+            return Err(BotError::InvalidName("name is too long"));
+        }
+
+        if self.owned_by(owner).len() >= MAX_BOTS_PER_OWNER {
+            return Err(BotError::TooMany);
+        }
+
+        let bot = Bot {
+            id: Uuid::new_v4(),
+            name,
+            token: format!("bot_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+            owner,
+            created_at: Utc::now(),
+        };
+        self.bots.insert(bot.id, bot.clone());
+
+        Ok(bot)
+    }
+
+    /// Deletes a bot and returns the sockets it is still connected on.
+    pub fn delete(&self, owner: Uuid, bot_id: Uuid) -> Result<Vec<Sid>, BotError> {
+        self.bots
+            .remove_if(&bot_id, |_, bot| bot.owner == owner)
+            .ok_or(BotError::NotFound)?;
+
+        let sockets: Vec<Sid> = self
+            .sessions
+            .iter()
+            .filter(|session| session.0 == bot_id)
+            .map(|session| *session.key())
+            .collect();
+        for sid in &sockets {
+            self.sessions.remove(sid);
+        }
+
+        Ok(sockets)
+    }
+
+    pub fn owned_by(&self, owner: Uuid) -> Vec<Bot> {
+        let mut bots: Vec<Bot> = self
+            .bots
+            .iter()
+            .filter(|bot| bot.owner == owner)
+            .map(|bot| bot.clone())
+            .collect();
+        bots.sort_by_key(|bot| bot.created_at);
+        bots
+    }
+
+    pub fn info(&self, bot: &Bot) -> BotInfo {
+        BotInfo {
+            id: bot.id,
+            name: bot.name.clone(),
+            created_at: bot.created_at,
+            token: None,
+            connected: self.sessions.iter().any(|session| session.0 == bot.id),
+        }
+    }
+
+    pub fn by_token(&self, token: &str) -> Option<Bot> {
+        self.bots
+            .iter()
+            .find(|bot| bot.token == token)
+            .map(|bot| bot.clone())
+    }
+
+    pub fn connect(&self, sid: Sid, bot_id: Uuid) {
+        self.sessions.insert(sid, (bot_id, Vec::new()));
+    }
+
+    pub fn disconnect(&self, sid: Sid) {
+        self.sessions.remove(&sid);
+    }
+
+    pub fn is_bot(&self, sid: Sid) -> bool {
+        self.sessions.contains_key(&sid)
+    }
+
+    /// Replaces the commands a connected bot handles.
+    pub fn register_commands(
+        &self,
+        sid: Sid,
+        commands: Vec<BotCommand>,
+    ) -> Result<Vec<BotCommand>, BotError> {
+        let mut session = self.sessions.get_mut(&sid).ok_or(BotError::NotABot)?;
+
+        if commands.len() > MAX_COMMANDS {
+            return Err(BotError::InvalidCommand(format!(
+                "a bot can register at most {MAX_COMMANDS} commands"
+            )));
+        }
+
+        let mut commands = commands;
+        for command in &mut commands {
+            command.name = command.name.trim_start_matches('/').to_lowercase();
+            if command.name.is_empty()
+                || command.name.chars().count() > MAX_COMMAND_CHARS
+                || !command
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(BotError::InvalidCommand(format!(
+                    "/{} must be 1 to {MAX_COMMAND_CHARS} letters, digits, '-' or '_'",
+                    command.name
+                )));
+            }
+            if command.description.chars().count() > MAX_DESCRIPTION_CHARS {
+                return Err(BotError::InvalidCommand(format!(
+                    "description of /{} is too long",
+                    command.name
+                )));
+            }
+        }
+
+        session.1 = commands.clone();
+        Ok(commands)
+    }
+
+    /// Commands offered by the connected bots among `members`.
+    pub fn commands_for<'a>(
+        &self,
+        members: impl IntoIterator<Item = &'a Sid>,
+    ) -> Vec<(Sid, BotCommand)> {
+        members
+            .into_iter()
+            .filter_map(|sid| self.sessions.get(sid))
+            .flat_map(|session| {
+                let sid = *session.key();
+                session
+                    .1
+                    .iter()
+                    .map(|command| (sid, command.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn start_invocation(&self, invocation: PendingInvocation) -> Uuid {
+        self.invocations
+            .retain(|_, pending| pending.started_at.elapsed() < INVOCATION_TTL);
+
+        let id = Uuid::new_v4();
+        self.invocations.insert(id, invocation);
+        id
+    }
+
+    /// Looks up an invocation the given bot is allowed to answer.
+    pub fn invocation(&self, id: Uuid, bot: Sid) -> Option<PendingInvocation> {
+        self.invocations
+            .get(&id)
+            .filter(|pending| pending.bot == bot && pending.started_at.elapsed() < INVOCATION_TTL)
+            .map(|pending| pending.clone())
+    }
+}
@@ -0,0 +1,690 @@
+//! Server-side handling of formatted message bodies.
+//!
+//! Only a small markdown subset is accepted: bold, italics, inline code, code
+//! blocks, links and quotes. Everything else is kept as literal text. The
+//! sanitized markdown is re-rendered from the parsed tree, so anything a client
+//! renders from it is limited to that subset.
+
+use std::fmt;
+
+use tracing::warn;
+
+use crate::models::{FormattedBody, MessageFormat};
+
+const MAX_BODY_CHARS: usize = 8_000;
+const MAX_DEPTH: usize = 8;
+const MAX_LANGUAGE_CHARS: usize = 20;
+const ALLOWED_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    TooLong,
+    Empty,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::TooLong => write!(f, "formatted body exceeds {MAX_BODY_CHARS} characters"),
+            FormatError::Empty => write!(f, "formatted body has no text"),
+        }
+    }
+}
+
+/// A formatted body after sanitization, with its plain text fallback.
+#[derive(Debug, Clone)]
+pub struct Sanitized {
+    pub body: FormattedBody,
+    pub plain: String,
+}
+
+/// Returns the plain text and formatted body to store for a message. When a
+/// formatted body is present its plain rendering replaces `content`; an
+/// invalid one is dropped and the message falls back to `content`.
+pub fn apply(
+    content: String,
+    formatted: Option<&FormattedBody>,
+) -> (String, Option<FormattedBody>) {
+    let Some(formatted) = formatted else {
+        return (content, None);
+    };
+
+    match sanitize(formatted) {
+        Ok(sanitized) => (sanitized.plain, Some(sanitized.body)),
+        Err(e) => {
+            warn!("Dropping invalid formatted body: {}", e);
+            (content, None)
+        }
+    }
+}
+
+pub fn sanitize(body: &FormattedBody) -> Result<Sanitized, FormatError> {
+    match body.format {
+        MessageFormat::Markdown => sanitize_markdown(&body.body),
+    }
+}
+
+fn sanitize_markdown(input: &str) -> Result<Sanitized, FormatError> {
+    if input.chars().count() > MAX_BODY_CHARS {
+        return Err(FormatError::TooLong);
+    }
+
+    let normalized = input.replace("\r\n", "\n");
+    let lines: Vec<&str> = normalized.lines().collect();
+    let blocks = parse_blocks(&lines, 0);
+
+    let markdown = render_blocks_markdown(&blocks);
+    let plain = render_blocks_plain(&blocks);
+
+    if plain.trim().is_empty() {
+        return Err(FormatError::Empty);
+    }
+
+    Ok(Sanitized {
+        body: FormattedBody {
+            format: MessageFormat::Markdown,
+            body: markdown,
+        },
+        plain,
+    })
+}
+
+#[derive(Debug)]
+enum Block {
+    Paragraph(Vec<Vec<Inline>>),
+    Code {
+        language: String,
+        lines: Vec<String>,
+    },
+    Quote(Vec<Block>),
+}
+
+#[derive(Debug)]
+enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Code(String),
+    Link { text: Vec<Inline>, url: String },
+}
+
+fn parse_blocks(lines: &[&str], depth: usize) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+
+        if trimmed.is_empty() {
+            i += 1;
+            continue;
+        }
+
+        if let Some(info) = trimmed.strip_prefix("```") {
+            let language: String = info
+                .trim()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+                .take(MAX_LANGUAGE_CHARS)
+                .collect();
+
+            let mut code_lines = Vec::new();
+            i += 1;
+            while i < lines.len() && !lines[i].trim_start().starts_with("```") {
+                code_lines.push(lines[i].to_string());
+                i += 1;
+            }
+            // Skip the closing fence; an unterminated block runs to the end.
+            i += 1;
+
+            blocks.push(Block::Code {
+                language,
+                lines: code_lines,
+            });
+            continue;
+        }
+
+        if trimmed.starts_with('>') && depth < MAX_DEPTH {
+            let mut quoted = Vec::new();
+            while i < lines.len() {
+                let Some(rest) = lines[i].trim_start().strip_prefix('>') else {
+                    break;
+                };
+                quoted.push(rest.strip_prefix(' ').unwrap_or(rest));
+                i += 1;
+            }
+            blocks.push(Block::Quote(parse_blocks(&quoted, depth + 1)));
+            continue;
+        }
+
+        let mut paragraph = Vec::new();
+        while i < lines.len() {
+            let current = lines[i].trim_start();
+            if current.is_empty()
+                || current.starts_with("```")
+                || (current.starts_with('>') && depth < MAX_DEPTH)
+            {
+                break;
+            }
+            paragraph.push(parse_inline(current.trim_end(), 0));
+            i += 1;
+        }
+        blocks.push(Block::Paragraph(paragraph));
+    }
+
+    blocks
+}
+
+fn parse_inline(text: &str, depth: usize) -> Vec<Inline> {
+    let mut nodes = Vec::new();
+    let mut literal = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        // Escapes and code spans cannot nest, so they still apply past the
+        // depth limit.
+        let parsed = match c {
+            '\\' => parse_escape(rest),
+            '`' => parse_code(rest),
+            _ if depth >= MAX_DEPTH => None,
+            _ => match c {
+                '*' if rest.starts_with("**") => parse_delimited(rest, "**", depth)
+                    .map(|(inner, len)| (Inline::Bold(inner), len)),
+                '*' | '_' => {
+                    let opens_word = literal
+                        .chars()
+                        .next_back()
+                        .is_none_or(|prev| !prev.is_alphanumeric());
+                    if opens_word {
+                        let delimiter = if c == '*' { "*" } else { "_" };
+                        parse_delimited(rest, delimiter, depth)
+                            .map(|(inner, len)| (Inline::Italic(inner), len))
+                    } else {
+                        None
+                    }
+                }
+                '[' => parse_link(rest, depth),
+                '!' if rest[1..].starts_with('[') => {
+                    parse_link(&rest[1..], depth).map(|(link, len)| (image_alt(link), len + 1))
+                }
+                _ => None,
+            },
+        };
+
+        match parsed {
+            // Escapes and flattened links stay part of the surrounding text,
+            // so escaping on output sees their neighbours.
+            Some((Inline::Text(text), len)) => {
+                literal.push_str(&text);
+                rest = &rest[len..];
+            }
+            Some((node, len)) => {
+                if !literal.is_empty() {
+                    nodes.push(Inline::Text(std::mem::take(&mut literal)));
+                }
+                nodes.push(node);
+                rest = &rest[len..];
+            }
+            None => {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if !literal.is_empty() {
+        nodes.push(Inline::Text(literal));
+    }
+
+    nodes
+}
+
+fn parse_escape(rest: &str) -> Option<(Inline, usize)> {
+    let escaped = rest[1..].chars().next()?;
+    escaped
+        .is_ascii_punctuation()
+        .then(|| (Inline::Text(escaped.to_string()), 1 + escaped.len_utf8()))
+}
+
+fn parse_code(rest: &str) -> Option<(Inline, usize)> {
+    let ticks = rest.chars().take_while(|c| *c == '`').count();
+    let fence = &rest[..ticks];
+    let end = rest[ticks..].find(fence)?;
+    let code = &rest[ticks..ticks + end];
+    if code.is_empty() {
+        return None;
+    }
+
+    let code = if code.starts_with(' ') && code.ends_with(' ') && code.len() > 1 {
+        &code[1..code.len() - 1]
+    } else {
+        code
+    };
+
+    Some((Inline::Code(code.to_string()), ticks * 2 + end))
+}
+
+fn parse_delimited(rest: &str, delimiter: &str, depth: usize) -> Option<(Vec<Inline>, usize)> {
+    let start = delimiter.len();
+    let body = &rest[start..];
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut search_from = 0;
+    loop {
+        let offset = body[search_from..].find(delimiter)? + search_from;
+        let inner = &body[..offset];
+        if is_escaped(inner) {
+            search_from = offset + delimiter.len();
+            continue;
+        }
+        // A single `*` must not close on half of a `**`.
+        let doubled = delimiter.len() == 1 && body[offset + 1..].starts_with(delimiter);
+        if !inner.is_empty() && !inner.ends_with(char::is_whitespace) && !doubled {
+            return Some((
+                parse_inline(inner, depth + 1),
+                start + offset + delimiter.len(),
+            ));
+        }
+        search_from = offset + delimiter.len() + usize::from(doubled);
+        if search_from >= body.len() {
+            return None;
+        }
+    }
+}
+
+/// Whether the character after `text` is escaped by a backslash.
+fn is_escaped(text: &str) -> bool {
+    text.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+fn parse_link(rest: &str, depth: usize) -> Option<(Inline, usize)> {
+    let close_text = rest.find("](")?;
+    let text = &rest[1..close_text];
+    let url_start = close_text + 2;
+    let close_url = closing_paren(&rest[url_start..])? + url_start;
+    let url = rest[url_start..close_url].trim();
+
+    if text.is_empty() || text.contains('[') || url.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let text = parse_inline(text, depth + 1);
+    let len = close_url + 1;
+
+    if is_allowed_url(url) {
+        Some((
+            Inline::Link {
+                text,
+                url: url.to_string(),
+            },
+            len,
+        ))
+    } else {
+        // Drop the target of links with unsafe schemes, keeping their text.
+        Some((flatten(text), len))
+    }
+}
+
+/// Finds the `)` ending a link target, allowing balanced parentheses inside it.
+fn closing_paren(target: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in target.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn image_alt(link: Inline) -> Inline {
+    match link {
+        Inline::Link { text, .. } => flatten(text),
+        other => other,
+    }
+}
+
+/// Collapses inline nodes into a single text node with their plain rendering.
+fn flatten(nodes: Vec<Inline>) -> Inline {
+    let mut plain = String::new();
+    render_inline_plain(&nodes, &mut plain);
+    Inline::Text(plain)
+}
+
+fn is_allowed_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    ALLOWED_SCHEMES
+        .iter()
+        .any(|scheme| lower.starts_with(scheme) && lower.len() > scheme.len())
+}
+
+fn render_blocks_markdown(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(|block| match block {
+            Block::Paragraph(lines) => lines
+                .iter()
+                .map(|line| {
+                    let mut out = String::new();
+                    render_inline_markdown(line, &mut out);
+                    out
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Block::Code { language, lines } => {
+                let fence = "`".repeat(longest_backtick_run(lines).max(2) + 1);
+                let mut out = format!("{fence}{language}\n");
+                for line in lines {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                out.push_str(&fence);
+                out
+            }
+            Block::Quote(inner) => render_blocks_markdown(inner)
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {line}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn render_inline_markdown(nodes: &[Inline], out: &mut String) {
+    for node in nodes {
+        match node {
+            Inline::Text(text) => escape_markdown(text, out),
+            Inline::Bold(inner) => {
+                out.push_str("**");
+                render_inline_markdown(inner, out);
+                out.push_str("**");
+            }
+            Inline::Italic(inner) => {
+                out.push('_');
+                render_inline_markdown(inner, out);
+                out.push('_');
+            }
+            Inline::Code(code) => {
+                let fence = "`".repeat(longest_backtick_run(std::slice::from_ref(code)) + 1);
+                let pad = if code.starts_with('`') || code.ends_with('`') {
+                    " "
+                } else {
+                    ""
+                };
+                out.push_str(&format!("{fence}{pad}{code}{pad}{fence}"));
+            }
+            Inline::Link { text, url } => {
+                out.push('[');
+                render_inline_markdown(text, out);
+                out.push_str("](");
+                out.push_str(&url.replace('(', "%28").replace(')', "%29"));
+                out.push(')');
+            }
+        }
+    }
+}
+
+fn escape_markdown(text: &str, out: &mut String) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let at_line_start = out.is_empty() || out.ends_with('\n');
+        let needs_escape = match c {
+            '\\' | '*' | '_' | '`' | '[' | ']' | '<' => true,
+            '#' | '>' | '-' | '+' => at_line_start,
+            '!' => chars.peek() == Some(&'['),
+            _ => false,
+        };
+        if needs_escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+fn longest_backtick_run(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .flat_map(|line| line.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0)
+}
+
+fn render_blocks_plain(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(|block| match block {
+            Block::Paragraph(lines) => lines
+                .iter()
+                .map(|line| {
+                    let mut out = String::new();
+                    render_inline_plain(line, &mut out);
+                    out
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Block::Code { lines, .. } => lines.join("\n"),
+            Block::Quote(inner) => render_blocks_plain(inner),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn render_inline_plain(nodes: &[Inline], out: &mut String) {
+    for node in nodes {
+        match node {
+            Inline::Text(text) | Inline::Code(text) => out.push_str(text),
+            Inline::Bold(inner) | Inline::Italic(inner) => render_inline_plain(inner, out),
+            Inline::Link { text, url } => {
+                let start = out.len();
+                render_inline_plain(text, out);
+                if out[start..] != *url {
+                    out.push_str(&format!(" ({url})"));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(body: &str) -> Result<Sanitized, FormatError> {
+        sanitize(&FormattedBody {
+            format: MessageFormat::Markdown,
+            body: body.to_string(),
+        })
+    }
+
+    fn rendered(body: &str) -> (String, String) {
+        let sanitized = markdown(body).unwrap();
+        (sanitized.body.body, sanitized.plain)
+    }
+
+    #[test]
+    fn keeps_supported_formatting() {
+        assert_eq!(
+            rendered("**bold** *it* `code`"),
+            ("**bold** _it_ `code`".into(), "bold it code".into())
+        );
+        assert_eq!(
+            rendered("```rust\nfn main() {}\n```"),
+            ("```rust\nfn main() {}\n```".into(), "fn main() {}".into())
+        );
+    }
+
+    #[test]
+    fn escapes_markup_in_text() {
+        assert_eq!(
+            rendered("<script>alert(1)</script>"),
+            (
+                r"\<script>alert(1)\</script>".into(),
+                "<script>alert(1)</script>".into()
+            )
+        );
+        assert_eq!(
+            rendered(r"\*not italic\* a\\b"),
+            (r"\*not italic\* a\\b".into(), r"*not italic* a\b".into())
+        );
+        assert_eq!(
+            rendered(r"_a \_b_ c").1,
+            "a _b c",
+            "an escaped delimiter must not close"
+        );
+        assert_eq!(
+            rendered("snake_case_name and 2*3*4").0,
+            r"snake\_case\_name and 2\*3\*4"
+        );
+    }
+
+    #[test]
+    fn escapes_unsupported_block_markers() {
+        assert_eq!(
+            rendered("# heading\n- item\n+ plus").0,
+            "\\# heading\n\\- item\n\\+ plus"
+        );
+    }
+
+    #[test]
+    fn code_fences_outgrow_their_content() {
+        assert_eq!(
+            rendered("```\ncode with ``` inside\n```").0,
+            "````\ncode with ``` inside\n````"
+        );
+        assert_eq!(rendered("``a`b``").0, "``a`b``");
+        assert_eq!(
+            rendered("```rust<script>\nx\n```").0,
+            "```rustscript\nx\n```"
+        );
+    }
+
+    #[test]
+    fn nests_inline_formatting() {
+        assert_eq!(
+            rendered("**bold _and italic_**"),
+            ("**bold _and italic_**".into(), "bold and italic".into())
+        );
+        assert_eq!(rendered("*a **b** c*").0, "_a **b** c_");
+        assert_eq!(
+            rendered("**[link](https://x.test)**").0,
+            "**[link](https://x.test)**"
+        );
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let quotes = format!("{} x", ">".repeat(30));
+        let (body, plain) = rendered(&quotes);
+        assert!(body.starts_with(&"> ".repeat(MAX_DEPTH)));
+        assert_eq!(plain, format!("{} x", ">".repeat(30 - MAX_DEPTH)));
+
+        let stars = format!("{}x{}", "*".repeat(40), "*".repeat(40));
+        assert!(markdown(&stars).unwrap().plain.contains('x'));
+    }
+
+    #[test]
+    fn keeps_links_with_allowed_schemes() {
+        assert_eq!(
+            rendered("[site](https://example.com)"),
+            (
+                "[site](https://example.com)".into(),
+                "site (https://example.com)".into()
+            )
+        );
+        assert_eq!(
+            rendered("[https://example.com](https://example.com)").1,
+            "https://example.com"
+        );
+        assert_eq!(
+            rendered("[mail](mailto:a@example.com)").0,
+            "[mail](mailto:a@example.com)"
+        );
+        assert_eq!(
+            rendered("[w](https://en.wikipedia.org/wiki/Foo_(bar))").0,
+            "[w](https://en.wikipedia.org/wiki/Foo_%28bar%29)"
+        );
+    }
+
+    #[test]
+    fn drops_links_with_other_schemes() {
+        for link in [
+            "[click](javascript:alert(1))",
+            "[click](JaVaScRiPt:alert(1))",
+            "[click](data:text/html;base64,AAAA)",
+            "[click](vbscript:msgbox)",
+            "[click](<https://x.test>)",
+            "[click](https:)",
+            "[click](/relative)",
+        ] {
+            assert_eq!(rendered(link), ("click".into(), "click".into()), "{link}");
+        }
+    }
+
+    #[test]
+    fn images_become_their_alt_text() {
+        assert_eq!(
+            rendered("![alt](https://x.test/y.png)"),
+            ("alt".into(), "alt".into())
+        );
+        assert_eq!(
+            rendered("![[x](https://a.test)](https://b.test)").0,
+            r"\!\[[x](https://a.test)\](https://b.test)"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_bodies() {
+        assert_eq!(markdown("").unwrap_err(), FormatError::Empty);
+        assert_eq!(markdown("` `\n\n> ").unwrap_err(), FormatError::Empty);
+        assert_eq!(
+            markdown(&"a".repeat(MAX_BODY_CHARS + 1)).unwrap_err(),
+            FormatError::TooLong
+        );
+    }
+
+    #[test]
+    fn sanitizing_is_idempotent() {
+        for input in [
+            "<b>hi</b> & **there**",
+            "![[x](https://a.test)](https://b.test)",
+            "[[a](https://x.test)",
+            r"\\*a\\* \\\*b\\\*",
+            "> > > nested\n> back",
+            "_a_b_ **** ** `` ` ``",
+            "```\nuses `` ticks\n```",
+            "_a _b_ c_ *__a__*",
+            "**a **b** c**",
+            &format!("{} x", ">".repeat(30)),
+        ] {
+            let once = markdown(input).unwrap();
+            let twice = markdown(&once.body.body).unwrap();
+            assert_eq!(once.body.body, twice.body.body, "{input:?}");
+            assert_eq!(once.plain, twice.plain, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn apply_falls_back_to_content() {
+        let empty = FormattedBody {
+            format: MessageFormat::Markdown,
+            body: "   ".to_string(),
+        };
+        let (content, formatted) = apply("plain".into(), Some(&empty));
+        assert_eq!(content, "plain");
+        assert!(formatted.is_none());
+        assert_eq!(apply("plain".into(), None).0, "plain");
+    }
+}
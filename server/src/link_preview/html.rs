@@ -0,0 +1,163 @@
+use reqwest::Url;
+
+use super::FetchedPage;
+use crate::models::LinkPreview;
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+
+/// Pulls title, description and image out of a page, preferring Open Graph
+/// tags over `<title>` and the plain description meta tag.
+pub fn extract_preview(requested: &Url, page: &FetchedPage) -> Option<LinkPreview> {
+    let head = head_section(&page.html);
+
+    let mut og_title = None;
+    let mut og_description = None;
+    let mut og_image = None;
+    let mut og_site_name = None;
+    let mut description = None;
+
+    for tag in tags(head, "meta") {
+        let key = attribute(tag, "property").or_else(|| attribute(tag, "name"));
+        let (Some(key), Some(content)) = (key, attribute(tag, "content")) else {
+            continue;
+        };
+
+        let slot = match key.to_ascii_lowercase().as_str() {
+            "og:title" | "twitter:title" => &mut og_title,
+            "og:description" | "twitter:description" => &mut og_description,
+            "og:image" | "og:image:url" | "twitter:image" => &mut og_image,
+            "og:site_name" => &mut og_site_name,
+            "description" => &mut description,
+            _ => continue,
+        };
+        slot.get_or_insert(content);
+    }
+
+    let title = og_title
+        .or_else(|| title_tag(head))
+        .map(|title| clean(&title, MAX_TITLE_CHARS))
+        .filter(|title| !title.is_empty());
+    let description = og_description
+        .or(description)
+        .map(|description| clean(&description, MAX_DESCRIPTION_CHARS))
+        .filter(|description| !description.is_empty());
+    let image_url = og_image
+        .and_then(|image| page.url.join(image.trim()).ok())
+        .filter(|image| matches!(image.scheme(), "http" | "https"))
+        .map(String::from);
+
+    if title.is_none() && description.is_none() {
+        return None;
+    }
+
+    Some(LinkPreview {
+        url: requested.to_string(),
+        title,
+        description,
+        image_url,
+        site_name: og_site_name.map(|name| clean(&name, MAX_TITLE_CHARS)),
+    })
+}
+
+fn head_section(html: &str) -> &str {
+    let end = find_ignore_case(html, "</head").unwrap_or(html.len());
+    &html[..end]
+}
+
+/// Yields the attribute text of each `<name ...>` tag.
+fn tags<'a>(html: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    let open = format!("<{name}");
+    let mut rest = html;
+
+    std::iter::from_fn(move || {
+        loop {
+            let start = find_ignore_case(rest, &open)?;
+            let after = &rest[start + open.len()..];
+            let end = after.find('>')?;
+            rest = &after[end..];
+
+            if after.starts_with(|c: char| c.is_whitespace() || c == '/') {
+                return Some(&after[..end]);
+            }
+        }
+    })
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .trim();
+        let value_part = rest[eq + 1..].trim_start();
+
+        let (value, consumed) = match value_part.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let close = value_part[1..].find(quote)?;
+                (&value_part[1..1 + close], 2 + close)
+            }
+            _ => {
+                let end = value_part
+                    .find(|c: char| c.is_whitespace())
+                    .unwrap_or(value_part.len());
+                (&value_part[..end], end)
+            }
+        };
+
+        if key.eq_ignore_ascii_case(name) {
+            return Some(decode_entities(value));
+        }
+
+        let offset = rest.len() - value_part.len() + consumed;
+        rest = &rest[offset..];
+    }
+
+    None
+}
+
+fn title_tag(head: &str) -> Option<String> {
+    let start = find_ignore_case(head, "<title")?;
+    let open_end = head[start..].find('>')? + start + 1;
+    let close = find_ignore_case(&head[open_end..], "</title")? + open_end;
+    Some(decode_entities(&head[open_end..close]))
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Collapses whitespace, strips control characters and truncates.
+fn clean(text: &str, max_chars: usize) -> String {
+    let collapsed = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+
+    if collapsed.chars().count() > max_chars {
+        let truncated: String = collapsed.chars().take(max_chars).collect();
+        format!("{}...", truncated.trim_end())
+    } else {
+        collapsed
+    }
+}
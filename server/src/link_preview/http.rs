@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{Url, header, redirect};
+
+use super::{FetchError, FetchedPage, PageFetcher, ssrf};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 3;
+const MAX_BODY_BYTES: usize = 512 * 1024;
+const USER_AGENT: &str = concat!("simple-chat-unfurler/", env!("CARGO_PKG_VERSION"));
+
+/// Fetches pages over HTTP, refusing to connect to private addresses.
+///
+/// Each hop is resolved up front and the connection is pinned to the checked
+/// address, so DNS cannot change between the check and the request. Redirects
+/// are followed manually for the same reason, and proxies from the environment
+/// are ignored since they would resolve the host themselves.
+pub struct HttpFetcher {
+    allow_private: bool,
+}
+
+impl HttpFetcher {
+    pub fn new(allow_private: bool) -> Self {
+        Self { allow_private }
+    }
+}
+
+#[async_trait]
+impl PageFetcher for HttpFetcher {
+    async fn fetch(&self, url: &Url) -> Result<FetchedPage, FetchError> {
+        let mut url = url.clone();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let addr = ssrf::resolve_public(&url, self.allow_private).await?;
+            let host = url.host_str().unwrap_or_default().to_string();
+
+            let client = reqwest::Client::builder()
+                .redirect(redirect::Policy::none())
+                .no_proxy()
+                .timeout(TIMEOUT)
+                .user_agent(USER_AGENT)
+                .resolve(&host, addr)
+                .build()
+                .map_err(|e| FetchError::Http(e.to_string()))?;
+
+            let mut response = client
+                .get(url.clone())
+                .header(header::ACCEPT, "text/html,application/xhtml+xml")
+                .send()
+                .await
+                .map_err(|e| FetchError::Http(e.to_string()))?;
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| FetchError::Http("redirect without location".to_string()))?;
+                url = url
+                    .join(location)
+                    .map_err(|e| FetchError::Http(e.to_string()))?;
+                continue;
+            }
+
+            if !response.status().is_success() {
+                return Err(FetchError::Http(format!("status {}", response.status())));
+            }
+
+            let is_html = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.contains("html"));
+            if !is_html {
+                return Err(FetchError::NotHtml);
+            }
+
+            let mut body = Vec::new();
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|e| FetchError::Http(e.to_string()))?
+            {
+                let remaining = MAX_BODY_BYTES - body.len();
+                body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+                if body.len() == MAX_BODY_BYTES {
+                    break;
+                }
+            }
+
+            return Ok(FetchedPage {
+                url,
+                html: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+
+        Err(FetchError::Http("too many redirects".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        Router,
+        extract::Path,
+        response::{Html, IntoResponse, Redirect},
+        routing::get,
+    };
+
+    use super::*;
+
+    async fn serve() -> SocketAddr {
+        let router = Router::new()
+            .route("/page", get(|| async { Html("<title>Hello</title>") }))
+            .route(
+                "/hop/{n}",
+                get(|Path(n): Path<u32>| async move {
+                    match n {
+                        0 => Redirect::temporary("/page"),
+                        n => Redirect::temporary(&format!("/hop/{}", n - 1)),
+                    }
+                }),
+            )
+            .route(
+                "/image",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png")], vec![0u8; 16]) }),
+            )
+            .route(
+                "/large",
+                get(|| async { Html("a".repeat(MAX_BODY_BYTES * 2)) }),
+            )
+            .route(
+                "/missing",
+                get(|| async { http::StatusCode::NOT_FOUND.into_response() }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    fn url(addr: SocketAddr, path: &str) -> Url {
+        Url::parse(&format!("http://{addr}{path}")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fetches_html() {
+        let addr = serve().await;
+
+        let page = HttpFetcher::new(true)
+            .fetch(&url(addr, "/page"))
+            .await
+            .unwrap();
+
+        assert_eq!(page.url, url(addr, "/page"));
+        assert_eq!(page.html, "<title>Hello</title>");
+    }
+
+    #[tokio::test]
+    async fn follows_redirects_up_to_the_limit() {
+        let addr = serve().await;
+        let fetcher = HttpFetcher::new(true);
+
+        let page = fetcher
+            .fetch(&url(addr, &format!("/hop/{}", MAX_REDIRECTS - 1)))
+            .await
+            .unwrap();
+        assert_eq!(page.url, url(addr, "/page"));
+
+        let result = fetcher
+            .fetch(&url(addr, &format!("/hop/{MAX_REDIRECTS}")))
+            .await;
+        assert!(matches!(result, Err(FetchError::Http(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_content() {
+        let addr = serve().await;
+        let fetcher = HttpFetcher::new(true);
+
+        assert!(matches!(
+            fetcher.fetch(&url(addr, "/image")).await,
+            Err(FetchError::NotHtml)
+        ));
+        assert!(matches!(
+            fetcher.fetch(&url(addr, "/missing")).await,
+            Err(FetchError::Http(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn truncates_large_bodies() {
+        let addr = serve().await;
+
+        let page = HttpFetcher::new(true)
+            .fetch(&url(addr, "/large"))
+            .await
+            .unwrap();
+
+        assert_eq!(page.html.len(), MAX_BODY_BYTES);
+    }
+
+    #[tokio::test]
+    async fn refuses_private_addresses() {
+        let addr = serve().await;
+
+        let result = HttpFetcher::new(false).fetch(&url(addr, "/page")).await;
+
+        assert!(matches!(result, Err(FetchError::Blocked(_))));
+    }
+}
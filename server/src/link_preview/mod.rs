@@ -0,0 +1,295 @@
+//! Link unfurling for URLs posted in messages.
+//!
+//! Pages are fetched through [`PageFetcher`] so the network side can be swapped
+//! out, and results (including failures) are cached per URL.
+
+mod html;
+mod http;
+pub(crate) mod ssrf;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use reqwest::Url;
+use socketioxide::{SocketIo, socket::Sid};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    models::{LinkPreview, LinkPreviewEvent, RoomEvent, RoomEventData},
+    socket::protocol,
+    state::AppState,
+};
+
+pub use http::HttpFetcher;
+
+const MAX_LINKS_PER_MESSAGE: usize = 3;
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+const MAX_CACHE_ENTRIES: usize = 1_000;
+
+/// A fetched HTML document.
+#[derive(Debug, Clone)]
+pub struct FetchedPage {
+    /// The final URL after redirects, used to resolve relative image links.
+    pub url: Url,
+    pub html: String,
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    Blocked(String),
+    Http(String),
+    NotHtml,
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Blocked(reason) => write!(f, "blocked: {reason}"),
+            FetchError::Http(reason) => write!(f, "request failed: {reason}"),
+            FetchError::NotHtml => write!(f, "response is not HTML"),
+        }
+    }
+}
+
+#[async_trait]
+pub trait PageFetcher: Send + Sync {
+    async fn fetch(&self, url: &Url) -> Result<FetchedPage, FetchError>;
+}
+
+struct CachedPreview {
+    fetched_at: Instant,
+    preview: Option<LinkPreview>,
+}
+
+pub struct LinkPreviewService {
+    fetcher: Arc<dyn PageFetcher>,
+    cache: DashMap<String, CachedPreview>,
+}
+
+impl LinkPreviewService {
+    pub fn new(fetcher: Arc<dyn PageFetcher>) -> Self {
+        Self {
+            fetcher,
+            cache: DashMap::new(),
+        }
+    }
+
+    /// Builds the service from the environment. Set
+    /// `LINK_PREVIEW_ALLOW_PRIVATE=true` to allow fetching from private
+    /// networks, e.g. a local test server.
+    pub fn from_env() -> Self {
+        let allow_private = std::env::var("LINK_PREVIEW_ALLOW_PRIVATE")
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false);
+
+        Self::new(Arc::new(HttpFetcher::new(allow_private)))
+    }
+
+    pub async fn preview(&self, url: &Url) -> Option<LinkPreview> {
+        if let Some(cached) = self.cache.get(url.as_str())
+            && cached.fetched_at.elapsed() < CACHE_TTL
+        {
+            return cached.preview.clone();
+        }
+
+        let preview = match self.fetcher.fetch(url).await {
+            Ok(page) => html::extract_preview(url, &page),
+            Err(e) => {
+                debug!("Failed to unfurl {}: {}", url, e);
+                None
+            }
+        };
+
+        if self.cache.len() >= MAX_CACHE_ENTRIES {
+            self.cache
+                .retain(|_, cached| cached.fetched_at.elapsed() < CACHE_TTL);
+            if self.cache.len() >= MAX_CACHE_ENTRIES {
+                self.cache.clear();
+            }
+        }
+
+        self.cache.insert(
+            url.to_string(),
+            CachedPreview {
+                fetched_at: Instant::now(),
+                preview: preview.clone(),
+            },
+        );
+
+        preview
+    }
+}
+
+/// Finds the http(s) URLs in a message, in order and without duplicates.
+pub fn extract_urls(content: &str) -> Vec<Url> {
+    let mut urls: Vec<Url> = Vec::new();
+
+    for word in content.split_whitespace() {
+        let Some(start) = word.find("http://").or_else(|| word.find("https://")) else {
+            continue;
+        };
+
+        let candidate = word[start..]
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"', '>', ']']);
+
+        if let Ok(url) = Url::parse(candidate)
+            && url.host_str().is_some()
+            && !urls.contains(&url)
+        {
+            urls.push(url);
+        }
+
+        if urls.len() == MAX_LINKS_PER_MESSAGE {
+            break;
+        }
+    }
+
+    urls
+}
+
+/// Fetches previews for the links in a message, stores them on the message and
+/// broadcasts a `LinkPreview` follow-up event.
+pub async fn unfurl(
+    io: SocketIo,
+    state: AppState,
+    room_id: Uuid,
+    message_id: Uuid,
+    from: Sid,
+    urls: Vec<Url>,
+) {
+    let mut previews = Vec::new();
+    for url in &urls {
+        if let Some(preview) = state.link_previews.preview(url).await {
+            previews.push(preview);
+        }
+    }
+
+    if previews.is_empty() {
+        return;
+    }
+
+    // The sender may have left by now, so the follow-up is attributed through
+    // the message rather than their connection.
+    let sender_key = {
+        let Some(mut room) = state.rooms.get_mut(&room_id) else {
+            return;
+        };
+
+        let Some(message) = room.events.iter_mut().find(|event| event.id == message_id) else {
+            return;
+        };
+
+        match &mut message.data {
+            RoomEventData::Message(message_event) if !message_event.deleted => {
+                message_event.link_previews = previews.clone();
+            }
+            _ => return,
+        }
+        message.sender_key
+    };
+
+    let event = RoomEvent {
+        id: Uuid::new_v4(),
+        from,
+        timestamp: chrono::Utc::now(),
+        data: RoomEventData::LinkPreview(LinkPreviewEvent {
+            message_id,
+            previews,
+        }),
+        sender_key,
+    };
+
+    protocol::broadcast_room_event(&io, &state, room_id, &event).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{Router, response::Html, routing::get};
+
+    use super::*;
+
+    const PAGE: &str = r#"<html><head>
+        <title>Fallback title</title>
+        <meta property="og:title" content="Open &amp; shut">
+        <meta name="description" content="A case   about
+            whitespace">
+        <meta property="og:image" content="/cover.png">
+        <meta property="og:site_name" content="Example">
+        </head><body><meta property="og:title" content="Ignored"></body></html>"#;
+
+    /// Serves [`PAGE`] on a local port and counts how often it was requested.
+    async fn serve() -> (Url, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let router = Router::new().route(
+            "/article",
+            get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { Html(PAGE) }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        (Url::parse(&format!("http://{addr}/article")).unwrap(), hits)
+    }
+
+    #[tokio::test]
+    async fn previews_and_caches_pages() {
+        let (url, hits) = serve().await;
+        let service = LinkPreviewService::new(Arc::new(HttpFetcher::new(true)));
+
+        let preview = service.preview(&url).await.unwrap();
+        assert_eq!(preview.url, url.as_str());
+        assert_eq!(preview.title.as_deref(), Some("Open & shut"));
+        assert_eq!(
+            preview.description.as_deref(),
+            Some("A case about whitespace")
+        );
+        assert_eq!(
+            preview.image_url,
+            Some(url.join("/cover.png").unwrap().to_string())
+        );
+        assert_eq!(preview.site_name.as_deref(), Some("Example"));
+
+        assert!(service.preview(&url).await.is_some());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn caches_failures() {
+        let (url, hits) = serve().await;
+        let service = LinkPreviewService::new(Arc::new(HttpFetcher::new(false)));
+
+        assert!(service.preview(&url).await.is_none());
+        assert!(service.preview(&url).await.is_none());
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+        assert_eq!(service.cache.len(), 1);
+    }
+
+    #[test]
+    fn extracts_unique_urls() {
+        let urls = extract_urls(
+            "see (https://example.com/a), http://example.com/a! and \
+             <https://example.org> or ftp://example.net https://",
+        );
+
+        assert_eq!(
+            urls.iter().map(Url::as_str).collect::<Vec<_>>(),
+            [
+                "https://example.com/a",
+                "http://example.com/a",
+                "https://example.org/"
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::Url;
+
+use super::FetchError;
+
+/// Resolves the URL's host and returns one address that is safe to connect to.
+/// Every resolved address must be public, so a host cannot smuggle a private
+/// address in next to a public one.
+pub async fn resolve_public(url: &Url, allow_private: bool) -> Result<SocketAddr, FetchError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(FetchError::Blocked(format!("scheme {}", url.scheme())));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| FetchError::Blocked("missing host".to_string()))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| FetchError::Blocked("missing port".to_string()))?;
+
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| FetchError::Http(format!("failed to resolve {host}: {e}")))?
+        .collect();
+
+    let Some(first) = addrs.first().copied() else {
+        return Err(FetchError::Http(format!("no addresses for {host}")));
+    };
+
+    if !allow_private && let Some(blocked) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(FetchError::Blocked(format!(
+            "{host} resolves to non-public address {}",
+            blocked.ip()
+        )));
+    }
+
+    Ok(first)
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(embedded) => is_public_v4(embedded),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+/// The IPv4 address a v6 address is routed to: IPv4-mapped `::ffff:a.b.c.d`,
+/// IPv4-compatible `::a.b.c.d` and 6to4 `2002:aabb:ccdd::/48`.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    if let Some(embedded) = ip.to_ipv4() {
+        return Some(embedded);
+    }
+
+    let [first, high, low, ..] = ip.segments();
+    (first == 0x2002).then(|| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // 0.0.0.0/8 "this network"
+        || a == 0
+        // 100.64.0.0/10 carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // 192.0.0.0/24 protocol assignments
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        // 198.18.0.0/15 benchmarking
+        || (a == 198 && (18..20).contains(&b))
+        // 240.0.0.0/4 reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10 link local
+        || (first & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentation
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        // 64:ff9b::/96 NAT64, which can reach IPv4 private ranges
+        || (first == 0x0064 && ip.segments()[1] == 0xff9b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn allows_public_addresses() {
+        assert!(public("8.8.8.8"));
+        assert!(public("1.1.1.1"));
+        assert!(public("2606:4700:4700::1111"));
+        assert!(public("::ffff:8.8.8.8"));
+        assert!(public("2002:0808:0808::1"));
+    }
+
+    #[test]
+    fn blocks_private_ipv4() {
+        for ip in [
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "192.0.0.1",
+            "198.18.0.1",
+            "224.0.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(!public(ip), "{ip} should be blocked");
+        }
+    }
+
+    #[test]
+    fn blocks_private_ipv6() {
+        for ip in [
+            "::",
+            "::1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!public(ip), "{ip} should be blocked");
+        }
+    }
+
+    #[test]
+    fn blocks_private_ipv4_embedded_in_ipv6() {
+        for ip in [
+            // IPv4-mapped
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            // IPv4-compatible
+            "::127.0.0.1",
+            "::169.254.169.254",
+            "::192.168.0.1",
+            // 6to4
+            "2002:7f00:0001::1",
+            "2002:a9fe:a9fe::",
+            "2002:c0a8:0101:1::1",
+        ] {
+            assert!(!public(ip), "{ip} should be blocked");
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_private_hosts_unless_allowed() {
+        let url = Url::parse("http://127.0.0.1:8080/").unwrap();
+
+        assert!(matches!(
+            resolve_public(&url, false).await,
+            Err(FetchError::Blocked(_))
+        ));
+        assert_eq!(
+            resolve_public(&url, true).await.unwrap(),
+            "127.0.0.1:8080".parse().unwrap()
+        );
+
+        let url = Url::parse("http://[::ffff:127.0.0.1]/").unwrap();
+        assert!(matches!(
+            resolve_public(&url, false).await,
+            Err(FetchError::Blocked(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn refuses_other_schemes() {
+        let url = Url::parse("ftp://8.8.8.8/").unwrap();
+        assert!(matches!(
+            resolve_public(&url, true).await,
+            Err(FetchError::Blocked(_))
+        ));
+    }
+}
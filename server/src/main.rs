@@ -1,10 +1,23 @@
+mod audit;
+mod bots;
+mod formatting;
+mod link_preview;
+mod media;
 mod models;
+mod moderation;
+mod rate_limit;
+mod rest;
 mod socket;
 mod state;
+mod tasks;
+mod usernames;
+mod webhooks;
 
-use axum::{Router, routing::get};
+use std::net::SocketAddr;
+
+use axum::{Extension, Router, routing::get};
 use color_eyre::eyre::Context;
-use socketioxide::{SocketIoBuilder, layer::SocketIoLayer};
+use socketioxide::{SocketIo, SocketIoBuilder, layer::SocketIoLayer};
 use tokio::net::TcpListener;
 use tracing::{info, level_filters::LevelFilter, warn};
 use tracing_error::ErrorLayer;
@@ -22,16 +35,14 @@ async fn main() -> color_eyre::Result<()> {
 
     init_tracing().wrap_err("failed to set global tracing subscriber")?;
 
-    let app_state = AppState {
-        rooms: std::sync::Arc::new(Default::default()),
-        usernames: std::sync::Arc::new(Default::default()),
-        starred_messages: std::sync::Arc::new(Default::default()),
-    };
+    let app_state = AppState::from_env();
 
     let (layer, io) = SocketIoBuilder::new()
         .with_state(app_state.clone())
         .build_layer();
-    let app = init_axum(app_state.clone(), layer);
+    let app = init_axum(app_state.clone(), io.clone(), layer);
+
+    tasks::spawn_all(io.clone(), app_state.clone());
 
     init_io(io)?;
 
@@ -46,9 +57,12 @@ async fn main() -> color_eyre::Result<()> {
             .wrap_err("failed to get local address")?
     );
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .wrap_err("failed to run server")?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .wrap_err("failed to run server")?;
 
     Ok(())
 }
@@ -67,10 +81,12 @@ fn init_tracing() -> color_eyre::Result<()> {
     Ok(())
 }
 
-fn init_axum(state: AppState, io_layer: SocketIoLayer) -> Router {
+fn init_axum(state: AppState, io: SocketIo, io_layer: SocketIoLayer) -> Router {
     axum::Router::new()
         .route("/", get(|| async { "Hello, World!" }))
+        .nest("/api", rest::router())
         .layer(io_layer)
+        .layer(Extension(io))
         .with_state(state)
 }
 
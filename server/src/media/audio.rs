@@ -0,0 +1,522 @@
+//! Reads codec and duration from the audio containers the mobile apps record:
+//! Ogg (Opus or Vorbis), MP4/M4A (AAC) and WAV (PCM).
+
+use crate::models::AudioCodec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub codec: AudioCodec,
+    pub duration_ms: u64,
+    pub mime_type: &'static str,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProbeError {
+    UnknownContainer,
+    UnsupportedCodec,
+    Malformed(&'static str),
+}
+
+impl std::fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::UnknownContainer => write!(f, "unrecognized audio container"),
+            ProbeError::UnsupportedCodec => write!(f, "unsupported audio codec"),
+            ProbeError::Malformed(reason) => write!(f, "malformed audio file: {reason}"),
+        }
+    }
+}
+
+pub fn probe(data: &[u8]) -> Result<AudioInfo, ProbeError> {
+    if data.starts_with(b"OggS") {
+        probe_ogg(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+        probe_wav(data)
+    } else if data.get(4..8) == Some(b"ftyp") {
+        probe_mp4(data)
+    } else {
+        Err(ProbeError::UnknownContainer)
+    }
+}
+
+fn probe_ogg(data: &[u8]) -> Result<AudioInfo, ProbeError> {
+    const PAGE_HEADER_LEN: usize = 27;
+
+    let mut pos = 0;
+    let mut stream = None;
+    let mut last_granule = None;
+
+    while pos < data.len() {
+        let header = data
+            .get(pos..pos + PAGE_HEADER_LEN)
+            .filter(|header| header.starts_with(b"OggS"))
+            .ok_or(ProbeError::Malformed("truncated Ogg page"))?;
+
+        let granule = i64::from_le_bytes(header[6..14].try_into().unwrap());
+        let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+        let segment_count = usize::from(header[26]);
+
+        let segments = data
+            .get(pos + PAGE_HEADER_LEN..pos + PAGE_HEADER_LEN + segment_count)
+            .ok_or(ProbeError::Malformed("truncated Ogg segment table"))?;
+        let body_start = pos + PAGE_HEADER_LEN + segment_count;
+        let body_len: usize = segments.iter().map(|len| usize::from(*len)).sum();
+        let body = data
+            .get(body_start..body_start + body_len)
+            .ok_or(ProbeError::Malformed("truncated Ogg page body"))?;
+
+        match stream {
+            None => stream = Some((serial, ogg_stream_header(body)?)),
+            // A granule position of -1 marks a page on which no packet ends.
+            Some((stream_serial, _)) if stream_serial == serial && granule >= 0 => {
+                last_granule = Some(granule);
+            }
+            _ => {}
+        }
+
+        pos = body_start + body_len;
+    }
+
+    let (_, (codec, sample_rate, pre_skip)) =
+        stream.ok_or(ProbeError::Malformed("empty Ogg stream"))?;
+    let granule = last_granule.ok_or(ProbeError::Malformed("Ogg stream has no audio"))?;
+    let samples = u64::try_from(granule)
+        .unwrap_or_default()
+        .saturating_sub(pre_skip);
+
+    Ok(AudioInfo {
+        codec,
+        duration_ms: samples.saturating_mul(1000) / sample_rate,
+        mime_type: "audio/ogg",
+    })
+}
+
+/// Reads codec, sample rate and pre-skip from the first packet of a stream.
+fn ogg_stream_header(packet: &[u8]) -> Result<(AudioCodec, u64, u64), ProbeError> {
+    if packet.starts_with(b"OpusHead") {
+        let pre_skip = packet
+            .get(10..12)
+            .ok_or(ProbeError::Malformed("truncated Opus header"))?;
+        // Opus granule positions always count 48 kHz samples.
+        return Ok((
+            AudioCodec::Opus,
+            48_000,
+            u64::from(u16::from_le_bytes([pre_skip[0], pre_skip[1]])),
+        ));
+    }
+
+    if packet.starts_with(b"\x01vorbis") {
+        let rate = packet
+            .get(12..16)
+            .ok_or(ProbeError::Malformed("truncated Vorbis header"))?;
+        let rate = u32::from_le_bytes(rate.try_into().unwrap());
+        if rate == 0 {
+            return Err(ProbeError::Malformed("zero sample rate"));
+        }
+        return Ok((AudioCodec::Vorbis, u64::from(rate), 0));
+    }
+
+    Err(ProbeError::UnsupportedCodec)
+}
+
+fn probe_wav(data: &[u8]) -> Result<AudioInfo, ProbeError> {
+    const PCM: u16 = 1;
+    const IEEE_FLOAT: u16 = 3;
+    const EXTENSIBLE: u16 = 0xfffe;
+
+    let mut pos = 12;
+    let mut byte_rate = None;
+    let mut data_len = None;
+
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body_start = pos + 8;
+        let available = data.len() - body_start;
+
+        match id {
+            b"fmt " => {
+                let fmt = data
+                    .get(body_start..body_start + 16)
+                    .ok_or(ProbeError::Malformed("truncated fmt chunk"))?;
+                let format = u16::from_le_bytes([fmt[0], fmt[1]]);
+                if !matches!(format, PCM | IEEE_FLOAT | EXTENSIBLE) {
+                    return Err(ProbeError::UnsupportedCodec);
+                }
+                byte_rate = Some(u32::from_le_bytes(fmt[8..12].try_into().unwrap()));
+            }
+            // Recorders that stream to disk may leave the size unset, so never
+            // trust it past the end of the file.
+            b"data" => data_len = Some(size.min(available)),
+            _ => {}
+        }
+
+        pos = body_start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    let byte_rate = byte_rate
+        .filter(|rate| *rate > 0)
+        .ok_or(ProbeError::Malformed("missing fmt chunk"))?;
+    let data_len = data_len.ok_or(ProbeError::Malformed("missing data chunk"))?;
+
+    Ok(AudioInfo {
+        codec: AudioCodec::Pcm,
+        duration_ms: data_len as u64 * 1000 / u64::from(byte_rate),
+        mime_type: "audio/wav",
+    })
+}
+
+fn probe_mp4(data: &[u8]) -> Result<AudioInfo, ProbeError> {
+    let moov = find_box(data, b"moov").ok_or(ProbeError::Malformed("missing moov box"))?;
+    let mvhd = find_box(moov, b"mvhd").ok_or(ProbeError::Malformed("missing mvhd box"))?;
+
+    let (timescale, duration) = match mvhd.first() {
+        Some(0) if mvhd.len() >= 20 => (
+            u32::from_be_bytes(mvhd[12..16].try_into().unwrap()),
+            u64::from(u32::from_be_bytes(mvhd[16..20].try_into().unwrap())),
+        ),
+        Some(1) if mvhd.len() >= 32 => (
+            u32::from_be_bytes(mvhd[20..24].try_into().unwrap()),
+            u64::from_be_bytes(mvhd[24..32].try_into().unwrap()),
+        ),
+        _ => return Err(ProbeError::Malformed("bad mvhd box")),
+    };
+
+    if timescale == 0 {
+        return Err(ProbeError::Malformed("zero timescale"));
+    }
+
+    let is_aac = boxes(moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .filter_map(|(_, trak)| sample_entry_format(trak))
+        .any(|format| format == *b"mp4a");
+    if !is_aac {
+        return Err(ProbeError::UnsupportedCodec);
+    }
+
+    Ok(AudioInfo {
+        codec: AudioCodec::Aac,
+        duration_ms: duration.saturating_mul(1000) / u64::from(timescale),
+        mime_type: "audio/mp4",
+    })
+}
+
+/// Returns the format code of a track's first sample description.
+fn sample_entry_format(trak: &[u8]) -> Option<[u8; 4]> {
+    let stsd = [b"mdia", b"minf", b"stbl", b"stsd"]
+        .iter()
+        .try_fold(trak, |parent, kind| find_box(parent, kind))?;
+
+    // Version and flags, entry count, then the first entry's size and format.
+    stsd.get(12..16)?.try_into().ok()
+}
+
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(box_kind, _)| box_kind == kind)
+        .map(|(_, body)| body)
+}
+
+/// Iterates over the boxes directly inside `data`, yielding type and body.
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = data;
+
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?);
+        let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+
+        let (header_len, box_len) = match size {
+            0 => (8, rest.len()),
+            1 => {
+                let size = u64::from_be_bytes(rest.get(8..16)?.try_into().ok()?);
+                (16, usize::try_from(size).ok()?)
+            }
+            size => (8, size as usize),
+        };
+
+        if box_len < header_len || box_len > rest.len() {
+            return None;
+        }
+
+        let body = &rest[header_len..box_len];
+        rest = &rest[box_len..];
+        Some((kind, body))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ogg_page(serial: u32, granule: i64, body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        // Sequence number and checksum, which the probe does not read.
+        page.extend_from_slice(&[0; 8]);
+
+        let mut lacing = vec![255; body.len() / 255];
+        lacing.push((body.len() % 255) as u8);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(body);
+        page
+    }
+
+    fn opus_head(pre_skip: u16) -> Vec<u8> {
+        let mut head = b"OpusHead\x01\x01".to_vec();
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        head
+    }
+
+    fn vorbis_header(rate: u32) -> Vec<u8> {
+        let mut header = b"\x01vorbis\0\0\0\0\x02".to_vec();
+        header.extend_from_slice(&rate.to_le_bytes());
+        header.extend_from_slice(&[0; 14]);
+        header
+    }
+
+    fn ogg(head: &[u8], granules: &[i64]) -> Vec<u8> {
+        let mut data = ogg_page(7, 0, head);
+        for granule in granules {
+            data.extend(ogg_page(7, *granule, &[0; 300]));
+        }
+        data
+    }
+
+    #[test]
+    fn probes_ogg_opus() {
+        let data = ogg(&opus_head(312), &[48_000, -1, 96_312]);
+
+        assert_eq!(
+            probe(&data),
+            Ok(AudioInfo {
+                codec: AudioCodec::Opus,
+                duration_ms: 2_000,
+                mime_type: "audio/ogg",
+            })
+        );
+    }
+
+    #[test]
+    fn probes_ogg_vorbis_and_ignores_other_streams() {
+        let mut data = ogg(&vorbis_header(44_100), &[132_300]);
+        data.extend(ogg_page(8, 441_000_000, b"other stream"));
+
+        let info = probe(&data).unwrap();
+        assert_eq!(info.codec, AudioCodec::Vorbis);
+        assert_eq!(info.duration_ms, 3_000);
+    }
+
+    #[test]
+    fn saturates_huge_ogg_granules() {
+        let data = ogg(&opus_head(0), &[i64::MAX]);
+
+        assert_eq!(probe(&data).unwrap().duration_ms, u64::MAX / 48_000);
+    }
+
+    #[test]
+    fn rejects_malformed_ogg() {
+        let data = ogg(&opus_head(0), &[48_000]);
+        assert!(matches!(
+            probe(&data[..data.len() - 1]),
+            Err(ProbeError::Malformed(_))
+        ));
+        assert!(matches!(probe(&data[..30]), Err(ProbeError::Malformed(_))));
+
+        let mut trailing = data.clone();
+        trailing.extend_from_slice(b"junk");
+        assert!(matches!(probe(&trailing), Err(ProbeError::Malformed(_))));
+
+        assert_eq!(
+            probe(&ogg(&opus_head(0), &[])),
+            Err(ProbeError::Malformed("Ogg stream has no audio"))
+        );
+        assert_eq!(
+            probe(&ogg(b"OpusHead", &[48_000])),
+            Err(ProbeError::Malformed("truncated Opus header"))
+        );
+        assert_eq!(
+            probe(&ogg(&vorbis_header(0), &[48_000])),
+            Err(ProbeError::Malformed("zero sample rate"))
+        );
+        assert_eq!(
+            probe(&ogg(b"\x80theora", &[48_000])),
+            Err(ProbeError::UnsupportedCodec)
+        );
+    }
+
+    fn wav(format: u16, byte_rate: u32, samples: &[u8], data_size: u32) -> Vec<u8> {
+        let mut data = b"RIFF\0\0\0\0WAVE".to_vec();
+
+        data.extend_from_slice(b"fmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&format.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&(byte_rate / 2).to_le_bytes());
+        data.extend_from_slice(&byte_rate.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&16u16.to_le_bytes());
+
+        // An odd-sized chunk, which is padded to an even length.
+        data.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&data_size.to_le_bytes());
+        data.extend_from_slice(samples);
+        data
+    }
+
+    #[test]
+    fn probes_wav() {
+        let data = wav(1, 16_000, &[0; 24_000], 24_000);
+
+        assert_eq!(
+            probe(&data),
+            Ok(AudioInfo {
+                codec: AudioCodec::Pcm,
+                duration_ms: 1_500,
+                mime_type: "audio/wav",
+            })
+        );
+    }
+
+    #[test]
+    fn clamps_unset_wav_data_size() {
+        let data = wav(1, 16_000, &[0; 8_000], u32::MAX);
+
+        assert_eq!(probe(&data).unwrap().duration_ms, 500);
+    }
+
+    #[test]
+    fn rejects_malformed_wav() {
+        assert_eq!(
+            probe(&wav(0x55, 16_000, &[0; 100], 100)),
+            Err(ProbeError::UnsupportedCodec)
+        );
+        assert_eq!(
+            probe(&wav(1, 0, &[0; 100], 100)),
+            Err(ProbeError::Malformed("missing fmt chunk"))
+        );
+
+        let data = wav(1, 16_000, &[0; 100], 100);
+        assert_eq!(
+            probe(&data[..30]),
+            Err(ProbeError::Malformed("truncated fmt chunk"))
+        );
+        assert_eq!(
+            probe(&data[..48]),
+            Err(ProbeError::Malformed("missing data chunk"))
+        );
+        assert_eq!(
+            probe(b"RIFF\0\0\0\0WAVE"),
+            Err(ProbeError::Malformed("missing fmt chunk"))
+        );
+    }
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn mp4(mvhd: &[u8], format: &[u8; 4]) -> Vec<u8> {
+        let mut stsd = vec![0; 4];
+        stsd.extend_from_slice(&1u32.to_be_bytes());
+        stsd.extend_from_slice(&16u32.to_be_bytes());
+        stsd.extend_from_slice(format);
+        stsd.extend_from_slice(&[0; 8]);
+
+        let trak = [b"stsd", b"stbl", b"minf", b"mdia", b"trak"]
+            .iter()
+            .fold(stsd, |body, kind| mp4_box(kind, &body));
+
+        let mut moov = mp4_box(b"mvhd", mvhd);
+        moov.extend(trak);
+
+        let mut data = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        data.extend(mp4_box(b"free", &[0; 16]));
+        data.extend(mp4_box(b"moov", &moov));
+        data
+    }
+
+    fn mvhd_v0(timescale: u32, duration: u32) -> Vec<u8> {
+        let mut mvhd = vec![0; 12];
+        mvhd.extend_from_slice(&timescale.to_be_bytes());
+        mvhd.extend_from_slice(&duration.to_be_bytes());
+        mvhd.extend_from_slice(&[0; 80]);
+        mvhd
+    }
+
+    fn mvhd_v1(timescale: u32, duration: u64) -> Vec<u8> {
+        let mut mvhd = vec![1, 0, 0, 0];
+        mvhd.extend_from_slice(&[0; 16]);
+        mvhd.extend_from_slice(&timescale.to_be_bytes());
+        mvhd.extend_from_slice(&duration.to_be_bytes());
+        mvhd.extend_from_slice(&[0; 80]);
+        mvhd
+    }
+
+    #[test]
+    fn probes_mp4() {
+        assert_eq!(
+            probe(&mp4(&mvhd_v0(44_100, 110_250), b"mp4a")),
+            Ok(AudioInfo {
+                codec: AudioCodec::Aac,
+                duration_ms: 2_500,
+                mime_type: "audio/mp4",
+            })
+        );
+        assert_eq!(
+            probe(&mp4(&mvhd_v1(1_000, u64::MAX), b"mp4a"))
+                .unwrap()
+                .duration_ms,
+            u64::MAX / 1_000
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_mp4() {
+        assert_eq!(
+            probe(&mp4(&mvhd_v0(44_100, 1), b"avc1")),
+            Err(ProbeError::UnsupportedCodec)
+        );
+        assert_eq!(
+            probe(&mp4(&mvhd_v0(0, 1), b"mp4a")),
+            Err(ProbeError::Malformed("zero timescale"))
+        );
+        assert_eq!(
+            probe(&mp4(&[0; 8], b"mp4a")),
+            Err(ProbeError::Malformed("bad mvhd box"))
+        );
+
+        let data = mp4(&mvhd_v0(44_100, 1), b"mp4a");
+        assert_eq!(
+            probe(&data[..data.len() - 1]),
+            Err(ProbeError::Malformed("missing moov box"))
+        );
+        assert_eq!(
+            probe(&mp4_box(b"ftyp", b"M4A ")),
+            Err(ProbeError::Malformed("missing moov box"))
+        );
+
+        // A box that claims to be smaller than its own header.
+        let mut data = mp4_box(b"ftyp", b"M4A ");
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(b"moov");
+        assert_eq!(probe(&data), Err(ProbeError::Malformed("missing moov box")));
+    }
+
+    #[test]
+    fn rejects_unknown_containers() {
+        assert_eq!(probe(b""), Err(ProbeError::UnknownContainer));
+        assert_eq!(probe(b"ID3\x04"), Err(ProbeError::UnknownContainer));
+        assert_eq!(
+            probe(b"RIFF\0\0\0\0AVI "),
+            Err(ProbeError::UnknownContainer)
+        );
+    }
+}
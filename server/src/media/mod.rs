@@ -0,0 +1,275 @@
+//! Storage for uploaded media, referenced from events by blob id.
+//!
+//! Blobs live in memory. An upload that no message or profile claims within
+//! [`UNCLAIMED_TTL`] is dropped by the media task, and a claimed one is freed
+//! when its message is redacted or pruned. Each uploader may hold at most
+//! [`MAX_USER_BYTES`], so no single user can fill the store.
+
+pub mod audio;
+pub mod image;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::models::RoomEventData;
+
+/// Largest single upload.
+pub const MAX_BLOB_BYTES: usize = 5 * 1024 * 1024;
+/// Upper bound on everything held by the store.
+const MAX_STORE_BYTES: usize = 512 * 1024 * 1024;
+/// Upper bound on everything one user key has uploaded and not yet freed.
+pub const MAX_USER_BYTES: usize = 50 * 1024 * 1024;
+pub const UNCLAIMED_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct StoredBlob {
+    pub mime_type: String,
+    pub data: Arc<[u8]>,
+    /// User key of the uploader.
+    pub uploaded_by: Uuid,
+    pub uploaded_at: DateTime<Utc>,
+    /// Whether a message references the blob yet.
+    pub claimed: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MediaError {
+    Empty,
+    TooLarge,
+    StoreFull,
+    QuotaExceeded,
+    UnsupportedType(String),
+    NotFound,
+    NotOwner,
+    AlreadyClaimed,
+}
+
+impl std::fmt::Display for MediaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaError::Empty => write!(f, "Upload is empty"),
+            MediaError::TooLarge => write!(
+                f,
+                "Upload exceeds the {} MB limit",
+                MAX_BLOB_BYTES / (1024 * 1024)
+            ),
+            MediaError::StoreFull => write!(f, "Media storage is full, try again later"),
+            MediaError::QuotaExceeded => write!(
+                f,
+                "You have uploaded more than {} MB of media, delete some messages first",
+                MAX_USER_BYTES / (1024 * 1024)
+            ),
+            MediaError::UnsupportedType(mime_type) => {
+                write!(f, "Unsupported media type {mime_type}")
+            }
+            MediaError::NotFound => write!(f, "Media does not exist"),
+            MediaError::NotOwner => write!(f, "Media was uploaded by someone else"),
+            MediaError::AlreadyClaimed => write!(f, "Media is already used by another message"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MediaStore {
+    blobs: DashMap<Uuid, StoredBlob>,
+    total_bytes: AtomicUsize,
+    /// Bytes held per uploader, by user key.
+    user_bytes: DashMap<Uuid, usize>,
+}
+
+impl MediaStore {
+    pub fn insert(
+        &self,
+        mime_type: String,
+        data: Vec<u8>,
+        uploaded_by: Uuid,
+    ) -> Result<Uuid, MediaError> {
+        if data.is_empty() {
+            return Err(MediaError::Empty);
+        }
+        if data.len() > MAX_BLOB_BYTES {
+            return Err(MediaError::TooLarge);
+        }
+
+        let size = data.len();
+        {
+            let mut used = self.user_bytes.entry(uploaded_by).or_insert(0);
+            if *used + size > MAX_USER_BYTES {
+                return Err(MediaError::QuotaExceeded);
+            }
+            *used += size;
+        }
+
+        if self
+            .total_bytes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+                (total + size <= MAX_STORE_BYTES).then_some(total + size)
+            })
+            .is_err()
+        {
+            self.forget_bytes(uploaded_by, size);
+            return Err(MediaError::StoreFull);
+        }
+
+        let id = Uuid::new_v4();
+        self.blobs.insert(
+            id,
+            StoredBlob {
+                mime_type,
+                data: data.into(),
+                uploaded_by,
+                uploaded_at: Utc::now(),
+                claimed: false,
+            },
+        );
+
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<StoredBlob> {
+        self.blobs.get(id).map(|blob| blob.clone())
+    }
+
+    /// Marks an upload as used by a message. Only the uploader may claim a
+    /// blob, and only once.
+    pub fn claim(&self, id: &Uuid, user_key: Uuid) -> Result<StoredBlob, MediaError> {
+        let mut blob = self.blobs.get_mut(id).ok_or(MediaError::NotFound)?;
+
+        if blob.uploaded_by != user_key {
+            return Err(MediaError::NotOwner);
+        }
+        if blob.claimed {
+            return Err(MediaError::AlreadyClaimed);
+        }
+
+        blob.claimed = true;
+        Ok(blob.clone())
+    }
+
+    /// Deletes a blob that is no longer referenced, such as a replaced avatar.
+    pub fn remove(&self, id: &Uuid) {
+        if let Some((_, blob)) = self.blobs.remove(id) {
+            self.total_bytes
+                .fetch_sub(blob.data.len(), Ordering::SeqCst);
+            self.forget_bytes(blob.uploaded_by, blob.data.len());
+        }
+    }
+
+    /// Frees the blob an event refers to, once the event is redacted or
+    /// pruned from the history.
+    pub fn release(&self, data: &RoomEventData) {
+        if let RoomEventData::Audio(audio) = data {
+            self.remove(&audio.blob_id);
+        }
+    }
+
+    /// Drops uploads that were never claimed by a message.
+    pub fn prune_unclaimed(&self, now: DateTime<Utc>) {
+        let cutoff = now - UNCLAIMED_TTL;
+        let mut freed = Vec::new();
+
+        self.blobs.retain(|_, blob| {
+            let keep = blob.claimed || blob.uploaded_at > cutoff;
+            if !keep {
+                freed.push((blob.uploaded_by, blob.data.len()));
+            }
+            keep
+        });
+
+        let total: usize = freed.iter().map(|(_, size)| size).sum();
+        for (uploaded_by, size) in freed {
+            self.forget_bytes(uploaded_by, size);
+        }
+
+        if total > 0 {
+            self.total_bytes.fetch_sub(total, Ordering::SeqCst);
+            info!("Pruned {} bytes of unclaimed media", total);
+        }
+    }
+
+    fn forget_bytes(&self, uploaded_by: Uuid, size: usize) {
+        self.user_bytes.remove_if_mut(&uploaded_by, |_, used| {
+            *used = used.saturating_sub(size);
+            *used == 0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AudioCodec, AudioMessageEvent};
+
+    #[test]
+    fn releasing_an_audio_event_frees_its_blob() {
+        let store = MediaStore::default();
+        let owner = Uuid::new_v4();
+        let blob_id = store
+            .insert("audio/ogg".to_string(), vec![0; 1024], owner)
+            .unwrap();
+        store.claim(&blob_id, owner).unwrap();
+
+        let mut data = RoomEventData::Audio(AudioMessageEvent {
+            blob_id,
+            duration_ms: 1_000,
+            codec: AudioCodec::Opus,
+            waveform: vec![0; 32],
+            mime_type: "audio/ogg".to_string(),
+            size: 1024,
+            deleted: false,
+            reply_to: None,
+            expires_at: None,
+        });
+        assert!(data.redact());
+        store.release(&data);
+
+        assert!(store.get(&blob_id).is_none());
+        assert_eq!(store.total_bytes.load(Ordering::SeqCst), 0);
+
+        // Releasing twice, e.g. when a redacted message is later pruned, is
+        // harmless.
+        store.release(&data);
+        assert_eq!(store.total_bytes.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn limits_what_one_user_may_hold() {
+        let store = MediaStore::default();
+        let (greedy, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut uploaded = Vec::new();
+        for _ in 0..MAX_USER_BYTES / MAX_BLOB_BYTES {
+            uploaded.push(
+                store
+                    .insert("audio/ogg".to_string(), vec![0; MAX_BLOB_BYTES], greedy)
+                    .unwrap(),
+            );
+        }
+        assert_eq!(
+            store.insert("audio/ogg".to_string(), vec![0; 1024], greedy),
+            Err(MediaError::QuotaExceeded)
+        );
+        assert!(
+            store
+                .insert("audio/ogg".to_string(), vec![0; 1024], other)
+                .is_ok()
+        );
+
+        store.remove(&uploaded[0]);
+        assert!(
+            store
+                .insert("audio/ogg".to_string(), vec![0; 1024], greedy)
+                .is_ok()
+        );
+    }
+}
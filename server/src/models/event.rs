@@ -1,24 +1,37 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::socket::Sid;
 use ts_rs::TS;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+use crate::models::ProfileSummary;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct RoomEvent {
     pub id: Uuid,
     #[ts(type = "String")]
+    #[schemars(with = "String")]
     pub from: Sid,
     pub timestamp: DateTime<Utc>,
     pub data: RoomEventData,
+    /// The sender's user key, kept so that blocks still apply once they have
+    /// disconnected. User keys are credentials, so this is never sent.
+    #[serde(skip)]
+    #[ts(skip)]
+    #[schemars(skip)]
+    pub sender_key: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub enum RoomEventData {
     Message(TextMessageEvent),
     Image(ImageMessageEvent),
+    Audio(AudioMessageEvent),
     MessageEdit(MessageEditEvent),
     MessageDelete(MessageDeleteEvent),
     Reaction(ReactionEvent),
@@ -27,9 +40,78 @@ pub enum RoomEventData {
     UserLeave(UserLeaveEvent),
     MessageStar(MessageStarEvent),
     MessageUnstar(MessageUnstarEvent),
+    MessagePin(MessagePinEvent),
+    MessageUnpin(MessageUnpinEvent),
+    LinkPreview(LinkPreviewEvent),
+    Poll(PollEvent),
+    PollUpdate(PollUpdateEvent),
+    TopicChange(TopicChangeEvent),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+impl RoomEventData {
+    /// Clears the content of a message and everything derived from it,
+    /// leaving a deleted placeholder behind. Returns `false` for events that
+    /// are not messages.
+    pub fn redact(&mut self) -> bool {
+        match self {
+            RoomEventData::Message(message) => {
+                message.deleted = true;
+                message.content = String::new();
+                message.formatted = None;
+                message.link_previews = Vec::new();
+                message.mentions = Vec::new();
+                message.mentions_room = false;
+                message.emote = false;
+                true
+            }
+            RoomEventData::Image(image) => {
+                image.deleted = true;
+                image.image_data = String::new();
+                image.filename = String::new();
+                image.width = None;
+                image.height = None;
+                true
+            }
+            RoomEventData::Audio(audio) => {
+                audio.deleted = true;
+                audio.waveform = Vec::new();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn reply_to_mut(&mut self) -> Option<&mut MessageReply> {
+        match self {
+            RoomEventData::Message(message) => message.reply_to.as_mut(),
+            RoomEventData::Image(image) => image.reply_to.as_mut(),
+            RoomEventData::Audio(audio) => audio.reply_to.as_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            RoomEventData::Message(message) if !message.deleted => message.expires_at,
+            RoomEventData::Image(image) if !image.deleted => image.expires_at,
+            RoomEventData::Audio(audio) if !audio.deleted => audio.expires_at,
+            _ => None,
+        }
+    }
+
+    /// Whether this is a message that has been neither deleted nor expired.
+    pub fn is_live_message(&self, now: DateTime<Utc>) -> bool {
+        let deleted = match self {
+            RoomEventData::Message(message) => message.deleted,
+            RoomEventData::Image(image) => image.deleted,
+            RoomEventData::Audio(audio) => audio.deleted,
+            _ => return false,
+        };
+        !deleted && self.expires_at().is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct TextMessageEvent {
     pub content: String,
@@ -39,107 +121,324 @@ pub struct TextMessageEvent {
     pub deleted: bool,
     #[serde(default)]
     pub reply_to: Option<MessageReply>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Users mentioned by `@username`, resolved by the server.
+    #[serde(default)]
+    #[ts(type = "Array<String>")]
+    #[schemars(with = "Vec<String>")]
+    pub mentions: Vec<Sid>,
+    /// Whether the message mentions `@room`.
+    #[serde(default)]
+    pub mentions_room: bool,
+    /// Optional rich text version of `content`, which then holds the plain fallback.
+    #[serde(default)]
+    pub formatted: Option<FormattedBody>,
+    /// Previews for links in `content`, filled in by the server after sending.
+    #[serde(default)]
+    pub link_previews: Vec<LinkPreview>,
+    /// Sent with `/me`: `content` describes something the sender does.
+    #[serde(default)]
+    pub emote: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct FormattedBody {
+    pub format: MessageFormat,
+    pub body: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum MessageFormat {
+    Markdown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct ImageMessageEvent {
     pub image_data: String, // Base64 encoded image
     pub filename: String,
     pub mime_type: String,
+    /// Bytes of decoded image data, measured by the server.
     pub size: u32,
     #[serde(default)]
     pub width: Option<u32>,
     #[serde(default)]
     pub height: Option<u32>,
     #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
     pub reply_to: Option<MessageReply>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+/// A voice note or audio clip. The audio itself is uploaded separately with
+/// `media.upload` and referenced by `blob_id`.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct AudioMessageEvent {
+    pub blob_id: Uuid,
+    /// Checked against the container metadata when the message is sent.
+    pub duration_ms: u32,
+    pub codec: AudioCodec,
+    /// Peak levels from 0 to 255 sampled across the clip, for drawing a
+    /// waveform without downloading the audio.
+    #[serde(default)]
+    pub waveform: Vec<u8>,
+    /// Filled in by the server from the stored blob.
+    #[serde(default)]
+    pub mime_type: String,
+    /// Filled in by the server from the stored blob.
+    #[serde(default)]
+    pub size: u32,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub reply_to: Option<MessageReply>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum AudioCodec {
+    /// Opus in an Ogg container.
+    Opus,
+    /// Vorbis in an Ogg container.
+    Vorbis,
+    /// AAC in an MP4/M4A container.
+    Aac,
+    /// Uncompressed PCM in a WAV container.
+    Pcm,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct MessageEditEvent {
     pub message_id: Uuid,
     pub new_content: String,
+    #[serde(default)]
+    #[ts(type = "Array<String>")]
+    #[schemars(with = "Vec<String>")]
+    pub mentions: Vec<Sid>,
+    #[serde(default)]
+    pub mentions_room: bool,
+    #[serde(default)]
+    pub formatted: Option<FormattedBody>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct MessageDeleteEvent {
     pub message_id: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct ReactionEvent {
     pub message_id: Uuid,
     pub reaction: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct ReactionRemoveEvent {
     pub message_id: Uuid,
     pub reaction: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct UserJoinEvent {
     #[ts(type = "String")]
+    #[schemars(with = "String")]
     pub user_id: Sid,
     pub username: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct UserLeaveEvent {
     #[ts(type = "String")]
+    #[schemars(with = "String")]
     pub user_id: Sid,
     pub username: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct RoomMembersResponse {
     pub members: Vec<RoomMember>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct RoomMember {
     #[ts(type = "String")]
+    #[schemars(with = "String")]
     pub user_id: Sid,
     pub username: Option<String>,
+    #[serde(default)]
+    pub is_bot: bool,
+    #[serde(default)]
+    pub profile: Option<ProfileSummary>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct MessageReply {
     pub message_id: Uuid,
     #[ts(type = "String")]
+    #[schemars(with = "String")]
     pub user_id: Sid,
     pub username: Option<String>,
     pub content_preview: String,
     pub message_type: ReplyMessageType,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct MessageStarEvent {
     pub message_id: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct MessageUnstarEvent {
     pub message_id: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct MessagePinEvent {
+    pub message_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct MessageUnpinEvent {
+    pub message_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct LinkPreviewEvent {
+    pub message_id: Uuid,
+    pub previews: Vec<LinkPreview>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct PollEvent {
+    pub question: String,
+    pub options: Vec<PollOption>,
+    #[serde(default)]
+    pub multiple_choice: bool,
+    /// Hides who voted for what; only the tallies are shared.
+    #[serde(default)]
+    pub anonymous: bool,
+    #[serde(default)]
+    pub closes_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub closed: bool,
+    #[serde(default)]
+    pub total_voters: u32,
+    /// Every voter's ballot, keyed by user key so reconnecting does not grant
+    /// another vote. Never sent to clients.
+    #[serde(skip)]
+    #[ts(skip)]
+    pub ballots: HashMap<Uuid, PollBallot>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PollBallot {
+    /// The connection the vote was last cast from, listed as the voter.
+    pub voter: Sid,
+    pub choices: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct PollOption {
+    pub text: String,
+    #[serde(default)]
+    pub votes: u32,
+    /// Who voted for this option, left empty for anonymous polls.
+    #[serde(default)]
+    #[ts(type = "Array<String>")]
+    #[schemars(with = "Vec<String>")]
+    pub voters: Vec<Sid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct PollUpdateEvent {
+    pub poll_id: Uuid,
+    pub options: Vec<PollOption>,
+    pub total_voters: u32,
+    pub closed: bool,
+}
+
+impl PollEvent {
+    /// Recomputes the per-option tallies from the ballots.
+    pub fn recount(&mut self) {
+        for option in &mut self.options {
+            option.votes = 0;
+            option.voters.clear();
+        }
+
+        for ballot in self.ballots.values() {
+            for index in &ballot.choices {
+                if let Some(option) = self.options.get_mut(*index) {
+                    option.votes += 1;
+                    if !self.anonymous {
+                        option.voters.push(ballot.voter);
+                    }
+                }
+            }
+        }
+
+        self.total_voters = self.ballots.len() as u32;
+    }
+
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        !self.closed && self.closes_at.is_none_or(|closes_at| closes_at > now)
+    }
+
+    pub fn update_event(&self, poll_id: Uuid) -> PollUpdateEvent {
+        PollUpdateEvent {
+            poll_id,
+            options: self.options.clone(),
+            total_voters: self.total_voters,
+            closed: self.closed,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub enum ReplyMessageType {
     Text,
     Image,
+    Audio,
     Deleted,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct TopicChangeEvent {
+    pub topic: String,
+}
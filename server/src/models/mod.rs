@@ -1,5 +1,7 @@
 pub mod event;
+pub mod profile;
 pub mod room;
 
 pub use event::*;
+pub use profile::*;
 pub use room::*;
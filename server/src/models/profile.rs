@@ -0,0 +1,42 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub struct UserProfile {
+    /// Shown instead of the username where there is room for it.
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Blob id of an image uploaded with `media.upload`, fetched with
+    /// `user.get_avatar`.
+    #[serde(default)]
+    pub avatar_id: Option<Uuid>,
+    #[serde(default)]
+    pub bio: Option<String>,
+    /// IANA time zone name, such as `Europe/Berlin`.
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+/// The parts of a profile shown next to a member's name.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ProfileSummary {
+    pub display_name: Option<String>,
+    pub avatar_id: Option<Uuid>,
+}
+
+impl UserProfile {
+    pub fn summary(&self) -> Option<ProfileSummary> {
+        if self.display_name.is_none() && self.avatar_id.is_none() {
+            return None;
+        }
+
+        Some(ProfileSummary {
+            display_name: self.display_name.clone(),
+            avatar_id: self.avatar_id,
+        })
+    }
+}
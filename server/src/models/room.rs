@@ -1,17 +1,272 @@
 use std::collections::HashSet;
 
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::socket::Sid;
 use ts_rs::TS;
 use uuid::Uuid;
 
-use crate::models::RoomEvent;
+use crate::models::{ReplyMessageType, RoomEvent};
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 pub struct Room {
     pub id: Uuid,
     pub name: String,
     #[ts(type = "HashSet<String>")]
+    #[schemars(with = "HashSet<String>")]
     pub members: HashSet<Sid>,
+    /// User keys of everyone who joined and has not left or been banned.
+    /// Unlike `members` this survives disconnects, so work done on a user's
+    /// behalf while they are offline can check it.
+    #[serde(default)]
+    pub member_keys: HashSet<Uuid>,
     pub events: Vec<RoomEvent>,
+    #[serde(default)]
+    pub policy: RoomPolicy,
+    /// User keys of the room's moderators, so the role survives reconnects.
+    #[serde(default)]
+    pub moderators: HashSet<Uuid>,
+    #[serde(default)]
+    pub pinned: Vec<Uuid>,
+    #[serde(default)]
+    pub kind: RoomKind,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub slow_mode: Option<SlowMode>,
+    /// User keys of the users banned from the room.
+    #[serde(default)]
+    pub banned: HashSet<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum RoomKind {
+    #[default]
+    Public,
+    /// A private conversation between a fixed set of users, identified by
+    /// their sorted user keys.
+    Direct { participants: Vec<Uuid> },
+}
+
+impl Room {
+    pub fn new(id: Uuid, name: String) -> Self {
+        Self {
+            id,
+            name,
+            members: HashSet::new(),
+            member_keys: HashSet::new(),
+            events: Vec::new(),
+            policy: RoomPolicy::default(),
+            moderators: HashSet::new(),
+            pinned: Vec::new(),
+            kind: RoomKind::Public,
+            topic: None,
+            slow_mode: None,
+            banned: HashSet::new(),
+        }
+    }
+
+    pub fn new_direct(id: Uuid, name: String, participants: Vec<Uuid>) -> Self {
+        Self {
+            kind: RoomKind::Direct { participants },
+            ..Self::new(id, name)
+        }
+    }
+
+    pub fn is_direct(&self) -> bool {
+        matches!(self.kind, RoomKind::Direct { .. })
+    }
+
+    /// Public rooms are open to everyone; direct conversations only to their
+    /// participants.
+    pub fn can_join(&self, user_key: &Uuid) -> bool {
+        match &self.kind {
+            RoomKind::Public => true,
+            RoomKind::Direct { participants } => participants.contains(user_key),
+        }
+    }
+
+    pub fn is_banned(&self, user_key: &Uuid) -> bool {
+        self.banned.contains(user_key)
+    }
+
+    /// Removes what still points at a redacted message: its pin, and the
+    /// quoted text in replies to it.
+    pub fn forget_redacted(&mut self, message_id: Uuid) {
+        self.pinned.retain(|id| *id != message_id);
+
+        for event in &mut self.events {
+            if let Some(reply) = event
+                .data
+                .reply_to_mut()
+                .filter(|reply| reply.message_id == message_id)
+            {
+                reply.content_preview = String::new();
+                reply.message_type = ReplyMessageType::Deleted;
+            }
+        }
+    }
+
+    pub fn is_moderator(&self, user_key: &Uuid) -> bool {
+        self.moderators.contains(user_key)
+    }
+
+    pub fn can_pin(&self, user_id: &Sid, user_key: &Uuid) -> bool {
+        match self.policy.pin_permission {
+            PinPermission::Members => self.members.contains(user_id),
+            PinPermission::Moderators => self.is_moderator(user_key),
+        }
+    }
+}
+
+/// Limits every member except moderators to one message per interval.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SlowMode {
+    pub interval_secs: u32,
+    /// Slow mode turns itself off at this time. Without it, slow mode stays
+    /// on until a moderator turns it off.
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl SlowMode {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.until.is_none_or(|until| now < until)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS, JsonSchema)]
+#[ts(export)]
+pub struct RoomPolicy {
+    /// How long after sending a message its author may still edit it.
+    #[serde(default)]
+    pub edit_window_secs: Option<u32>,
+    /// How long after sending a message its author may still delete it.
+    #[serde(default)]
+    pub delete_window_secs: Option<u32>,
+    /// Events older than this are pruned by the retention task.
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Only the newest `max_events` events are kept by the retention task.
+    #[serde(default)]
+    pub max_events: Option<u32>,
+    /// Default lifetime of new messages, after which their content is removed.
+    #[serde(default)]
+    pub message_ttl_secs: Option<u32>,
+    #[serde(default)]
+    pub pin_permission: PinPermission,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum PinPermission {
+    #[default]
+    Members,
+    Moderators,
+}
+
+impl RoomPolicy {
+    pub fn can_edit(&self, sent_at: DateTime<Utc>) -> bool {
+        within_window(self.edit_window_secs, sent_at)
+    }
+
+    pub fn can_delete(&self, sent_at: DateTime<Utc>) -> bool {
+        within_window(self.delete_window_secs, sent_at)
+    }
+
+    pub fn retention_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.retention_days
+            .map(|days| now - Duration::days(i64::from(days)))
+    }
+}
+
+fn within_window(window_secs: Option<u32>, sent_at: DateTime<Utc>) -> bool {
+    match window_secs {
+        Some(secs) => Utc::now() - sent_at <= Duration::seconds(i64::from(secs)),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        FormattedBody, LinkPreview, MessageFormat, MessageReply, RoomEventData, TextMessageEvent,
+    };
+
+    fn text(content: &str, reply_to: Option<MessageReply>) -> RoomEvent {
+        RoomEvent {
+            id: Uuid::new_v4(),
+            from: Sid::new(),
+            timestamp: Utc::now(),
+            data: RoomEventData::Message(TextMessageEvent {
+                content: content.to_string(),
+                edited: false,
+                deleted: false,
+                reply_to,
+                expires_at: None,
+                mentions: vec![Sid::new()],
+                mentions_room: true,
+                formatted: Some(FormattedBody {
+                    format: MessageFormat::Markdown,
+                    body: format!("**{content}**"),
+                }),
+                link_previews: vec![LinkPreview {
+                    url: "https://example.com".to_string(),
+                    title: Some(content.to_string()),
+                    description: None,
+                    image_url: None,
+                    site_name: None,
+                }],
+                emote: true,
+            }),
+            sender_key: None,
+        }
+    }
+
+    #[test]
+    fn redacting_clears_everything_derived_from_the_message() {
+        let mut room = Room::new(Uuid::new_v4(), "room".to_string());
+        let original = text("secret https://example.com", None);
+        let reply = text(
+            "reply",
+            Some(MessageReply {
+                message_id: original.id,
+                user_id: original.from,
+                username: None,
+                content_preview: "secret".to_string(),
+                message_type: ReplyMessageType::Text,
+            }),
+        );
+        room.events = vec![original.clone(), reply];
+        room.pinned = vec![original.id];
+
+        assert!(room.events[0].data.redact());
+        room.forget_redacted(original.id);
+
+        let RoomEventData::Message(redacted) = &room.events[0].data else {
+            unreachable!();
+        };
+        assert!(redacted.deleted);
+        assert!(redacted.content.is_empty());
+        assert!(redacted.formatted.is_none());
+        assert!(redacted.link_previews.is_empty());
+        assert!(redacted.mentions.is_empty());
+        assert!(!redacted.mentions_room);
+        assert!(!redacted.emote);
+
+        assert!(room.pinned.is_empty());
+
+        let reply = room.events[1].data.reply_to_mut().unwrap();
+        assert!(reply.content_preview.is_empty());
+        assert!(matches!(reply.message_type, ReplyMessageType::Deleted));
+        assert!(
+            !serde_json::to_string(&room.events)
+                .unwrap()
+                .contains("secret")
+        );
+    }
 }
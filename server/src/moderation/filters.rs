@@ -0,0 +1,347 @@
+//! The built-in [`MessageFilter`]s.
+
+use std::{collections::HashSet, ops::Range};
+
+use regex::{Captures, Regex, RegexBuilder};
+use reqwest::Url;
+
+use super::MessageFilter;
+
+const MAX_BLOCKED_WORDS: usize = 500;
+const MAX_WORD_CHARS: usize = 64;
+const MAX_PATTERN_CHARS: usize = 500;
+/// Compiled size limit for patterns, which keeps pathological ones cheap.
+const MAX_PATTERN_BYTES: usize = 1 << 20;
+const MAX_ALLOWED_DOMAINS: usize = 50;
+const MAX_REPEATS: u32 = 1_000;
+
+pub struct Blocklist {
+    words: HashSet<String>,
+}
+
+impl Blocklist {
+    pub fn new(words: &[String]) -> Result<Self, String> {
+        if words.len() > MAX_BLOCKED_WORDS {
+            return Err(format!(
+                "Blocklists are limited to {MAX_BLOCKED_WORDS} words"
+            ));
+        }
+
+        let words = words
+            .iter()
+            .map(|word| {
+                let word = word.trim().to_lowercase();
+                if word.is_empty() || !word.chars().all(char::is_alphanumeric) {
+                    return Err(format!("Blocked words must be single words, not {word:?}"));
+                }
+                if word.chars().count() > MAX_WORD_CHARS {
+                    return Err(format!(
+                        "Blocked words are limited to {MAX_WORD_CHARS} characters"
+                    ));
+                }
+                Ok(word)
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        if words.is_empty() {
+            return Err("Blocklists need at least one word".to_string());
+        }
+
+        Ok(Self { words })
+    }
+
+    fn blocked(&self, text: &str) -> Vec<Range<usize>> {
+        spans(text, char::is_alphanumeric)
+            .into_iter()
+            .filter(|span| self.words.contains(&text[span.clone()].to_lowercase()))
+            .collect()
+    }
+}
+
+impl MessageFilter for Blocklist {
+    fn check(&self, content: &str) -> Option<String> {
+        (!self.blocked(content).is_empty()).then(|| "contains a blocked word".to_string())
+    }
+
+    fn mask(&self, content: &str) -> String {
+        replace_spans(content, &self.blocked(content), stars)
+    }
+}
+
+pub struct PatternFilter {
+    regex: Regex,
+}
+
+impl PatternFilter {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        if pattern.chars().count() > MAX_PATTERN_CHARS {
+            return Err(format!(
+                "Patterns are limited to {MAX_PATTERN_CHARS} characters"
+            ));
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .size_limit(MAX_PATTERN_BYTES)
+            .build()
+            .map_err(|e| format!("Invalid pattern: {e}"))?;
+
+        // Such a pattern would match, and mask nothing in, every message.
+        if regex.is_match("") {
+            return Err("Patterns must not match empty text".to_string());
+        }
+
+        Ok(Self { regex })
+    }
+}
+
+impl MessageFilter for PatternFilter {
+    fn check(&self, content: &str) -> Option<String> {
+        self.regex
+            .is_match(content)
+            .then(|| "matches a blocked pattern".to_string())
+    }
+
+    fn mask(&self, content: &str) -> String {
+        self.regex
+            .replace_all(content, |caps: &Captures| stars(&caps[0]))
+            .into_owned()
+    }
+}
+
+/// Refuses http(s) links to hosts outside the list. An empty list refuses
+/// every link.
+pub struct LinkAllowlist {
+    domains: Vec<String>,
+}
+
+impl LinkAllowlist {
+    pub fn new(domains: &[String]) -> Result<Self, String> {
+        if domains.len() > MAX_ALLOWED_DOMAINS {
+            return Err(format!(
+                "Link allowlists are limited to {MAX_ALLOWED_DOMAINS} domains"
+            ));
+        }
+
+        let domains = domains
+            .iter()
+            .map(|domain| {
+                let domain = domain.trim().trim_matches('.').to_lowercase();
+                if domain.is_empty()
+                    || !domain
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '.'))
+                {
+                    return Err(format!("{domain:?} is not a domain name"));
+                }
+                Ok(domain)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { domains })
+    }
+
+    fn allows(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.');
+        self.domains.iter().any(|domain| {
+            host == domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|subdomain| subdomain.ends_with('.'))
+        })
+    }
+
+    /// The links to hosts that are not allowed, with their hosts.
+    fn disallowed(&self, text: &str) -> Vec<(Range<usize>, String)> {
+        let mut links = Vec::new();
+
+        for span in spans(text, |c| !c.is_whitespace()) {
+            let word = &text[span.clone()];
+            let Some(start) = word.find("http://").or_else(|| word.find("https://")) else {
+                continue;
+            };
+
+            let candidate = word[start..]
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"', '>', ']']);
+            let Some(host) = Url::parse(candidate)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+            else {
+                continue;
+            };
+
+            if !self.allows(&host) {
+                let start = span.start + start;
+                links.push((start..start + candidate.len(), host));
+            }
+        }
+
+        links
+    }
+}
+
+impl MessageFilter for LinkAllowlist {
+    fn check(&self, content: &str) -> Option<String> {
+        self.disallowed(content)
+            .into_iter()
+            .next()
+            .map(|(_, host)| format!("links to {host}, which is not allowed here"))
+    }
+
+    fn mask(&self, content: &str) -> String {
+        let links: Vec<_> = self
+            .disallowed(content)
+            .into_iter()
+            .map(|(span, _)| span)
+            .collect();
+        replace_spans(content, &links, |_| "[link removed]".to_string())
+    }
+}
+
+/// Catches shouting. Masking lowercases the message.
+pub struct CapsFilter {
+    max_percent: usize,
+    min_letters: usize,
+}
+
+impl CapsFilter {
+    pub fn new(max_percent: u8, min_letters: u32) -> Result<Self, String> {
+        if max_percent > 100 {
+            return Err("Capital letter limits are a percentage up to 100".to_string());
+        }
+        if min_letters == 0 {
+            return Err("Capital letter limits need at least 1 letter to apply".to_string());
+        }
+
+        Ok(Self {
+            max_percent: usize::from(max_percent),
+            min_letters: min_letters as usize,
+        })
+    }
+}
+
+impl MessageFilter for CapsFilter {
+    fn check(&self, content: &str) -> Option<String> {
+        let letters = content.chars().filter(|c| c.is_alphabetic()).count();
+        let capitals = content.chars().filter(|c| c.is_uppercase()).count();
+
+        (letters >= self.min_letters && capitals * 100 > self.max_percent * letters)
+            .then(|| "has too many capital letters".to_string())
+    }
+
+    fn mask(&self, content: &str) -> String {
+        content.to_lowercase()
+    }
+}
+
+/// Catches runs such as `!!!!!!!!` or `spam spam spam spam`. Masking cuts
+/// each run down to the limit.
+pub struct RepeatFilter {
+    max_repeats: usize,
+}
+
+impl RepeatFilter {
+    pub fn new(max_repeats: u32) -> Result<Self, String> {
+        if !(2..=MAX_REPEATS).contains(&max_repeats) {
+            return Err(format!("Repeat limits must be between 2 and {MAX_REPEATS}"));
+        }
+
+        Ok(Self {
+            max_repeats: max_repeats as usize,
+        })
+    }
+
+    /// Each word past the limit, with the whitespace before it.
+    fn repeated_words(&self, text: &str) -> Vec<Range<usize>> {
+        let words = spans(text, |c| !c.is_whitespace());
+        let mut excess = Vec::new();
+        let mut run = 1;
+
+        for pair in words.windows(2) {
+            if text[pair[0].clone()].to_lowercase() == text[pair[1].clone()].to_lowercase() {
+                run += 1;
+                if run > self.max_repeats {
+                    excess.push(pair[0].end..pair[1].end);
+                }
+            } else {
+                run = 1;
+            }
+        }
+
+        excess
+    }
+
+    /// Each character past the limit. Whitespace is left alone.
+    fn repeated_chars(&self, text: &str) -> Vec<Range<usize>> {
+        let mut excess = Vec::new();
+        let mut previous = None;
+        let mut run = 0;
+
+        for (i, c) in text.char_indices() {
+            if previous == Some(c) && !c.is_whitespace() {
+                run += 1;
+                if run > self.max_repeats {
+                    excess.push(i..i + c.len_utf8());
+                }
+            } else {
+                previous = Some(c);
+                run = 1;
+            }
+        }
+
+        excess
+    }
+}
+
+impl MessageFilter for RepeatFilter {
+    fn check(&self, content: &str) -> Option<String> {
+        (!self.repeated_words(content).is_empty() || !self.repeated_chars(content).is_empty())
+            .then(|| "repeats the same text too many times".to_string())
+    }
+
+    fn mask(&self, content: &str) -> String {
+        let content = replace_spans(content, &self.repeated_words(content), |_| String::new());
+        replace_spans(&content, &self.repeated_chars(&content), |_| String::new())
+    }
+}
+
+/// Byte ranges of the runs of characters in `text` that satisfy `keep`.
+fn spans(text: &str, keep: impl Fn(char) -> bool) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        match (keep(c), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                spans.push(from..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        spans.push(from..text.len());
+    }
+
+    spans
+}
+
+/// Replaces the given ordered, non-overlapping byte ranges of `text`.
+fn replace_spans(text: &str, spans: &[Range<usize>], with: impl Fn(&str) -> String) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for span in spans {
+        replaced.push_str(&text[last..span.start]);
+        replaced.push_str(&with(&text[span.clone()]));
+        last = span.end;
+    }
+    replaced.push_str(&text[last..]);
+
+    replaced
+}
+
+fn stars(text: &str) -> String {
+    "*".repeat(text.chars().count())
+}
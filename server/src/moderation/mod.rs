@@ -0,0 +1,242 @@
+//! Filters that screen messages before they are stored, configured per room.
+//!
+//! A room lists [`FilterRule`]s in its [`ModerationConfig`]. Each rule pairs a
+//! [`MessageFilter`] with what to do when it matches: reject the message, mask
+//! the matching text, or deliver it and flag it for the room's moderators.
+//! Rules run in order, so a masking rule can clean up text before a later rule
+//! looks at it. Configurations are compiled into a [`Pipeline`] once, when a
+//! moderator sets them.
+//!
+//! Members can also report messages themselves; those reports wait in the
+//! room's [`ReportQueue`] until a moderator acts on them.
+
+mod filters;
+mod reports;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::socket::Sid;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use filters::{Blocklist, CapsFilter, LinkAllowlist, PatternFilter, RepeatFilter};
+
+pub use reports::{
+    MAX_REASON_CHARS, ModerationLogEntry, ModeratorAction, Report, ReportQueue, Resolution,
+};
+
+pub const MAX_RULES_PER_ROOM: usize = 20;
+/// Flagged messages kept per room for moderators to review.
+const MAX_FLAGGED_PER_ROOM: usize = 200;
+
+/// Inspects the text of a message.
+pub trait MessageFilter: Send + Sync {
+    /// Describes why the message matched, or returns `None` if it passes.
+    fn check(&self, content: &str) -> Option<String>;
+
+    /// Returns the message with the matching text hidden.
+    fn mask(&self, content: &str) -> String;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS, JsonSchema)]
+#[ts(export)]
+pub struct ModerationConfig {
+    #[serde(default)]
+    pub rules: Vec<FilterRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct FilterRule {
+    pub filter: FilterKind,
+    pub action: FilterAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub enum FilterKind {
+    /// Single words, compared case-insensitively.
+    Blocklist { words: Vec<String> },
+    /// A case-insensitive regular expression.
+    Pattern { pattern: String },
+    /// Only allows links to these domains and their subdomains.
+    LinkAllowlist { domains: Vec<String> },
+    /// Messages with at least `min_letters` letters of which more than
+    /// `max_percent` are capitals.
+    Caps { max_percent: u8, min_letters: u32 },
+    /// The same character or word more than `max_repeats` times in a row.
+    Repeat { max_repeats: u32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum FilterAction {
+    /// Refuse the message and tell the sender why.
+    Reject,
+    /// Deliver the message with the matching text hidden.
+    Mask,
+    /// Deliver the message unchanged and flag it for moderators.
+    Flag,
+}
+
+/// A delivered message that matched a `Flag` rule.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct FlaggedMessage {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+    pub username: Option<String>,
+    /// The message as it read when it was flagged.
+    pub content: String,
+    pub reasons: Vec<String>,
+    pub flagged_at: DateTime<Utc>,
+}
+
+/// What happened to a message that was let through.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub masked: bool,
+    /// Why the message was flagged, one entry per matching rule.
+    pub flags: Vec<String>,
+}
+
+struct Rule {
+    filter: Box<dyn MessageFilter>,
+    action: FilterAction,
+}
+
+pub struct Pipeline {
+    rules: Vec<Rule>,
+}
+
+impl Pipeline {
+    /// Builds the filters of a configuration, refusing invalid ones.
+    pub fn compile(config: &ModerationConfig) -> Result<Self, String> {
+        if config.rules.len() > MAX_RULES_PER_ROOM {
+            return Err(format!(
+                "Rooms are limited to {MAX_RULES_PER_ROOM} filter rules"
+            ));
+        }
+
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let filter: Box<dyn MessageFilter> = match &rule.filter {
+                    FilterKind::Blocklist { words } => Box::new(Blocklist::new(words)?),
+                    FilterKind::Pattern { pattern } => Box::new(PatternFilter::new(pattern)?),
+                    FilterKind::LinkAllowlist { domains } => Box::new(LinkAllowlist::new(domains)?),
+                    FilterKind::Caps {
+                        max_percent,
+                        min_letters,
+                    } => Box::new(CapsFilter::new(*max_percent, *min_letters)?),
+                    FilterKind::Repeat { max_repeats } => {
+                        Box::new(RepeatFilter::new(*max_repeats)?)
+                    }
+                };
+                Ok(Rule {
+                    filter,
+                    action: rule.action,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Runs every rule over `content`, masking it in place. Returns the reason
+    /// if a rule rejects the message.
+    pub fn run(&self, content: &mut String) -> Result<Outcome, String> {
+        let mut outcome = Outcome::default();
+
+        for rule in &self.rules {
+            let Some(reason) = rule.filter.check(content) else {
+                continue;
+            };
+
+            match rule.action {
+                FilterAction::Reject => return Err(reason),
+                FilterAction::Mask => {
+                    *content = rule.filter.mask(content);
+                    outcome.masked = true;
+                }
+                FilterAction::Flag => outcome.flags.push(reason),
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+struct RoomFilters {
+    config: ModerationConfig,
+    pipeline: Arc<Pipeline>,
+}
+
+#[derive(Default)]
+pub struct ModerationService {
+    filters: DashMap<Uuid, RoomFilters>,
+    flagged: DashMap<Uuid, VecDeque<FlaggedMessage>>,
+}
+
+impl ModerationService {
+    pub fn config(&self, room_id: Uuid) -> ModerationConfig {
+        self.filters
+            .get(&room_id)
+            .map(|filters| filters.config.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the rules of a room. An empty configuration turns filtering
+    /// off.
+    pub fn set_config(&self, room_id: Uuid, config: ModerationConfig) -> Result<(), String> {
+        if config.rules.is_empty() {
+            self.filters.remove(&room_id);
+            return Ok(());
+        }
+
+        let pipeline = Arc::new(Pipeline::compile(&config)?);
+        self.filters
+            .insert(room_id, RoomFilters { config, pipeline });
+        Ok(())
+    }
+
+    /// Runs the room's rules over a message, if it has any.
+    pub fn screen(&self, room_id: Uuid, content: &mut String) -> Result<Outcome, String> {
+        // Release the map entry before running filters that may take a while.
+        let Some(pipeline) = self
+            .filters
+            .get(&room_id)
+            .map(|filters| filters.pipeline.clone())
+        else {
+            return Ok(Outcome::default());
+        };
+
+        pipeline.run(content)
+    }
+
+    pub fn flag(&self, message: FlaggedMessage) {
+        let mut flagged = self.flagged.entry(message.room_id).or_default();
+        flagged.push_back(message);
+        while flagged.len() > MAX_FLAGGED_PER_ROOM {
+            flagged.pop_front();
+        }
+    }
+
+    /// Flagged messages of a room, oldest first.
+    pub fn flagged(&self, room_id: Uuid) -> Vec<FlaggedMessage> {
+        self.flagged
+            .get(&room_id)
+            .map(|flagged| flagged.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,225 @@
+//! Reports members file against messages, and the log of what moderators did
+//! about them.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::socket::Sid;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::models::RoomEvent;
+
+pub const MAX_REASON_CHARS: usize = 500;
+/// Reports kept per room. Closed reports are dropped first to make room.
+const MAX_REPORTS_PER_ROOM: usize = 500;
+/// Log entries kept per room.
+const MAX_LOG_ENTRIES: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum ModeratorAction {
+    /// Close the report without acting on it.
+    Dismiss,
+    DeleteMessage,
+    /// Send the author a warning.
+    Warn,
+    /// Remove the author from the room and keep them out.
+    Ban,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct Report {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub reported_user: Sid,
+    pub reported_username: Option<String>,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub reporter: Sid,
+    pub reporter_username: Option<String>,
+    pub reason: String,
+    /// The message as it was when it was reported.
+    pub message: RoomEvent,
+    pub created_at: DateTime<Utc>,
+    /// Set once a moderator has dealt with the report.
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+    /// Identifies the author across reconnects, for bans. Unknown if they had
+    /// already disconnected when the report was filed. Never sent to clients.
+    #[serde(skip)]
+    #[ts(skip)]
+    pub reported_user_key: Option<Uuid>,
+    #[serde(skip)]
+    #[ts(skip)]
+    pub reporter_key: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct Resolution {
+    pub action: ModeratorAction,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub moderator: Sid,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub closed_at: DateTime<Utc>,
+}
+
+/// One moderator action, kept for later review.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ModerationLogEntry {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub report_id: Uuid,
+    pub message_id: Uuid,
+    pub action: ModeratorAction,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub moderator: Sid,
+    pub moderator_username: Option<String>,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub target: Sid,
+    pub target_username: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReportError {
+    AlreadyReported,
+    NotFound,
+    AlreadyClosed,
+    QueueFull,
+}
+
+impl std::fmt::Display for ReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReportError::AlreadyReported => write!(f, "You have already reported this message"),
+            ReportError::NotFound => write!(f, "Report does not exist"),
+            ReportError::AlreadyClosed => write!(f, "Report has already been closed"),
+            ReportError::QueueFull => write!(
+                f,
+                "This room has too many open reports. Try again once moderators have caught up"
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ReportQueue {
+    /// Reports keyed by room id, oldest first.
+    reports: DashMap<Uuid, Vec<Report>>,
+    log: DashMap<Uuid, VecDeque<ModerationLogEntry>>,
+}
+
+impl ReportQueue {
+    /// Adds a report to its room's queue. Each user can have one open report
+    /// per message.
+    pub fn file(&self, report: Report) -> Result<(), ReportError> {
+        let mut reports = self.reports.entry(report.room_id).or_default();
+
+        if reports.iter().any(|existing| {
+            existing.resolution.is_none()
+                && existing.message_id == report.message_id
+                && existing.reporter_key == report.reporter_key
+        }) {
+            return Err(ReportError::AlreadyReported);
+        }
+
+        if reports.len() >= MAX_REPORTS_PER_ROOM {
+            let Some(oldest_closed) = reports
+                .iter()
+                .position(|existing| existing.resolution.is_some())
+            else {
+                return Err(ReportError::QueueFull);
+            };
+            reports.remove(oldest_closed);
+        }
+
+        reports.push(report);
+        Ok(())
+    }
+
+    pub fn get(&self, room_id: Uuid, report_id: Uuid) -> Option<Report> {
+        self.reports
+            .get(&room_id)?
+            .iter()
+            .find(|report| report.id == report_id)
+            .cloned()
+    }
+
+    /// The reports of a room, oldest first.
+    pub fn for_room(&self, room_id: Uuid, include_closed: bool) -> Vec<Report> {
+        self.reports
+            .get(&room_id)
+            .map(|reports| {
+                reports
+                    .iter()
+                    .filter(|report| include_closed || report.resolution.is_none())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Closes a report along with every other open report about the same
+    /// message, and returns them all.
+    pub fn close(
+        &self,
+        room_id: Uuid,
+        report_id: Uuid,
+        resolution: Resolution,
+    ) -> Result<Vec<Report>, ReportError> {
+        let mut reports = self
+            .reports
+            .get_mut(&room_id)
+            .ok_or(ReportError::NotFound)?;
+
+        let report = reports
+            .iter()
+            .find(|report| report.id == report_id)
+            .ok_or(ReportError::NotFound)?;
+        if report.resolution.is_some() {
+            return Err(ReportError::AlreadyClosed);
+        }
+        let message_id = report.message_id;
+
+        Ok(reports
+            .iter_mut()
+            .filter(|report| report.message_id == message_id && report.resolution.is_none())
+            .map(|report| {
+                report.resolution = Some(resolution.clone());
+                report.clone()
+            })
+            .collect())
+    }
+
+    pub fn log(&self, entry: ModerationLogEntry) {
+        let mut log = self.log.entry(entry.room_id).or_default();
+        log.push_back(entry);
+        while log.len() > MAX_LOG_ENTRIES {
+            log.pop_front();
+        }
+    }
+
+    /// The moderation log of a room, oldest first.
+    pub fn log_for(&self, room_id: Uuid) -> Vec<ModerationLogEntry> {
+        self.log
+            .get(&room_id)
+            .map(|log| log.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,338 @@
+//! Token buckets limiting how often each user may perform actions that are
+//! cheap to send but expensive to fan out, plus the per-room slow mode.
+//!
+//! Every user gets one bucket per [`Action`], keyed by their user key so that
+//! reconnecting does not reset it. Since anyone may mint a new user key by
+//! connecting again, the address a request came from gets a bucket of its own
+//! as well, [`USERS_PER_ADDRESS`] times the size to allow for users sharing
+//! one. A limit of `burst/seconds` allows `burst`
+//! requests at once and refills the bucket completely over `seconds`. Limits
+//! are read from `RATE_LIMIT_ROOM_SEND`, `RATE_LIMIT_TYPING_START`,
+//! `RATE_LIMIT_ROOM_CREATE`, `RATE_LIMIT_USERNAME_CHANGE` and
+//! `RATE_LIMIT_MEDIA_UPLOAD` in that format.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use axum::{extract::ConnectInfo, http::Extensions};
+use dashmap::DashMap;
+use tracing::warn;
+use uuid::Uuid;
+
+/// The longest interval slow mode may be set to.
+pub const MAX_SLOW_MODE_SECS: u32 = 60 * 60;
+
+/// How many users' worth of requests one address may make, since users behind
+/// the same NAT share it.
+pub const USERS_PER_ADDRESS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    RoomSend,
+    TypingStart,
+    RoomCreate,
+    UsernameChange,
+    MediaUpload,
+}
+
+impl Action {
+    const ALL: [Action; 5] = [
+        Action::RoomSend,
+        Action::TypingStart,
+        Action::RoomCreate,
+        Action::UsernameChange,
+        Action::MediaUpload,
+    ];
+
+    /// The socket event the action is performed with.
+    pub fn event(self) -> &'static str {
+        match self {
+            Action::RoomSend => "room.send",
+            Action::TypingStart => "typing.start",
+            Action::RoomCreate => "room.create",
+            Action::UsernameChange => "user.set_username",
+            Action::MediaUpload => "media.upload",
+        }
+    }
+
+    fn env_var(self) -> &'static str {
+        match self {
+            Action::RoomSend => "RATE_LIMIT_ROOM_SEND",
+            Action::TypingStart => "RATE_LIMIT_TYPING_START",
+            Action::RoomCreate => "RATE_LIMIT_ROOM_CREATE",
+            Action::UsernameChange => "RATE_LIMIT_USERNAME_CHANGE",
+            Action::MediaUpload => "RATE_LIMIT_MEDIA_UPLOAD",
+        }
+    }
+
+    fn default_limit(self) -> Limit {
+        match self {
+            Action::RoomSend => Limit::new(10, 10),
+            Action::TypingStart => Limit::new(10, 10),
+            Action::RoomCreate => Limit::new(3, 60),
+            Action::UsernameChange => Limit::new(3, 10 * 60),
+            Action::MediaUpload => Limit::new(5, 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    pub burst: u32,
+    pub per: Duration,
+}
+
+impl Limit {
+    pub fn new(burst: u32, per_secs: u64) -> Self {
+        Self {
+            burst,
+            per: Duration::from_secs(per_secs),
+        }
+    }
+
+    /// Parses `<burst>/<seconds>`, such as `10/10`.
+    fn parse(value: &str) -> Option<Self> {
+        let (burst, secs) = value.split_once('/')?;
+        let burst = burst.trim().parse().ok().filter(|burst| *burst > 0)?;
+        let secs = secs.trim().parse().ok().filter(|secs| *secs > 0)?;
+        Some(Self::new(burst, secs))
+    }
+
+    /// Tokens regained per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.burst) / self.per.as_secs_f64()
+    }
+
+    /// The limit for an address shared by `users` users.
+    fn shared(self, users: u32) -> Self {
+        Self {
+            burst: self.burst.saturating_mul(users),
+            per: self.per,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimited {
+    pub action: Action,
+    pub retry_after: Duration,
+    /// Refused by the room's slow mode rather than the user's own limit.
+    pub slow_mode: bool,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.retry_after.as_secs_f64().ceil().max(1.0);
+        if self.slow_mode {
+            write!(
+                f,
+                "This room is in slow mode. You can send another message in {secs}s"
+            )
+        } else {
+            write!(
+                f,
+                "You are doing {} too often. Try again in {secs}s",
+                self.action.event()
+            )
+        }
+    }
+}
+
+pub struct RateLimiter {
+    limits: HashMap<Action, Limit>,
+    buckets: DashMap<(Uuid, Action), Bucket>,
+    address_buckets: DashMap<(IpAddr, Action), Bucket>,
+    /// When each user last posted to a room in slow mode, keyed by room id
+    /// and user key.
+    last_posts: DashMap<(Uuid, Uuid), Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<Action, Limit>) -> Self {
+        Self {
+            limits,
+            buckets: DashMap::new(),
+            address_buckets: DashMap::new(),
+            last_posts: DashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let limits = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let limit = match std::env::var(action.env_var()) {
+                    Ok(value) => Limit::parse(&value).unwrap_or_else(|| {
+                        warn!(
+                            "invalid {}={:?}, expected <burst>/<seconds>",
+                            action.env_var(),
+                            value
+                        );
+                        action.default_limit()
+                    }),
+                    Err(_) => action.default_limit(),
+                };
+                (action, limit)
+            })
+            .collect();
+
+        Self::new(limits)
+    }
+
+    /// Takes a token from `user`'s bucket for `action` and, when the address
+    /// the request came from is known, from that address's bucket.
+    pub fn check(
+        &self,
+        user: Uuid,
+        address: Option<IpAddr>,
+        action: Action,
+    ) -> Result<(), RateLimited> {
+        let limit = self.limit(action);
+        take(&self.buckets, (user, action), limit)?;
+        if let Some(address) = address {
+            take(
+                &self.address_buckets,
+                (address, action),
+                limit.shared(USERS_PER_ADDRESS),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn limit(&self, action: Action) -> Limit {
+        self.limits
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_limit())
+    }
+
+    /// Refuses `user` if they posted to a room in slow mode less than
+    /// `interval` ago. The post is only counted once [`Self::record_post`] is
+    /// called, so that a message refused for another reason does not use up
+    /// the user's turn.
+    pub fn check_slow_mode(
+        &self,
+        room_id: Uuid,
+        user: Uuid,
+        interval: Duration,
+    ) -> Result<(), RateLimited> {
+        let Some(last_post) = self.last_posts.get(&(room_id, user)) else {
+            return Ok(());
+        };
+
+        let elapsed = last_post.elapsed();
+        if elapsed < interval {
+            return Err(RateLimited {
+                action: Action::RoomSend,
+                retry_after: interval - elapsed,
+                slow_mode: true,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Starts `user`'s wait before they may post to a room in slow mode again.
+    pub fn record_post(&self, room_id: Uuid, user: Uuid) {
+        self.last_posts.insert((room_id, user), Instant::now());
+    }
+
+    /// Forgets buckets that have refilled and posts too old to matter.
+    pub fn prune(&self) {
+        self.buckets.retain(|(_, action), bucket| {
+            self.limits
+                .get(action)
+                .is_some_and(|limit| bucket.updated.elapsed() < limit.per)
+        });
+        self.address_buckets.retain(|(_, action), bucket| {
+            self.limits
+                .get(action)
+                .is_some_and(|limit| bucket.updated.elapsed() < limit.per)
+        });
+        self.last_posts.retain(|_, last_post| {
+            last_post.elapsed() < Duration::from_secs(u64::from(MAX_SLOW_MODE_SECS))
+        });
+    }
+}
+
+/// The address a request came from, as recorded by the server's connect
+/// info.
+pub fn client_address(extensions: &Extensions) -> Option<IpAddr> {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip())
+}
+
+/// Takes a token from the bucket at `key`, refilling it for the time since it
+/// was last used.
+fn take<K: Eq + Hash>(
+    buckets: &DashMap<(K, Action), Bucket>,
+    key: (K, Action),
+    limit: Limit,
+) -> Result<(), RateLimited> {
+    let action = key.1;
+    let now = Instant::now();
+
+    let mut bucket = buckets.entry(key).or_insert(Bucket {
+        tokens: f64::from(limit.burst),
+        updated: now,
+    });
+
+    let refilled = now.duration_since(bucket.updated).as_secs_f64() * limit.rate();
+    bucket.tokens = (bucket.tokens + refilled).min(f64::from(limit.burst));
+    bucket.updated = now;
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        return Ok(());
+    }
+
+    Err(RateLimited {
+        action,
+        retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate()),
+        slow_mode: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_user_keys_share_their_address_limit() {
+        let limiter = RateLimiter::new(HashMap::from([(Action::RoomCreate, Limit::new(1, 60))]));
+        let address = IpAddr::from([203, 0, 113, 7]);
+
+        for _ in 0..USERS_PER_ADDRESS {
+            assert!(
+                limiter
+                    .check(Uuid::new_v4(), Some(address), Action::RoomCreate)
+                    .is_ok()
+            );
+        }
+
+        let limited = limiter
+            .check(Uuid::new_v4(), Some(address), Action::RoomCreate)
+            .unwrap_err();
+        assert_eq!(limited.action, Action::RoomCreate);
+        assert!(!limited.slow_mode);
+        assert!(
+            limiter
+                .check(
+                    Uuid::new_v4(),
+                    Some(IpAddr::from([203, 0, 113, 8])),
+                    Action::RoomCreate
+                )
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+
+use super::{ApiError, ApiUser};
+use crate::{
+    audit::AuditQuery,
+    socket::audit::{self, AuditLogResponse},
+    state::AppState,
+};
+
+pub async fn get_audit_log(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditLogResponse>, ApiError> {
+    Ok(Json(audit::query(&state, user.user_key, &query)?))
+}
@@ -0,0 +1,76 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use socketioxide::SocketIo;
+use tracing::info;
+
+use super::ApiError;
+use crate::{
+    models::{FormattedBody, RoomEvent, RoomEventData, TextMessageEvent},
+    rate_limit::Action,
+    socket::{
+        HandlerError,
+        send_event::{self, SendEventPayload},
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct IncomingMessage {
+    pub content: String,
+    #[serde(default)]
+    pub formatted: Option<FormattedBody>,
+}
+
+/// Posts a message as the integration the token belongs to. The token is the
+/// only credential, so it is checked before anything else.
+pub async fn post_message(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    Path(token): Path<String>,
+    Json(data): Json<IncomingMessage>,
+) -> Result<(StatusCode, Json<RoomEvent>), ApiError> {
+    let webhook = state
+        .incoming_webhooks
+        .by_token(&token)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Unknown webhook token"))?;
+
+    if !state.rooms.contains_key(&webhook.room_id) {
+        return Err(HandlerError::NotFound("Room does not exist").into());
+    }
+
+    state
+        .rate_limits
+        .check(webhook.id, None, Action::RoomSend)?;
+
+    let payload = SendEventPayload {
+        room: webhook.room_id,
+        payload: RoomEventData::Message(TextMessageEvent {
+            content: data.content,
+            edited: false,
+            deleted: false,
+            reply_to: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            mentions_room: false,
+            formatted: data.formatted,
+            link_previews: Vec::new(),
+            emote: false,
+        }),
+        ttl_secs: None,
+    };
+
+    let event = send_event::deliver(&io, &state, webhook.sid, payload)
+        .await
+        .map_err(HandlerError::Invalid)?;
+
+    info!(
+        "Incoming webhook {} ({}) posted to room {}",
+        webhook.id, webhook.name, webhook.room_id
+    );
+
+    Ok((StatusCode::CREATED, Json(event)))
+}
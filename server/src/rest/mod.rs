@@ -0,0 +1,218 @@
+//! HTTP API mirroring the socket protocol, for scripts and integrations that
+//! do not speak socket.io.
+//!
+//! Requests authenticate with `Authorization: Bearer <user_key>`, using the
+//! key a socket client receives in `session.info`; keys the server never
+//! handed out are refused. Each key acts through an id of its own, so REST
+//! clients join rooms and own messages just like a socket does. A key that
+//! makes no requests for [`SESSION_IDLE_TTL`] leaves its rooms, and its next
+//! request starts over with a new id.
+//!
+//! Requests over a rate limit are answered with `429 Too Many Requests` and a
+//! `Retry-After` header.
+//!
+//! `GET /audit` returns the audit log to admins, filtered by the same fields
+//! as the `audit.query` socket event, passed as query parameters.
+//!
+//! Incoming webhooks are the exception: `POST /hooks/{token}` is
+//! authenticated by the token alone and posts as the integration.
+
+mod audit;
+mod hooks;
+mod rooms;
+
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, Router,
+    extract::FromRequestParts,
+    http::{StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post},
+};
+use socketioxide::{SocketIo, socket::Sid};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    rate_limit::{self, RateLimited},
+    socket::{self, ErrorResponse, HandlerError, user_management},
+    state::AppState,
+};
+
+pub const SESSION_IDLE_TTL: Duration = Duration::from_secs(60 * 60);
+const MAX_SESSIONS: usize = 10_000;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/asyncapi.json",
+            get(|| async { Json(socket::schema::asyncapi_document()) }),
+        )
+        .route(
+            "/schemas",
+            get(|| async {
+                Json(
+                    socket::schema::payload_schemas()
+                        .into_iter()
+                        .collect::<BTreeMap<_, _>>(),
+                )
+            }),
+        )
+        .route("/rooms", get(rooms::list_rooms).post(rooms::create_room))
+        .route(
+            "/rooms/{room_id}/events",
+            get(rooms::get_history).post(rooms::post_event),
+        )
+        .route(
+            "/rooms/{room_id}/events/{message_id}",
+            patch(rooms::edit_event).delete(rooms::delete_event),
+        )
+        .route("/rooms/{room_id}/members", get(rooms::get_members))
+        .route("/rooms/{room_id}/join", post(rooms::join_room))
+        .route("/rooms/{room_id}/leave", post(rooms::leave_room))
+        .route("/audit", get(audit::get_audit_log))
+        .route("/hooks/{token}", post(hooks::post_message))
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    /// Sent as `Retry-After` with `429 Too Many Requests`.
+    retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+}
+
+impl From<HandlerError> for ApiError {
+    fn from(error: HandlerError) -> Self {
+        let status = match error {
+            HandlerError::NotFound(_) => StatusCode::NOT_FOUND,
+            HandlerError::Forbidden(_) => StatusCode::FORBIDDEN,
+            HandlerError::Invalid(_) => StatusCode::BAD_REQUEST,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl From<RateLimited> for ApiError {
+    fn from(limited: RateLimited) -> Self {
+        Self {
+            retry_after_secs: Some(limited.retry_after.as_secs_f64().ceil() as u64),
+            ..Self::new(StatusCode::TOO_MANY_REQUESTS, limited.to_string())
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(ErrorResponse {
+            message: self.message,
+        });
+
+        match self.retry_after_secs {
+            Some(secs) => (
+                self.status,
+                [(header::RETRY_AFTER, secs.max(1).to_string())],
+                body,
+            )
+                .into_response(),
+            None => (self.status, body).into_response(),
+        }
+    }
+}
+
+pub struct RestSession {
+    /// The id the user acts as, standing in for a socket id.
+    pub sid: Sid,
+    pub last_seen: Instant,
+}
+
+/// The caller of a REST request.
+pub struct ApiUser {
+    pub user_key: Uuid,
+    /// The id the user acts as, standing in for a socket id.
+    pub sid: Sid,
+    /// Where the request came from, if known.
+    pub address: Option<IpAddr>,
+}
+
+impl FromRequestParts<AppState> for ApiUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        let user_key = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| Uuid::parse_str(token.trim()).ok())
+            .filter(|user_key| state.issued_keys.contains(user_key))
+            .ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "Missing or invalid bearer user key",
+                )
+            })?;
+
+        if !state.rest_sessions.contains_key(&user_key) && state.rest_sessions.len() >= MAX_SESSIONS
+        {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many API sessions, try again later",
+            ));
+        }
+
+        let mut session = state.rest_sessions.entry(user_key).or_insert_with(|| {
+            let sid = Sid::new();
+            state.user_keys.insert(sid, user_key);
+            RestSession {
+                sid,
+                last_seen: Instant::now(),
+            }
+        });
+        session.last_seen = Instant::now();
+
+        Ok(ApiUser {
+            user_key,
+            sid: session.sid,
+            address: rate_limit::client_address(&parts.extensions),
+        })
+    }
+}
+
+/// Ends the REST sessions that have been idle for [`SESSION_IDLE_TTL`],
+/// taking them out of their rooms the way a disconnecting socket is.
+pub async fn expire_sessions(io: &SocketIo, state: &AppState) {
+    let mut expired = Vec::new();
+    state.rest_sessions.retain(|_, session| {
+        let idle = session.last_seen.elapsed() >= SESSION_IDLE_TTL;
+        if idle {
+            expired.push(session.sid);
+        }
+        !idle
+    });
+
+    for sid in &expired {
+        user_management::leave_all_rooms(io, state, *sid).await;
+        state.usernames.remove(sid);
+        state.user_keys.remove(sid);
+    }
+
+    if !expired.is_empty() {
+        info!("Expired {} idle REST sessions", expired.len());
+    }
+}
@@ -0,0 +1,224 @@
+use std::borrow::Cow;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use socketioxide::SocketIo;
+use uuid::Uuid;
+
+use super::{ApiError, ApiUser};
+use crate::{
+    models::{FormattedBody, RoomEvent, RoomEventData, RoomMembersResponse},
+    rate_limit::Action,
+    socket::{
+        HandlerError,
+        message_management::{self, DeleteMessagePayload, EditMessagePayload},
+        protocol,
+        room_list::{self, CreateRoomPayload, RoomListItem, RoomListResponse},
+        send_event::{self, SendEventPayload},
+        user_management,
+    },
+    state::AppState,
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct HistoryQuery {
+    /// Return events older than this one.
+    #[serde(default)]
+    pub before: Option<Uuid>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HistoryResponse {
+    /// Oldest first.
+    pub events: Vec<RoomEvent>,
+    /// Pass as `before` to fetch the previous page, if there is one.
+    pub next_before: Option<Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PostEventPayload {
+    pub payload: RoomEventData,
+    #[serde(default)]
+    pub ttl_secs: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EditEventPayload {
+    pub new_content: String,
+    #[serde(default)]
+    pub formatted: Option<FormattedBody>,
+}
+
+pub async fn list_rooms(State(state): State<AppState>) -> Json<RoomListResponse> {
+    Json(room_list::public_rooms(&state))
+}
+
+pub async fn create_room(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    user: ApiUser,
+    Json(data): Json<CreateRoomPayload>,
+) -> Result<(StatusCode, Json<RoomListItem>), ApiError> {
+    state
+        .rate_limits
+        .check(user.user_key, user.address, Action::RoomCreate)?;
+
+    let room = room_list::create(&io, &state, &data.name, user.sid).await?;
+
+    Ok((StatusCode::CREATED, Json(room)))
+}
+
+pub async fn get_history(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(room_id): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>, ApiError> {
+    user_management::ensure_can_join(&state, room_id, user.user_key)?;
+
+    let Some(room) = state.rooms.get(&room_id) else {
+        return Err(HandlerError::NotFound("Room does not exist").into());
+    };
+
+    let end = match query.before {
+        Some(before) => room
+            .events
+            .iter()
+            .position(|event| event.id == before)
+            .ok_or(HandlerError::NotFound("Event does not exist"))?,
+        None => room.events.len(),
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let start = end.saturating_sub(limit);
+
+    Ok(Json(HistoryResponse {
+        events: protocol::history(&state, &room.events[start..end], user.user_key)
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect(),
+        next_before: (start > 0).then(|| room.events[start].id),
+    }))
+}
+
+pub async fn post_event(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    user: ApiUser,
+    Path(room_id): Path<Uuid>,
+    Json(data): Json<PostEventPayload>,
+) -> Result<(StatusCode, Json<RoomEvent>), ApiError> {
+    send_event::ensure_member(&state, room_id, user.sid)?;
+    send_event::check_rate_limit(&state, room_id, user.user_key, user.address)?;
+
+    let payload = SendEventPayload {
+        room: room_id,
+        payload: data.payload,
+        ttl_secs: data.ttl_secs,
+    };
+
+    let event = send_event::deliver(&io, &state, user.sid, payload)
+        .await
+        .map_err(HandlerError::Invalid)?;
+
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+pub async fn edit_event(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    user: ApiUser,
+    Path((room_id, message_id)): Path<(Uuid, Uuid)>,
+    Json(data): Json<EditEventPayload>,
+) -> Result<StatusCode, ApiError> {
+    let payload = EditMessagePayload {
+        room: room_id,
+        message_id,
+        new_content: data.new_content,
+        formatted: data.formatted,
+    };
+
+    message_management::edit_message(&io, &state, user.sid, payload).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn delete_event(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    user: ApiUser,
+    Path((room_id, message_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    let payload = DeleteMessagePayload {
+        room: room_id,
+        message_id,
+    };
+
+    message_management::delete_message(&io, &state, user.sid, payload).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_members(
+    State(state): State<AppState>,
+    user: ApiUser,
+    Path(room_id): Path<Uuid>,
+) -> Result<Json<RoomMembersResponse>, ApiError> {
+    user_management::ensure_can_join(&state, room_id, user.user_key)?;
+
+    user_management::room_members(&state, room_id)
+        .map(Json)
+        .ok_or_else(|| HandlerError::NotFound("Room does not exist").into())
+}
+
+pub async fn join_room(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    user: ApiUser,
+    Path(room_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    user_management::ensure_can_join(&state, room_id, user.user_key)?;
+
+    let joined = state.rooms.get_mut(&room_id).is_some_and(|mut room| {
+        room.member_keys.insert(user.user_key);
+        room.members.insert(user.sid)
+    });
+
+    if joined {
+        user_management::handle_user_join_room(user.sid, io, room_id, state).await;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn leave_room(
+    State(state): State<AppState>,
+    Extension(io): Extension<SocketIo>,
+    user: ApiUser,
+    Path(room_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let left = {
+        let mut room = state
+            .rooms
+            .get_mut(&room_id)
+            .ok_or(HandlerError::NotFound("Room does not exist"))?;
+        user_management::remove_member(&state, &mut room, user.sid)
+    };
+
+    if left {
+        user_management::handle_user_leave_room(user.sid, io, room_id, state).await;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
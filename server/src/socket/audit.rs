@@ -0,0 +1,72 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::error;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditAction, AuditEntry, AuditQuery},
+    socket::{ErrorResponse, HandlerError, session},
+    state::AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct AuditLogResponse {
+    /// Newest first.
+    pub entries: Vec<AuditEntry>,
+}
+
+pub async fn query_audit_log(
+    s: SocketRef,
+    Data(data): Data<AuditQuery>,
+    State(state): State<AppState>,
+) {
+    let response = match query(&state, session::user_key(&state, s.id), &data) {
+        Ok(response) => response,
+        Err(e) => {
+            let _ = s.emit("error", &ErrorResponse::from(e));
+            return;
+        }
+    };
+
+    if let Err(e) = s.emit("audit.log", &response) {
+        error!("Failed to send audit log to {}: {}", s.id, e);
+    }
+}
+
+/// Runs an audit log query for `user_key`, who must be an admin.
+pub fn query(
+    state: &AppState,
+    user_key: Uuid,
+    query: &AuditQuery,
+) -> Result<AuditLogResponse, HandlerError> {
+    if !state.audit.is_admin(user_key) {
+        return Err(HandlerError::Forbidden(
+            "Only admins can read the audit log",
+        ));
+    }
+
+    Ok(AuditLogResponse {
+        entries: state.audit.query(query),
+    })
+}
+
+/// Renders a setting as an entry's `before` or `after`.
+pub fn describe(setting: &impl Serialize) -> Option<String> {
+    serde_json::to_string(setting).ok()
+}
+
+/// Starts an audit entry for an action taken by `actor`, under their current
+/// username.
+pub fn entry(state: &AppState, action: AuditAction, actor: Sid) -> AuditEntry {
+    AuditEntry::new(
+        action,
+        actor,
+        state.usernames.get(&actor).map(|name| name.clone()),
+    )
+}
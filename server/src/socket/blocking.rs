@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    models::RoomEventData,
+    socket::{emit_error, session},
+    state::AppState,
+};
+
+const MAX_BLOCKED_USERS: usize = 1_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct BlockUserPayload {
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+}
+
+/// Names the user either way `user.blocked_list` does: by connection while
+/// they are online, or by username, which also works while they are offline.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct UnblockUserPayload {
+    #[serde(default)]
+    #[ts(type = "String | null")]
+    #[schemars(with = "Option<String>")]
+    pub user_id: Option<Sid>,
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// A blocked user. Offline users who never picked a name have neither field
+/// set, and can be unblocked once they reconnect.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct BlockedUser {
+    /// One of the user's connections, or nothing while they are offline.
+    #[ts(type = "String | null")]
+    #[schemars(with = "Option<String>")]
+    pub user_id: Option<Sid>,
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct BlockedUsersResponse {
+    pub users: Vec<BlockedUser>,
+}
+
+/// Stops delivering the user's messages, typing indicators and mentions to
+/// the caller, and keeps them from opening direct conversations with them.
+pub async fn block_user(
+    s: SocketRef,
+    Data(data): Data<BlockUserPayload>,
+    State(state): State<AppState>,
+) {
+    let own_key = session::user_key(&state, s.id);
+    let Some(target) = state.user_keys.get(&data.user_id).map(|key| *key) else {
+        emit_error(&s, "User is not connected");
+        return;
+    };
+
+    if target == own_key {
+        emit_error(&s, "You cannot block yourself");
+        return;
+    }
+
+    {
+        let mut blocked = state.blocks.entry(own_key).or_default();
+        if blocked.len() >= MAX_BLOCKED_USERS && !blocked.contains(&target) {
+            emit_error(
+                &s,
+                &format!("You can block at most {MAX_BLOCKED_USERS} users"),
+            );
+            return;
+        }
+        blocked.insert(target);
+    }
+
+    info!("User {} blocked {}", s.id, data.user_id);
+
+    emit_blocked(&s, &state, own_key);
+}
+
+pub async fn unblock_user(
+    s: SocketRef,
+    Data(data): Data<UnblockUserPayload>,
+    State(state): State<AppState>,
+) {
+    let own_key = session::user_key(&state, s.id);
+
+    // User keys double as credentials, so clients name the user and the key
+    // is looked up here.
+    let target = match (&data.user_id, &data.username) {
+        (Some(user_id), _) => state.user_keys.get(user_id).map(|key| *key),
+        (None, Some(username)) => state.username_registry.owner_of(username),
+        (None, None) => None,
+    };
+    let Some(target) = target else {
+        emit_error(&s, "User does not exist");
+        return;
+    };
+
+    // Empty sets are dropped so broadcasts can skip the lookup entirely when
+    // nobody blocks anyone.
+    state.blocks.remove_if_mut(&own_key, |_, blocked| {
+        blocked.remove(&target);
+        blocked.is_empty()
+    });
+
+    info!(
+        "User {} unblocked {}",
+        s.id,
+        data.user_id
+            .map(|sid| sid.to_string())
+            .or(data.username)
+            .unwrap_or_default()
+    );
+
+    emit_blocked(&s, &state, own_key);
+}
+
+pub async fn get_blocked(s: SocketRef, State(state): State<AppState>) {
+    let own_key = session::user_key(&state, s.id);
+    emit_blocked(&s, &state, own_key);
+}
+
+pub fn has_blocked(state: &AppState, blocker: Uuid, blocked: Uuid) -> bool {
+    state
+        .blocks
+        .get(&blocker)
+        .is_some_and(|users| users.contains(&blocked))
+}
+
+/// Whether the event belongs to its sender's messages, which are hidden from
+/// users who blocked them. Membership and room changes are still delivered
+/// so everyone's view of the room stays consistent.
+pub fn hides(data: &RoomEventData) -> bool {
+    matches!(
+        data,
+        RoomEventData::Message(_)
+            | RoomEventData::Image(_)
+            | RoomEventData::Audio(_)
+            | RoomEventData::MessageEdit(_)
+            | RoomEventData::LinkPreview(_)
+            | RoomEventData::Poll(_)
+    )
+}
+
+/// The connected sockets of every user who has blocked `from`.
+pub fn blockers_of(state: &AppState, from: Sid) -> HashSet<Sid> {
+    match state.user_keys.get(&from).map(|key| *key) {
+        Some(from_key) => blockers_of_key(state, from_key),
+        None => HashSet::new(),
+    }
+}
+
+/// The connected sockets of every user who has blocked the user with
+/// `from_key`.
+pub fn blockers_of_key(state: &AppState, from_key: Uuid) -> HashSet<Sid> {
+    if state.blocks.is_empty() {
+        return HashSet::new();
+    }
+
+    let blockers: HashSet<Uuid> = state
+        .blocks
+        .iter()
+        .filter(|entry| entry.value().contains(&from_key))
+        .map(|entry| *entry.key())
+        .collect();
+    if blockers.is_empty() {
+        return HashSet::new();
+    }
+
+    state
+        .user_keys
+        .iter()
+        .filter(|entry| blockers.contains(entry.value()))
+        .map(|entry| *entry.key())
+        .collect()
+}
+
+fn emit_blocked(s: &SocketRef, state: &AppState, own_key: Uuid) {
+    let keys: Vec<Uuid> = state
+        .blocks
+        .get(&own_key)
+        .map(|blocked| blocked.iter().copied().collect())
+        .unwrap_or_default();
+
+    let users = keys
+        .into_iter()
+        .map(|user_key| {
+            let sockets = session::sockets_for(state, user_key);
+            BlockedUser {
+                user_id: sockets.first().copied(),
+                username: sockets
+                    .iter()
+                    .find_map(|sid| state.usernames.get(sid).map(|name| name.clone()))
+                    .or_else(|| state.username_registry.name_of(user_key)),
+            }
+        })
+        .collect();
+
+    if let Err(e) = s.emit("user.blocked_list", &BlockedUsersResponse { users }) {
+        error!("Failed to send blocked users to {}: {}", s.id, e);
+    }
+}
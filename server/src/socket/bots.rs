@@ -0,0 +1,242 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    bots::{BotCommand, BotInfo},
+    models::{RoomEventData, TextMessageEvent},
+    socket::{
+        ErrorResponse, audit,
+        commands::{self, CommandResponse},
+        emit_error,
+        send_event::{self, SendEventPayload},
+        session,
+    },
+    state::AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CreateBotPayload {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DeleteBotPayload {
+    pub bot_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct BotListResponse {
+    pub bots: Vec<BotInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct RegisterCommandsPayload {
+    /// Replaces any commands registered earlier on this connection.
+    pub commands: Vec<BotCommand>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CommandRespondPayload {
+    pub invocation_id: Uuid,
+    pub content: String,
+    /// Post the reply to the room as the bot instead of showing it only to
+    /// the user who ran the command.
+    #[serde(default)]
+    pub public: bool,
+}
+
+pub async fn create_bot(
+    s: SocketRef,
+    Data(data): Data<CreateBotPayload>,
+    State(state): State<AppState>,
+) {
+    if state.bots.is_bot(s.id) {
+        emit_error(&s, "Bots cannot create bots");
+        return;
+    }
+
+    let owner = session::user_key(&state, s.id);
+    let bot = match state.bots.create(owner, &data.name) {
+        Ok(bot) => bot,
+        Err(e) => {
+            emit_error(&s, &e.to_string());
+            return;
+        }
+    };
+
+    // Bots share the username namespace, so a bot cannot pose as a user or
+    // take a reserved name.
+    if let Err(e) = state.username_registry.claim(bot.id, &bot.name) {
+        let _ = state.bots.delete(owner, bot.id);
+        emit_error(&s, &e.to_string());
+        return;
+    }
+
+    state.audit.record(
+        audit::entry(&state, AuditAction::BotCreated, s.id)
+            .target(bot.id)
+            .change(None, Some(bot.name.clone())),
+    );
+
+    info!("User {} created bot {} ({})", s.id, bot.id, bot.name);
+
+    // The token is only ever shown here.
+    let response = BotInfo {
+        token: Some(bot.token.clone()),
+        ..state.bots.info(&bot)
+    };
+    if let Err(e) = s.emit("bot.created", &response) {
+        error!("Failed to confirm bot to {}: {}", s.id, e);
+    }
+}
+
+pub async fn list_bots(s: SocketRef, State(state): State<AppState>) {
+    emit_list(&s, &state);
+}
+
+pub async fn delete_bot(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<DeleteBotPayload>,
+    State(state): State<AppState>,
+) {
+    let owner = session::user_key(&state, s.id);
+    let sockets = match state.bots.delete(owner, data.bot_id) {
+        Ok(sockets) => sockets,
+        Err(e) => {
+            emit_error(&s, &e.to_string());
+            return;
+        }
+    };
+
+    let name = state.username_registry.name_of(data.bot_id);
+    state.username_registry.release(data.bot_id);
+    state.audit.record(
+        audit::entry(&state, AuditAction::BotDeleted, s.id)
+            .target(data.bot_id)
+            .change(name, None),
+    );
+
+    for bot_socket in sockets.into_iter().filter_map(|sid| io.get_socket(sid)) {
+        bot_socket.disconnect().ok();
+    }
+
+    info!("User {} deleted bot {}", s.id, data.bot_id);
+    emit_list(&s, &state);
+}
+
+pub async fn register_commands(
+    s: SocketRef,
+    Data(data): Data<RegisterCommandsPayload>,
+    State(state): State<AppState>,
+) {
+    if let Some(name) = data
+        .commands
+        .iter()
+        .map(|command| command.name.trim_start_matches('/').to_lowercase())
+        .find(|name| commands::is_builtin(name))
+    {
+        emit_error(&s, &format!("/{name} is a built-in command"));
+        return;
+    }
+
+    match state.bots.register_commands(s.id, data.commands) {
+        Ok(registered) => {
+            info!("Bot {} registered {} commands", s.id, registered.len());
+            let response = RegisterCommandsPayload {
+                commands: registered,
+            };
+            if let Err(e) = s.emit("bot.commands", &response) {
+                error!("Failed to confirm commands to bot {}: {}", s.id, e);
+            }
+        }
+        Err(e) => emit_error(&s, &e.to_string()),
+    }
+}
+
+pub async fn respond(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<CommandRespondPayload>,
+    State(state): State<AppState>,
+) {
+    let Some(invocation) = state.bots.invocation(data.invocation_id, s.id) else {
+        emit_error(&s, "Invocation does not exist or has expired");
+        return;
+    };
+
+    if data.public {
+        if let Err(e) = send_event::ensure_member(&state, invocation.room_id, s.id) {
+            let _ = s.emit("error", &ErrorResponse::from(e));
+            return;
+        }
+
+        let payload = SendEventPayload {
+            room: invocation.room_id,
+            payload: RoomEventData::Message(TextMessageEvent {
+                content: data.content,
+                edited: false,
+                deleted: false,
+                reply_to: None,
+                expires_at: None,
+                mentions: Vec::new(),
+                mentions_room: false,
+                formatted: None,
+                link_previews: Vec::new(),
+                emote: false,
+            }),
+            ttl_secs: None,
+        };
+
+        if let Err(message) = send_event::deliver(&io, &state, s.id, payload).await {
+            emit_error(&s, &message);
+        }
+        return;
+    }
+
+    let Some(invoker) = io.get_socket(invocation.invoker) else {
+        // The user left before the bot answered.
+        return;
+    };
+
+    let response = CommandResponse {
+        room_id: invocation.room_id,
+        command: invocation.command,
+        content: data.content,
+        bot_id: Some(s.id),
+        bot_name: state.usernames.get(&s.id).map(|name| name.clone()),
+    };
+
+    if let Err(e) = invoker.emit("command.response", &response) {
+        error!("Failed to send bot response to {}: {}", invoker.id, e);
+    }
+}
+
+fn emit_list(s: &SocketRef, state: &AppState) {
+    let owner = session::user_key(state, s.id);
+    let response = BotListResponse {
+        bots: state
+            .bots
+            .owned_by(owner)
+            .iter()
+            .map(|bot| state.bots.info(bot))
+            .collect(),
+    };
+
+    if let Err(e) = s.emit("bot.list", &response) {
+        error!("Failed to send bot list to {}: {}", s.id, e);
+    }
+}
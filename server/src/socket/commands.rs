@@ -0,0 +1,344 @@
+//! Slash commands typed into the message box.
+//!
+//! A message that starts with `/` is run as a command instead of being
+//! stored. Built-in commands are handled here; any other command goes to a
+//! connected bot in the room that registered it. Replies are sent only to
+//! the invoker as `command.response`. Start a message with `//` to send it
+//! with a single leading slash.
+
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{SocketIo, extract::SocketRef, socket::Sid};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    bots::PendingInvocation,
+    models::{RoomEvent, RoomEventData, TextMessageEvent, TopicChangeEvent},
+    socket::{
+        HandlerError, audit, protocol, room_list,
+        send_event::{self, SendEventPayload},
+        session, user_management, validation,
+    },
+    state::AppState,
+};
+
+const MAX_TOPIC_CHARS: usize = 250;
+
+const BUILTINS: [(&str, &str); 4] = [
+    ("me", "/me <action> - Describe something you do"),
+    ("topic", "/topic [text] - Show or change the room topic"),
+    ("nick", "/nick <name> - Change your username"),
+    ("help", "/help - List the commands available in this room"),
+];
+
+/// A reply to a command, visible only to the user who ran it.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CommandResponse {
+    pub room_id: Uuid,
+    pub command: String,
+    pub content: String,
+    /// The bot that answered, if the command was not a built-in.
+    #[ts(type = "String | null")]
+    #[schemars(with = "Option<String>")]
+    pub bot_id: Option<Sid>,
+    pub bot_name: Option<String>,
+}
+
+/// Sent to a bot when someone runs one of its commands.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CommandInvocation {
+    /// Pass to `command.respond` to answer.
+    pub invocation_id: Uuid,
+    pub room_id: Uuid,
+    pub command: String,
+    /// Everything after the command name.
+    pub args: String,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+    pub username: Option<String>,
+}
+
+pub enum Parsed {
+    Message,
+    /// Starts with `//`, send it with one slash removed.
+    Escaped,
+    Command {
+        name: String,
+        args: String,
+    },
+}
+
+pub fn parse(content: &str) -> Parsed {
+    let Some(rest) = content.strip_prefix('/') else {
+        return Parsed::Message;
+    };
+    if rest.starts_with('/') {
+        return Parsed::Escaped;
+    }
+
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if name.is_empty() {
+        return Parsed::Message;
+    }
+
+    Parsed::Command {
+        name: name.to_lowercase(),
+        args: args.trim().to_string(),
+    }
+}
+
+/// Removes the escaping slash from a message that starts with `//`.
+pub fn unescape(message: &mut TextMessageEvent) {
+    message.content.remove(0);
+    if let Some(formatted) = &mut message.formatted
+        && formatted.body.starts_with("//")
+    {
+        formatted.body.remove(0);
+    }
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.iter().any(|(builtin, _)| *builtin == name)
+}
+
+/// Runs a command on behalf of `s`, who has already been checked to be a
+/// member of the room.
+pub async fn run(
+    s: &SocketRef,
+    io: &SocketIo,
+    state: &AppState,
+    room_id: Uuid,
+    name: &str,
+    args: &str,
+) {
+    info!("User {} ran /{} in room {}", s.id, name, room_id);
+
+    let result = match name {
+        "me" => emote(io, state, s.id, room_id, args).await,
+        "topic" => topic(io, state, s.id, room_id, args).await,
+        "nick" => nick(s, io, state, args).await,
+        "help" => Ok(Some(help(state, room_id))),
+        _ => invoke_bot(io, state, s.id, room_id, name, args),
+    };
+
+    match result {
+        Ok(Some(content)) => reply(s, room_id, name, content),
+        Ok(None) => {}
+        Err(e) => reply(s, room_id, name, e.to_string()),
+    }
+}
+
+fn reply(s: &SocketRef, room_id: Uuid, command: &str, content: String) {
+    let response = CommandResponse {
+        room_id,
+        command: command.to_string(),
+        content,
+        bot_id: None,
+        bot_name: None,
+    };
+
+    if let Err(e) = s.emit("command.response", &response) {
+        error!("Failed to send command response to {}: {}", s.id, e);
+    }
+}
+
+async fn emote(
+    io: &SocketIo,
+    state: &AppState,
+    from: Sid,
+    room_id: Uuid,
+    action: &str,
+) -> Result<Option<String>, HandlerError> {
+    if action.is_empty() {
+        return Err(HandlerError::Invalid("Usage: /me <action>".to_string()));
+    }
+
+    let payload = SendEventPayload {
+        room: room_id,
+        payload: RoomEventData::Message(TextMessageEvent {
+            content: action.to_string(),
+            edited: false,
+            deleted: false,
+            reply_to: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            mentions_room: false,
+            formatted: None,
+            link_previews: Vec::new(),
+            emote: true,
+        }),
+        ttl_secs: None,
+    };
+
+    send_event::deliver(io, state, from, payload)
+        .await
+        .map(|_| None)
+        .map_err(HandlerError::Invalid)
+}
+
+async fn topic(
+    io: &SocketIo,
+    state: &AppState,
+    from: Sid,
+    room_id: Uuid,
+    topic: &str,
+) -> Result<Option<String>, HandlerError> {
+    let topic = validation::clean_line(topic);
+    let topic = topic.as_str();
+
+    let is_public = {
+        let mut room = state
+            .rooms
+            .get_mut(&room_id)
+            .ok_or(HandlerError::NotFound("Room does not exist"))?;
+
+        if topic.is_empty() {
+            return Ok(Some(match &room.topic {
+                Some(topic) => format!("The topic is: {topic}"),
+                None => "This room has no topic.".to_string(),
+            }));
+        }
+
+        // Anyone may set the topic of a direct conversation.
+        if !room.is_direct() && !room.is_moderator(&session::user_key(state, from)) {
+            return Err(HandlerError::Forbidden(
+                "Only moderators can change the topic",
+            ));
+        }
+
+        if topic.chars().count() > MAX_TOPIC_CHARS {
+            return Err(HandlerError::Invalid(format!(
+                "Topics are limited to {MAX_TOPIC_CHARS} characters"
+            )));
+        }
+
+        let before = room.topic.replace(topic.to_string());
+        if !room.is_direct() {
+            state.audit.record(
+                audit::entry(state, AuditAction::TopicChanged, from)
+                    .in_room(room_id)
+                    .target(room_id)
+                    .change(before, Some(topic.to_string())),
+            );
+        }
+        !room.is_direct()
+    };
+
+    let event = RoomEvent {
+        id: Uuid::new_v4(),
+        from,
+        timestamp: Utc::now(),
+        data: RoomEventData::TopicChange(TopicChangeEvent {
+            topic: topic.to_string(),
+        }),
+        sender_key: None,
+    };
+
+    if let Some(mut room) = state.rooms.get_mut(&room_id) {
+        room.events.push(event.clone());
+    }
+
+    protocol::broadcast_room_event(io, state, room_id, &event).await;
+
+    if is_public {
+        io.emit("room.list", &room_list::public_rooms(state))
+            .await
+            .ok();
+    }
+
+    Ok(None)
+}
+
+async fn nick(
+    s: &SocketRef,
+    io: &SocketIo,
+    state: &AppState,
+    name: &str,
+) -> Result<Option<String>, HandlerError> {
+    if name.is_empty() {
+        return Err(HandlerError::Invalid("Usage: /nick <name>".to_string()));
+    }
+
+    let name = user_management::change_username(s, io, state, name)
+        .await
+        .map_err(|e| HandlerError::Invalid(e.to_string()))?;
+    Ok(Some(format!("You are now known as {name}")))
+}
+
+fn help(state: &AppState, room_id: Uuid) -> String {
+    let mut lines: Vec<String> = BUILTINS
+        .iter()
+        .map(|(_, usage)| usage.to_string())
+        .collect();
+
+    if let Some(room) = state.rooms.get(&room_id) {
+        for (bot, command) in state.bots.commands_for(&room.members) {
+            let bot_name = state.usernames.get(&bot).map(|name| name.clone());
+            lines.push(format!(
+                "/{} - {} ({})",
+                command.name,
+                command.description,
+                bot_name.as_deref().unwrap_or("bot")
+            ));
+        }
+    }
+
+    lines.join("\n")
+}
+
+fn invoke_bot(
+    io: &SocketIo,
+    state: &AppState,
+    invoker: Sid,
+    room_id: Uuid,
+    name: &str,
+    args: &str,
+) -> Result<Option<String>, HandlerError> {
+    let bot = state.rooms.get(&room_id).and_then(|room| {
+        state
+            .bots
+            .commands_for(&room.members)
+            .into_iter()
+            .find(|(_, command)| command.name == name)
+            .map(|(bot, _)| bot)
+    });
+
+    let Some(bot_socket) = bot.and_then(|bot| io.get_socket(bot)) else {
+        return Err(HandlerError::Invalid(format!(
+            "Unknown command /{name}. Type /help to see what is available."
+        )));
+    };
+
+    let invocation_id = state.bots.start_invocation(PendingInvocation {
+        room_id,
+        invoker,
+        bot: bot_socket.id,
+        command: name.to_string(),
+        started_at: std::time::Instant::now(),
+    });
+
+    let invocation = CommandInvocation {
+        invocation_id,
+        room_id,
+        command: name.to_string(),
+        args: args.to_string(),
+        user_id: invoker,
+        username: state.usernames.get(&invoker).map(|u| u.clone()),
+    };
+
+    if let Err(e) = bot_socket.emit("command.invoked", &invocation) {
+        error!("Failed to send /{} to bot {}: {}", name, bot_socket.id, e);
+        return Err(HandlerError::Invalid(
+            "The bot did not receive the command".to_string(),
+        ));
+    }
+
+    Ok(None)
+}
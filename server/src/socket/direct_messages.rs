@@ -0,0 +1,190 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    models::{Room, RoomKind},
+    socket::{audit, blocking, emit_error, session},
+    state::AppState,
+};
+
+/// Largest group a direct conversation may have, including its creator.
+const MAX_PARTICIPANTS: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct OpenDirectMessagePayload {
+    /// The other users to talk to.
+    #[ts(type = "Array<String>")]
+    #[schemars(with = "Vec<String>")]
+    pub user_ids: Vec<Sid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DirectConversation {
+    pub room_id: Uuid,
+    /// The other participants' names, as seen by the receiving user.
+    pub name: String,
+    /// Everyone in the conversation, including the receiving user.
+    pub participants: Vec<DirectParticipant>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DirectParticipant {
+    /// One of the participant's connections, or nothing while they are offline.
+    #[ts(type = "String | null")]
+    #[schemars(with = "Option<String>")]
+    pub user_id: Option<Sid>,
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DirectConversationsResponse {
+    pub conversations: Vec<DirectConversation>,
+}
+
+/// Opens the direct conversation between the caller and `user_ids`, creating
+/// it on first use. The same set of participants always gets the same room.
+pub async fn open_direct_message(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<OpenDirectMessagePayload>,
+    State(state): State<AppState>,
+) {
+    let own_key = session::user_key(&state, s.id);
+
+    let mut participants = vec![own_key];
+    for user_id in &data.user_ids {
+        let Some(key) = state.user_keys.get(user_id).map(|key| *key) else {
+            emit_error(&s, "User is not connected");
+            return;
+        };
+        if blocking::has_blocked(&state, key, own_key) {
+            emit_error(&s, "You cannot message this user");
+            return;
+        }
+        participants.push(key);
+    }
+    participants.sort_unstable();
+    participants.dedup();
+
+    if participants.len() < 2 {
+        emit_error(&s, "Direct messages need at least one other user");
+        return;
+    }
+
+    if participants.len() > MAX_PARTICIPANTS {
+        emit_error(&s, "Too many participants for a direct conversation");
+        return;
+    }
+
+    let room_id = *state
+        .direct_rooms
+        .entry(participants.clone())
+        .or_insert_with(|| {
+            let room_id = Uuid::new_v4();
+            let name = participant_names(&state, &participants, None);
+            state.rooms.insert(
+                room_id,
+                Room::new_direct(room_id, name.clone(), participants.clone()),
+            );
+            state.audit.record(
+                audit::entry(&state, AuditAction::RoomCreated, s.id)
+                    .in_room(room_id)
+                    .target(room_id)
+                    .change(None, Some(name)),
+            );
+            info!(
+                "User {} opened direct conversation {} with {} participants",
+                s.id,
+                room_id,
+                participants.len()
+            );
+            room_id
+        });
+
+    let listed = listed_participants(&state, &participants);
+    for key in &participants {
+        let conversation = DirectConversation {
+            room_id,
+            name: participant_names(&state, &participants, Some(*key)),
+            participants: listed.clone(),
+        };
+
+        for sid in session::sockets_for(&state, *key) {
+            let Some(socket) = io.get_socket(sid) else {
+                continue;
+            };
+            if let Err(e) = socket.emit("dm.opened", &conversation) {
+                error!("Failed to notify {} of direct conversation: {}", sid, e);
+            }
+        }
+    }
+}
+
+pub async fn list_direct_messages(s: SocketRef, State(state): State<AppState>) {
+    let own_key = session::user_key(&state, s.id);
+
+    let conversations = state
+        .rooms
+        .iter()
+        .filter_map(|entry| match &entry.value().kind {
+            RoomKind::Direct { participants } if participants.contains(&own_key) => {
+                Some(DirectConversation {
+                    room_id: entry.value().id,
+                    name: participant_names(&state, participants, Some(own_key)),
+                    participants: listed_participants(&state, participants),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    if let Err(e) = s.emit("dm.list", &DirectConversationsResponse { conversations }) {
+        error!("Failed to send direct conversations to {}: {}", s.id, e);
+    }
+}
+
+/// Describes the participants without revealing their user keys, which double
+/// as login credentials.
+fn listed_participants(state: &AppState, participants: &[Uuid]) -> Vec<DirectParticipant> {
+    participants
+        .iter()
+        .map(|key| {
+            let sockets = session::sockets_for(state, *key);
+            DirectParticipant {
+                user_id: sockets.first().copied(),
+                username: sockets
+                    .iter()
+                    .find_map(|sid| state.usernames.get(sid).map(|name| name.clone())),
+            }
+        })
+        .collect()
+}
+
+/// Names a conversation after its participants, leaving out `viewer`.
+fn participant_names(state: &AppState, participants: &[Uuid], viewer: Option<Uuid>) -> String {
+    let names: Vec<String> = participants
+        .iter()
+        .filter(|key| Some(**key) != viewer)
+        .map(|key| {
+            session::sockets_for(state, *key)
+                .iter()
+                .find_map(|sid| state.usernames.get(sid).map(|name| name.clone()))
+                .unwrap_or_else(|| "Unknown user".to_string())
+        })
+        .collect();
+
+    names.join(", ")
+}
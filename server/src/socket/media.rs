@@ -0,0 +1,216 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    media::{self, audio, image},
+    models::{AudioMessageEvent, RoomEventData},
+    rate_limit::Action,
+    socket::{emit_error, rate_limits, session},
+    state::AppState,
+};
+
+const MAX_AUDIO_DURATION_MS: u64 = 10 * 60 * 1000;
+/// How far the declared duration may be from the container's.
+const DURATION_TOLERANCE_MS: u64 = 1000;
+const MAX_WAVEFORM_SAMPLES: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct UploadMediaPayload {
+    /// Base64 encoded file contents.
+    pub data: String,
+    pub mime_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct MediaUploadedResponse {
+    pub blob_id: Uuid,
+    pub size: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct GetMediaPayload {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct MediaBlobResponse {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    pub mime_type: String,
+    /// Base64 encoded file contents.
+    pub data: String,
+}
+
+pub async fn upload_media(
+    s: SocketRef,
+    Data(data): Data<UploadMediaPayload>,
+    State(state): State<AppState>,
+) {
+    let user_key = session::user_key(&state, s.id);
+    if let Err(limited) =
+        state
+            .rate_limits
+            .check(user_key, session::address(&s), Action::MediaUpload)
+    {
+        rate_limits::emit_rate_limited(&s, None, &limited);
+        return;
+    }
+
+    let is_image = data.mime_type.starts_with("image/");
+    if !data.mime_type.starts_with("audio/") && !is_image {
+        emit_error(
+            &s,
+            &media::MediaError::UnsupportedType(data.mime_type).to_string(),
+        );
+        return;
+    }
+
+    // Base64 grows data by a third, so reject oversized uploads before decoding.
+    if data.data.len() / 4 * 3 > media::MAX_BLOB_BYTES + 3 {
+        emit_error(&s, &media::MediaError::TooLarge.to_string());
+        return;
+    }
+
+    let Ok(bytes) = STANDARD.decode(data.data.as_bytes()) else {
+        emit_error(&s, "Upload is not valid base64");
+        return;
+    };
+
+    // Images are only used as avatars, which are never probed again.
+    if is_image && image::sniff(&bytes) != Some(data.mime_type.as_str()) {
+        emit_error(
+            &s,
+            &media::MediaError::UnsupportedType(data.mime_type).to_string(),
+        );
+        return;
+    }
+
+    let size = bytes.len() as u32;
+
+    match state.media.insert(data.mime_type, bytes, user_key) {
+        Ok(blob_id) => {
+            info!("User {} uploaded {} bytes as {}", s.id, size, blob_id);
+            if let Err(e) = s.emit("media.uploaded", &MediaUploadedResponse { blob_id, size }) {
+                error!("Failed to confirm upload to {}: {}", s.id, e);
+            }
+        }
+        Err(e) => emit_error(&s, &e.to_string()),
+    }
+}
+
+/// Sends the media attached to a message to a member of its room.
+pub async fn get_media(
+    s: SocketRef,
+    Data(data): Data<GetMediaPayload>,
+    State(state): State<AppState>,
+) {
+    let blob_id = {
+        let Some(room) = state.rooms.get(&data.room_id) else {
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        if !room.members.contains(&s.id) {
+            emit_error(&s, "You are not a member of this room");
+            return;
+        }
+
+        let blob_id = room
+            .events
+            .iter()
+            .find(|event| event.id == data.message_id)
+            .and_then(|event| match &event.data {
+                RoomEventData::Audio(audio) if !audio.deleted => Some(audio.blob_id),
+                _ => None,
+            });
+
+        let Some(blob_id) = blob_id else {
+            emit_error(&s, "Message has no media");
+            return;
+        };
+        blob_id
+    };
+
+    let Some(blob) = state.media.get(&blob_id) else {
+        emit_error(&s, &media::MediaError::NotFound.to_string());
+        return;
+    };
+
+    let response = MediaBlobResponse {
+        room_id: data.room_id,
+        message_id: data.message_id,
+        mime_type: blob.mime_type,
+        data: STANDARD.encode(&blob.data),
+    };
+
+    if let Err(e) = s.emit("media.blob", &response) {
+        error!("Failed to send media {} to {}: {}", blob_id, s.id, e);
+    }
+}
+
+/// Checks an audio message against its uploaded blob and fills in the fields
+/// the server is responsible for. The blob is claimed only once everything
+/// checks out, so a rejected message can be fixed and resent.
+pub fn prepare_audio(
+    state: &AppState,
+    from: Sid,
+    audio_event: &mut AudioMessageEvent,
+) -> Result<(), String> {
+    let user_key = session::user_key(state, from);
+
+    let blob = state
+        .media
+        .get(&audio_event.blob_id)
+        .ok_or_else(|| media::MediaError::NotFound.to_string())?;
+    if blob.uploaded_by != user_key {
+        return Err(media::MediaError::NotOwner.to_string());
+    }
+
+    let info = audio::probe(&blob.data).map_err(|e| format!("Invalid audio: {e}"))?;
+
+    if info.codec != audio_event.codec {
+        return Err(format!(
+            "Declared codec {:?} does not match the file ({:?})",
+            audio_event.codec, info.codec
+        ));
+    }
+
+    if info.duration_ms > MAX_AUDIO_DURATION_MS {
+        return Err("Audio is too long".to_string());
+    }
+
+    if u64::from(audio_event.duration_ms).abs_diff(info.duration_ms) > DURATION_TOLERANCE_MS {
+        return Err(format!(
+            "Declared duration {} ms does not match the file ({} ms)",
+            audio_event.duration_ms, info.duration_ms
+        ));
+    }
+
+    if audio_event.waveform.len() > MAX_WAVEFORM_SAMPLES {
+        return Err("Waveform has too many samples".to_string());
+    }
+
+    state
+        .media
+        .claim(&audio_event.blob_id, user_key)
+        .map_err(|e| e.to_string())?;
+
+    audio_event.duration_ms = info.duration_ms as u32;
+    audio_event.mime_type = info.mime_type.to_string();
+    audio_event.size = blob.data.len() as u32;
+
+    Ok(())
+}
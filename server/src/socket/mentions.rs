@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    models::RoomEvent,
+    socket::{blocking, emit_error, session, validation},
+    state::AppState,
+};
+
+const PREVIEW_CHARS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct MentionNotification {
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub message_id: Uuid,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub from: Sid,
+    pub from_username: Option<String>,
+    pub content_preview: String,
+    /// Whether the user was reached through `@room` rather than by name.
+    pub room_mention: bool,
+    /// Unread mentions of the user in this room, including this one.
+    pub unread_count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ClearMentionsPayload {
+    pub room_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct MentionCountsResponse {
+    pub counts: HashMap<Uuid, u32>,
+}
+
+/// Mentions found in a message, resolved against the room's members.
+#[derive(Debug, Default)]
+pub struct Mentions {
+    pub users: Vec<Sid>,
+    pub room: bool,
+}
+
+/// Extracts `@name` tokens from `content`, as typed. `@room` is reported
+/// separately.
+fn parse(content: &str) -> (HashSet<String>, bool) {
+    let mut names = HashSet::new();
+    let mut room = false;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if c != '@' {
+            continue;
+        }
+
+        // An `@` glued to a preceding word is an email address, not a mention.
+        let preceded_by_word = content[..index]
+            .chars()
+            .next_back()
+            .is_some_and(|prev| prev.is_alphanumeric() || prev == '_');
+        if preceded_by_word {
+            continue;
+        }
+
+        let start = index + c.len_utf8();
+        let mut end = start;
+        while let Some(&(i, next)) = chars.peek() {
+            // Invisible characters are ignored in names, so they must not end
+            // the mention either.
+            if next.is_alphanumeric()
+                || matches!(next, '_' | '-' | '.')
+                || validation::is_invisible(next)
+            {
+                end = i + next.len_utf8();
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        let name = content[start..end].trim_end_matches('.');
+        if name.is_empty() {
+            continue;
+        }
+
+        if name.eq_ignore_ascii_case("room") {
+            room = true;
+        } else {
+            names.insert(name.to_string());
+        }
+    }
+
+    (names, room)
+}
+
+/// Matches mentioned names the way the username registry compares them, so a
+/// look-alike spelling still reaches the name's owner.
+pub fn resolve(state: &AppState, members: &HashSet<Sid>, content: &str) -> Mentions {
+    let (names, room) = parse(content);
+
+    let owners: HashSet<Uuid> = names
+        .iter()
+        .filter_map(|name| state.username_registry.owner_of(name))
+        .collect();
+
+    let mut users: Vec<Sid> = members
+        .iter()
+        .filter(|member| {
+            state
+                .user_keys
+                .get(*member)
+                .is_some_and(|user_key| owners.contains(&*user_key))
+        })
+        .copied()
+        .collect();
+    users.sort_by_key(|sid| sid.to_string());
+
+    Mentions { users, room }
+}
+
+/// Sends a `mention` notification directly to each recipient's socket, so it
+/// arrives even when they are not looking at the room.
+pub fn notify(
+    io: &SocketIo,
+    state: &AppState,
+    room_id: Uuid,
+    message: &RoomEvent,
+    content: &str,
+    mentions: &Mentions,
+    already_notified: &HashSet<Sid>,
+) {
+    let (room_name, recipients) = {
+        let Some(room) = state.rooms.get(&room_id) else {
+            return;
+        };
+
+        let mut recipients: HashSet<Sid> = mentions.users.iter().copied().collect();
+        if mentions.room {
+            recipients.extend(room.members.iter().copied());
+        }
+        recipients.remove(&message.from);
+        recipients.retain(|sid| !already_notified.contains(sid));
+        let blockers = message
+            .sender_key
+            .map(|from| blocking::blockers_of_key(state, from))
+            .unwrap_or_default();
+        recipients.retain(|sid| !blockers.contains(sid));
+
+        (room.name.clone(), recipients)
+    };
+
+    if recipients.is_empty() {
+        return;
+    }
+
+    let from_username = state.usernames.get(&message.from).map(|u| u.clone());
+    let content_preview = preview(content);
+
+    for recipient in recipients {
+        let user_key = session::user_key(state, recipient);
+        let unread_count = {
+            let mut counts = state.mention_counts.entry(user_key).or_default();
+            let count = counts.entry(room_id).or_insert(0);
+            *count += 1;
+            *count
+        };
+
+        let Some(socket) = io.get_socket(recipient) else {
+            continue;
+        };
+
+        let notification = MentionNotification {
+            room_id,
+            room_name: room_name.clone(),
+            message_id: message.id,
+            from: message.from,
+            from_username: from_username.clone(),
+            content_preview: content_preview.clone(),
+            room_mention: !mentions.users.contains(&recipient),
+            unread_count,
+        };
+
+        if let Err(e) = socket.emit("mention", &notification) {
+            error!(
+                "Failed to send mention notification to {}: {}",
+                recipient, e
+            );
+        }
+    }
+}
+
+fn preview(content: &str) -> String {
+    if content.chars().count() > PREVIEW_CHARS {
+        let truncated: String = content.chars().take(PREVIEW_CHARS).collect();
+        format!("{}...", truncated)
+    } else {
+        content.to_string()
+    }
+}
+
+pub async fn get_mention_counts(socket: SocketRef, State(state): State<AppState>) {
+    send_counts(&socket, &state);
+}
+
+pub async fn clear_mentions(
+    socket: SocketRef,
+    Data(data): Data<ClearMentionsPayload>,
+    State(state): State<AppState>,
+) {
+    if !state.rooms.contains_key(&data.room_id) {
+        emit_error(&socket, "Room does not exist");
+        return;
+    }
+
+    let user_key = session::user_key(&state, socket.id);
+    if let Some(mut counts) = state.mention_counts.get_mut(&user_key) {
+        counts.remove(&data.room_id);
+    }
+
+    info!(
+        "User {} cleared mentions in room {}",
+        socket.id, data.room_id
+    );
+
+    send_counts(&socket, &state);
+}
+
+fn send_counts(socket: &SocketRef, state: &AppState) {
+    let user_key = session::user_key(state, socket.id);
+    let counts = state
+        .mention_counts
+        .get(&user_key)
+        .map(|counts| counts.clone())
+        .unwrap_or_default();
+
+    if let Err(e) = socket.emit("mentions.counts", &MentionCountsResponse { counts }) {
+        error!("Failed to send mention counts: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(state: &AppState, name: &str) -> Sid {
+        let sid = Sid::new();
+        let user_key = Uuid::new_v4();
+        state.user_keys.insert(sid, user_key);
+        state.username_registry.claim(user_key, name).unwrap();
+        state.usernames.insert(sid, name.to_string());
+        sid
+    }
+
+    #[test]
+    fn resolves_look_alike_names_to_their_owner() {
+        let state = AppState::from_env();
+        let alice = member(&state, "Alice");
+        let strasse = member(&state, "straße");
+        let bob = member(&state, "bob");
+        let members = HashSet::from([alice, strasse, bob]);
+
+        for content in [
+            "hi @alice",
+            "hi @ＡＬＩＣＥ",
+            "hi @al\u{200b}ice!",
+            "hi @ALICE.",
+        ] {
+            let mentions = resolve(&state, &members, content);
+            assert_eq!(mentions.users, vec![alice], "{content}");
+        }
+
+        assert_eq!(resolve(&state, &members, "@STRASSE").users, vec![strasse]);
+        assert!(
+            resolve(&state, &members, "mail bob@example.com")
+                .users
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn ignores_names_of_users_outside_the_room() {
+        let state = AppState::from_env();
+        let alice = member(&state, "alice");
+        let bob = member(&state, "bob");
+
+        let mentions = resolve(&state, &HashSet::from([alice]), "@alice @bob @room");
+        assert_eq!(mentions.users, vec![alice]);
+        assert!(mentions.room);
+        assert!(!mentions.users.contains(&bob));
+    }
+}
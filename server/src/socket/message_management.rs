@@ -1,25 +1,37 @@
+use std::collections::HashSet;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::{
     SocketIo,
     extract::{Data, SocketRef, State},
+    socket::Sid,
 };
 use ts_rs::TS;
 use uuid::Uuid;
 
 use crate::{
-    models::{MessageDeleteEvent, MessageEditEvent, RoomEvent, RoomEventData},
+    formatting, link_preview,
+    models::{FormattedBody, MessageDeleteEvent, MessageEditEvent, RoomEvent, RoomEventData},
+    socket::{
+        ErrorResponse, HandlerError,
+        mentions::{self, Mentions},
+        moderation, protocol, send_event,
+    },
     state::AppState,
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct EditMessagePayload {
     pub room: Uuid,
     pub message_id: Uuid,
     pub new_content: String,
+    #[serde(default)]
+    pub formatted: Option<FormattedBody>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct DeleteMessagePayload {
     pub room: Uuid,
@@ -32,157 +44,384 @@ pub async fn handle_edit_message(
     Data(data): Data<EditMessagePayload>,
     State(state): State<AppState>,
 ) {
+    if let Err(e) = edit_message(&io, &state, s.id, data).await {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+    }
+}
+
+pub async fn handle_delete_message(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<DeleteMessagePayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = delete_message(&io, &state, s.id, data).await {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+    }
+}
+
+/// Edits a message on behalf of its author and broadcasts the change.
+pub async fn edit_message(
+    io: &SocketIo,
+    state: &AppState,
+    from: Sid,
+    mut data: EditMessagePayload,
+) -> Result<(), HandlerError> {
+    send_event::ensure_member(state, data.room, from)?;
+    state
+        .limits
+        .message(&mut data.new_content, data.formatted.as_mut())?;
+
+    let (mut new_content, mut formatted) =
+        formatting::apply(data.new_content, data.formatted.as_ref());
+    let flags = moderation::screen(state, data.room, from, &mut new_content, &mut formatted)
+        .map_err(HandlerError::Invalid)?;
+
+    let mut mentions = Mentions::default();
+    let mut previously_mentioned = HashSet::new();
+
     {
-        let Some(room) = state.rooms.get(&data.room) else {
-            println!("Room {} not found for user {}", data.room, s.id);
-            return;
+        let Some(mut room) = state.rooms.get_mut(&data.room) else {
+            return Err(HandlerError::NotFound("Room does not exist"));
         };
+        let room = &mut *room;
 
-        if !room.members.contains(&s.id) {
-            println!("User {} not a member of room {}", s.id, data.room);
-            return;
+        let Some(event) = room
+            .events
+            .iter_mut()
+            .find(|event| event.id == data.message_id)
+        else {
+            println!(
+                "Message {} not found in room {}",
+                data.message_id, data.room
+            );
+            return Err(HandlerError::NotFound("Message does not exist"));
         };
-    }
 
-    let mut message_found = false;
-    let mut message_owner_check = false;
-
-    if let Some(mut room) = state.rooms.get_mut(&data.room) {
-        for event in &mut room.events {
-            if event.id == data.message_id {
-                message_found = true;
-
-                if event.from != s.id {
-                    println!(
-                        "User {} trying to edit message {} they don't own",
-                        s.id, data.message_id
-                    );
-                    return;
-                }
-                message_owner_check = true;
-
-                if let RoomEventData::Message(ref mut message_event) = event.data {
-                    message_event.content = data.new_content.clone();
-                    message_event.edited = true;
-                }
-                break;
-            }
+        if event.from != from {
+            println!(
+                "User {} trying to edit message {} they don't own",
+                from, data.message_id
+            );
+            return Err(HandlerError::Forbidden(
+                "You can only edit your own messages",
+            ));
         }
-    }
 
-    if !message_found {
-        println!(
-            "Message {} not found in room {}",
-            data.message_id, data.room
-        );
-        return;
-    }
+        let RoomEventData::Message(message_event) = &event.data else {
+            return Err(HandlerError::Forbidden("Only text messages can be edited"));
+        };
 
-    if !message_owner_check {
-        return;
+        // A deleted or expired message must stay redacted.
+        if message_event.deleted
+            || message_event
+                .expires_at
+                .is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+        {
+            println!(
+                "User {} trying to edit deleted or expired message {}",
+                from, data.message_id
+            );
+            return Err(HandlerError::Forbidden(
+                "Deleted or expired messages cannot be edited",
+            ));
+        }
+
+        if !room.policy.can_edit(event.timestamp) {
+            println!(
+                "User {} trying to edit message {} outside the edit window",
+                from, data.message_id
+            );
+            return Err(HandlerError::Forbidden(
+                "The edit window for this message has passed",
+            ));
+        }
+
+        if let RoomEventData::Message(ref mut message_event) = event.data {
+            mentions = mentions::resolve(state, &room.members, &new_content);
+            previously_mentioned.extend(message_event.mentions.iter().copied());
+            if message_event.mentions_room {
+                previously_mentioned.extend(room.members.iter().copied());
+            }
+
+            message_event.content = new_content.clone();
+            message_event.formatted = formatted.clone();
+            message_event.edited = true;
+            message_event.mentions = mentions.users.clone();
+            message_event.mentions_room = mentions.room;
+            message_event.link_previews.clear();
+        }
     }
 
     let edit_event = RoomEventData::MessageEdit(MessageEditEvent {
         message_id: data.message_id,
-        new_content: data.new_content,
+        new_content: new_content.clone(),
+        mentions: mentions.users.clone(),
+        mentions_room: mentions.room,
+        formatted,
     });
 
-    if let Err(e) = io
-        .to(data.room.to_string())
-        .emit(
-            "room.event",
-            &RoomEvent {
-                id: Uuid::new_v4(),
-                from: s.id,
-                timestamp: chrono::Utc::now(),
-                data: edit_event,
-            },
-        )
-        .await
-    {
-        println!(
-            "Failed to broadcast message edit to room {}: {}",
-            data.room, e
+    let event = RoomEvent {
+        id: Uuid::new_v4(),
+        from,
+        timestamp: chrono::Utc::now(),
+        data: edit_event,
+        sender_key: state.user_keys.get(&from).map(|key| *key),
+    };
+    protocol::broadcast_room_event(io, state, data.room, &event).await;
+
+    let edited_message = state.rooms.get(&data.room).and_then(|room| {
+        room.events
+            .iter()
+            .find(|event| event.id == data.message_id)
+            .cloned()
+    });
+
+    if let Some(edited_message) = edited_message {
+        let urls = link_preview::extract_urls(&new_content);
+        if !urls.is_empty() {
+            tokio::spawn(link_preview::unfurl(
+                io.clone(),
+                state.clone(),
+                data.room,
+                data.message_id,
+                from,
+                urls,
+            ));
+        }
+
+        mentions::notify(
+            io,
+            state,
+            data.room,
+            &edited_message,
+            &new_content,
+            &mentions,
+            &previously_mentioned,
+        );
+
+        moderation::flag(
+            io,
+            state,
+            data.room,
+            data.message_id,
+            from,
+            &new_content,
+            flags,
         );
     }
+
+    Ok(())
 }
 
-pub async fn handle_delete_message(
-    s: SocketRef,
-    io: SocketIo,
-    Data(data): Data<DeleteMessagePayload>,
-    State(state): State<AppState>,
-) {
+/// Deletes a message on behalf of its author and broadcasts the deletion.
+pub async fn delete_message(
+    io: &SocketIo,
+    state: &AppState,
+    from: Sid,
+    data: DeleteMessagePayload,
+) -> Result<(), HandlerError> {
+    send_event::ensure_member(state, data.room, from)?;
+
     {
-        let Some(room) = state.rooms.get(&data.room) else {
-            println!("Room {} not found for user {}", data.room, s.id);
-            return;
+        let Some(mut room) = state.rooms.get_mut(&data.room) else {
+            return Err(HandlerError::NotFound("Room does not exist"));
         };
+        let room = &mut *room;
 
-        if !room.members.contains(&s.id) {
-            println!("User {} not a member of room {}", s.id, data.room);
-            return;
+        let Some(event) = room
+            .events
+            .iter_mut()
+            .find(|event| event.id == data.message_id)
+        else {
+            println!(
+                "Message {} not found in room {}",
+                data.message_id, data.room
+            );
+            return Err(HandlerError::NotFound("Message does not exist"));
         };
+
+        if event.from != from {
+            println!(
+                "User {} trying to delete message {} they don't own",
+                from, data.message_id
+            );
+            return Err(HandlerError::Forbidden(
+                "You can only delete your own messages",
+            ));
+        }
+
+        if !room.policy.can_delete(event.timestamp) {
+            println!(
+                "User {} trying to delete message {} outside the delete window",
+                from, data.message_id
+            );
+            return Err(HandlerError::Forbidden(
+                "The delete window for this message has passed",
+            ));
+        }
+
+        event.data.redact();
+        state.media.release(&event.data);
+        room.forget_redacted(data.message_id);
     }
 
-    let mut message_found = false;
-    let mut message_owner_check = false;
-
-    if let Some(mut room) = state.rooms.get_mut(&data.room) {
-        for event in &mut room.events {
-            if event.id == data.message_id {
-                message_found = true;
-
-                if event.from != s.id {
-                    println!(
-                        "User {} trying to delete message {} they don't own",
-                        s.id, data.message_id
-                    );
-                    return;
-                }
-                message_owner_check = true;
-
-                if let RoomEventData::Message(ref mut message_event) = event.data {
-                    message_event.deleted = true;
-                    message_event.content = String::new();
-                }
-                break;
-            }
+    announce_deletion(io, state, data.room, data.message_id, from).await;
+
+    Ok(())
+}
+
+/// Tells a room that a message was deleted by `by`, once it has been
+/// redacted.
+pub async fn announce_deletion(
+    io: &SocketIo,
+    state: &AppState,
+    room_id: Uuid,
+    message_id: Uuid,
+    by: Sid,
+) {
+    let event = RoomEvent {
+        id: Uuid::new_v4(),
+        from: by,
+        timestamp: chrono::Utc::now(),
+        data: RoomEventData::MessageDelete(MessageDeleteEvent { message_id }),
+        sender_key: None,
+    };
+    protocol::broadcast_room_event(io, state, room_id, &event).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{
+        models::{Room, TextMessageEvent},
+        socket::test_io,
+    };
+
+    fn room_with_message(state: &AppState, author: Sid, message: TextMessageEvent) -> (Uuid, Uuid) {
+        let mut room = Room::new(Uuid::new_v4(), "room".to_string());
+        room.members.insert(author);
+        let event = RoomEvent {
+            id: Uuid::new_v4(),
+            from: author,
+            timestamp: Utc::now(),
+            data: RoomEventData::Message(message),
+            sender_key: None,
+        };
+        let ids = (room.id, event.id);
+        room.events.push(event);
+        state.rooms.insert(room.id, room);
+        ids
+    }
+
+    fn message(content: &str) -> TextMessageEvent {
+        TextMessageEvent {
+            content: content.to_string(),
+            edited: false,
+            deleted: false,
+            reply_to: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            mentions_room: false,
+            formatted: None,
+            link_previews: Vec::new(),
+            emote: false,
         }
     }
 
-    if !message_found {
-        println!(
-            "Message {} not found in room {}",
-            data.message_id, data.room
-        );
-        return;
+    async fn edit(
+        state: &AppState,
+        from: Sid,
+        room: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), HandlerError> {
+        let io = test_io();
+        edit_message(
+            &io,
+            state,
+            from,
+            EditMessagePayload {
+                room,
+                message_id,
+                new_content: "rewritten".to_string(),
+                formatted: None,
+            },
+        )
+        .await
     }
 
-    if !message_owner_check {
-        return;
+    fn content(state: &AppState, room: Uuid, message_id: Uuid) -> String {
+        let room = state.rooms.get(&room).unwrap();
+        match &room
+            .events
+            .iter()
+            .find(|event| event.id == message_id)
+            .unwrap()
+            .data
+        {
+            RoomEventData::Message(message) => message.content.clone(),
+            _ => unreachable!(),
+        }
     }
 
-    let delete_event = RoomEventData::MessageDelete(MessageDeleteEvent {
-        message_id: data.message_id,
-    });
+    #[tokio::test]
+    async fn edits_live_messages() {
+        let state = AppState::from_env();
+        let author = Sid::new();
+        let (room, message_id) = room_with_message(&state, author, message("original"));
 
-    if let Err(e) = io
-        .to(data.room.to_string())
-        .emit(
-            "room.event",
-            &RoomEvent {
-                id: Uuid::new_v4(),
-                from: s.id,
-                timestamp: chrono::Utc::now(),
-                data: delete_event,
-            },
+        edit(&state, author, room, message_id).await.unwrap();
+        assert_eq!(content(&state, room, message_id), "rewritten");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_edit_deleted_messages() {
+        let state = AppState::from_env();
+        let author = Sid::new();
+        let mut deleted = message("");
+        deleted.deleted = true;
+        let (room, message_id) = room_with_message(&state, author, deleted);
+
+        assert!(matches!(
+            edit(&state, author, room, message_id).await,
+            Err(HandlerError::Forbidden(_))
+        ));
+        assert_eq!(content(&state, room, message_id), "");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_edit_expired_messages() {
+        let state = AppState::from_env();
+        let author = Sid::new();
+        let mut expired = message("gone");
+        expired.expires_at = Some(Utc::now() - Duration::seconds(1));
+        let (room, message_id) = room_with_message(&state, author, expired);
+
+        assert!(matches!(
+            edit(&state, author, room, message_id).await,
+            Err(HandlerError::Forbidden(_))
+        ));
+        assert_eq!(content(&state, room, message_id), "gone");
+    }
+
+    #[tokio::test]
+    async fn deleting_a_message_unpins_it() {
+        let state = AppState::from_env();
+        let author = Sid::new();
+        let (room, message_id) = room_with_message(&state, author, message("pinned"));
+        state.rooms.get_mut(&room).unwrap().pinned.push(message_id);
+
+        delete_message(
+            &test_io(),
+            &state,
+            author,
+            DeleteMessagePayload { room, message_id },
         )
         .await
-    {
-        println!(
-            "Failed to broadcast message delete to room {}: {}",
-            data.room, e
-        );
+        .unwrap();
+
+        assert!(state.rooms.get(&room).unwrap().pinned.is_empty());
     }
 }
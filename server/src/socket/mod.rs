@@ -1,50 +1,180 @@
-mod message_management;
+pub(crate) mod audit;
+mod blocking;
+mod bots;
+mod commands;
+mod direct_messages;
+mod media;
+mod mentions;
+pub(crate) mod message_management;
+mod moderation;
+mod pinned_messages;
+pub(crate) mod polls;
+mod profiles;
+pub(crate) mod protocol;
+pub(crate) mod rate_limits;
+mod reports;
 mod room_events;
-mod room_list;
-mod send_event;
-mod starred_messages;
+pub(crate) mod room_list;
+mod room_policy;
+pub(crate) mod scheduled_messages;
+pub(crate) mod schema;
+pub(crate) mod send_event;
+pub(crate) mod session;
+pub(crate) mod starred_messages;
 mod typing;
-mod user_management;
+pub(crate) mod user_management;
+pub(crate) mod validation;
+mod webhooks;
 
 use color_eyre::eyre::Result;
 use socketioxide::{
     SocketIo,
-    extract::{SocketRef, State},
+    extract::{SocketRef, State, TryData},
 };
 
-use crate::state::AppState;
+use crate::{socket::session::ConnectAuth, state::AppState};
+
+#[derive(serde::Serialize, schemars::JsonSchema, Debug)]
+pub struct ErrorResponse {
+    pub message: String,
+}
+
+/// Reports a refused request to the socket that made it.
+pub fn emit_error(s: &SocketRef, message: &str) {
+    let _ = s.emit(
+        "error",
+        &ErrorResponse {
+            message: message.to_string(),
+        },
+    );
+}
+
+/// Why a request was refused, shared by the socket handlers and the REST API
+/// so both can report it in their own way.
+#[derive(Debug)]
+pub enum HandlerError {
+    NotFound(&'static str),
+    Forbidden(&'static str),
+    Invalid(String),
+}
+
+impl std::fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandlerError::NotFound(message) | HandlerError::Forbidden(message) => {
+                write!(f, "{message}")
+            }
+            HandlerError::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl From<HandlerError> for ErrorResponse {
+    fn from(error: HandlerError) -> Self {
+        ErrorResponse {
+            message: error.to_string(),
+        }
+    }
+}
 
 pub fn init_io(io: SocketIo) -> Result<()> {
     let io_clone = io.clone();
-    io.ns("/", move |s: SocketRef, State(state): State<AppState>| {
-        room_list::send_room_list_on_connect(s.clone(), state);
-
-        s.on("room.send", send_event::handle);
-        s.on("room.join", room_events::join_room);
-        s.on("room.leave", room_events::leave_room);
-        s.on("room.list", room_list::list_rooms);
-        s.on("room.create", room_list::create_room);
-        s.on("user.set_username", user_management::set_username);
-        s.on("room.get_members", user_management::get_room_members);
-        s.on("typing.start", typing::start_typing);
-        s.on("typing.stop", typing::stop_typing);
-        s.on("message.edit", message_management::handle_edit_message);
-        s.on("message.delete", message_management::handle_delete_message);
-        s.on("message.star", starred_messages::star_message);
-        s.on("message.unstar", starred_messages::unstar_message);
-        s.on(
-            "starred_messages.get",
-            starred_messages::get_starred_messages,
-        );
-
-        let io_for_disconnect = io_clone.clone();
-        s.on_disconnect(move |s: SocketRef, State(state): State<AppState>| {
-            let io = io_for_disconnect.clone();
-            async move {
-                user_management::handle_disconnect(s, io, state).await;
-            }
-        });
-    });
+    io.ns(
+        "/",
+        move |s: SocketRef, State(state): State<AppState>, auth: TryData<ConnectAuth>| {
+            session::start_session(&s, &state, auth);
+            room_list::send_room_list_on_connect(s.clone(), state);
+
+            s.on("room.send", send_event::handle);
+            s.on("room.join", room_events::join_room);
+            s.on("room.leave", room_events::leave_room);
+            s.on("room.list", room_list::list_rooms);
+            s.on("room.create", room_list::create_room);
+            s.on("room.set_policy", room_policy::set_room_policy);
+            s.on("room.get_policy", room_policy::get_room_policy);
+            s.on("room.set_slow_mode", rate_limits::set_slow_mode);
+            s.on("dm.open", direct_messages::open_direct_message);
+            s.on("dm.list", direct_messages::list_direct_messages);
+            s.on("user.set_username", user_management::set_username);
+            s.on("user.set_profile", profiles::set_profile);
+            s.on("user.get_profile", profiles::get_profile);
+            s.on("user.get_avatar", profiles::get_avatar);
+            s.on("user.block", blocking::block_user);
+            s.on("user.unblock", blocking::unblock_user);
+            s.on("user.get_blocked", blocking::get_blocked);
+            s.on("room.get_members", user_management::get_room_members);
+            s.on("typing.start", typing::start_typing);
+            s.on("typing.stop", typing::stop_typing);
+            s.on("message.edit", message_management::handle_edit_message);
+            s.on("message.delete", message_management::handle_delete_message);
+            s.on("message.star", starred_messages::star_message);
+            s.on("message.unstar", starred_messages::unstar_message);
+            s.on(
+                "starred_messages.get",
+                starred_messages::get_starred_messages,
+            );
+            s.on("message.pin", pinned_messages::pin_message);
+            s.on("message.unpin", pinned_messages::unpin_message);
+            s.on("pinned_messages.get", pinned_messages::get_pinned_messages);
+            s.on("poll.vote", polls::vote);
+            s.on("poll.close", polls::close);
+            s.on("bot.create", bots::create_bot);
+            s.on("bot.list", bots::list_bots);
+            s.on("bot.delete", bots::delete_bot);
+            s.on("bot.register_commands", bots::register_commands);
+            s.on("command.respond", bots::respond);
+            s.on("media.upload", media::upload_media);
+            s.on("media.get", media::get_media);
+            s.on("webhook.create", webhooks::create_webhook);
+            s.on("webhook.delete", webhooks::delete_webhook);
+            s.on("webhook.list", webhooks::list_webhooks);
+            s.on("webhook.deliveries", webhooks::get_deliveries);
+            s.on("webhook.redeliver", webhooks::redeliver);
+            s.on("incoming_webhook.create", webhooks::create_incoming_webhook);
+            s.on("incoming_webhook.delete", webhooks::delete_incoming_webhook);
+            s.on("incoming_webhook.list", webhooks::list_incoming_webhooks);
+            s.on("moderation.set_filters", moderation::set_filters);
+            s.on("moderation.get_filters", moderation::get_filters);
+            s.on("moderation.get_flagged", moderation::get_flagged);
+            s.on("message.report", reports::report_message);
+            s.on("moderation.get_reports", reports::get_reports);
+            s.on("moderation.resolve_report", reports::resolve_report);
+            s.on("moderation.get_log", reports::get_log);
+            s.on("audit.query", audit::query_audit_log);
+            s.on("mentions.get", mentions::get_mention_counts);
+            s.on("mentions.clear", mentions::clear_mentions);
+            s.on("message.schedule", scheduled_messages::schedule_message);
+            s.on(
+                "scheduled_messages.edit",
+                scheduled_messages::edit_scheduled_message,
+            );
+            s.on(
+                "scheduled_messages.cancel",
+                scheduled_messages::cancel_scheduled_message,
+            );
+            s.on(
+                "scheduled_messages.get",
+                scheduled_messages::get_scheduled_messages,
+            );
+
+            let io_for_disconnect = io_clone.clone();
+            s.on_disconnect(move |s: SocketRef, State(state): State<AppState>| {
+                let io = io_for_disconnect.clone();
+                async move {
+                    user_management::handle_disconnect(s, io, state).await;
+                }
+            });
+        },
+    );
 
     Ok(())
 }
+
+/// A socket.io instance with the default namespace but no connections, for
+/// tests that broadcast.
+#[cfg(test)]
+pub(crate) fn test_io() -> SocketIo {
+    let (_, io) = SocketIo::new_layer();
+    io.ns("/", |_: SocketRef| {});
+    io
+}
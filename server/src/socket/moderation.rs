@@ -0,0 +1,223 @@
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    models::FormattedBody,
+    moderation::{FlaggedMessage, ModerationConfig},
+    socket::{ErrorResponse, audit, emit_error, session},
+    state::AppState,
+};
+
+/// Filter rules can reveal what they look for, so only moderators see them.
+const NOT_MODERATOR: &str = "Only moderators can manage message filters";
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SetFiltersPayload {
+    pub room_id: Uuid,
+    /// Replaces every rule of the room. No rules turns filtering off.
+    pub config: ModerationConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ModerationRoomPayload {
+    pub room_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct FiltersResponse {
+    pub room_id: Uuid,
+    pub config: ModerationConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct FlaggedListResponse {
+    pub room_id: Uuid,
+    /// Oldest first.
+    pub messages: Vec<FlaggedMessage>,
+}
+
+pub async fn set_filters(
+    s: SocketRef,
+    Data(data): Data<SetFiltersPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let rules = data.config.rules.len();
+    let before = state.moderation.config(data.room_id);
+    let after = audit::describe(&data.config);
+    if let Err(e) = state.moderation.set_config(data.room_id, data.config) {
+        emit_error(&s, &e);
+        return;
+    }
+    state.audit.record(
+        audit::entry(&state, AuditAction::FiltersChanged, s.id)
+            .in_room(data.room_id)
+            .target(data.room_id)
+            .change(audit::describe(&before), after),
+    );
+
+    info!(
+        "User {} set {} filter rules in room {}",
+        s.id, rules, data.room_id
+    );
+
+    emit_filters(&s, &state, data.room_id);
+}
+
+pub async fn get_filters(
+    s: SocketRef,
+    Data(data): Data<ModerationRoomPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    emit_filters(&s, &state, data.room_id);
+}
+
+pub async fn get_flagged(
+    s: SocketRef,
+    Data(data): Data<ModerationRoomPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let response = FlaggedListResponse {
+        room_id: data.room_id,
+        messages: state.moderation.flagged(data.room_id),
+    };
+    if let Err(e) = s.emit("moderation.flagged_list", &response) {
+        error!("Failed to send flagged messages to {}: {}", s.id, e);
+    }
+}
+
+/// Runs the room's filters over a message on behalf of `from`, masking it in
+/// place. A masked message loses its formatted body so the hidden text cannot
+/// survive there. Returns the reasons to flag the message for, or a message
+/// for the sender if it is rejected.
+pub fn screen(
+    state: &AppState,
+    room_id: Uuid,
+    from: Sid,
+    content: &mut String,
+    formatted: &mut Option<FormattedBody>,
+) -> Result<Vec<String>, String> {
+    let outcome = state
+        .moderation
+        .screen(room_id, content)
+        .map_err(|reason| {
+            info!(
+                "Filters in room {} rejected a message from {}: {}",
+                room_id, from, reason
+            );
+            format!("Your message was blocked because it {reason}")
+        })?;
+
+    if outcome.masked {
+        *formatted = None;
+    }
+
+    Ok(outcome.flags)
+}
+
+/// Records a delivered message for review and tells the room's moderators
+/// about it.
+pub fn flag(
+    io: &SocketIo,
+    state: &AppState,
+    room_id: Uuid,
+    message_id: Uuid,
+    from: Sid,
+    content: &str,
+    reasons: Vec<String>,
+) {
+    if reasons.is_empty() {
+        return;
+    }
+
+    let flagged = FlaggedMessage {
+        id: Uuid::new_v4(),
+        room_id,
+        message_id,
+        user_id: from,
+        username: state.usernames.get(&from).map(|name| name.clone()),
+        content: content.to_string(),
+        reasons,
+        flagged_at: Utc::now(),
+    };
+
+    info!(
+        "Flagged message {} from {} in room {}: {}",
+        message_id,
+        from,
+        room_id,
+        flagged.reasons.join(", ")
+    );
+
+    emit_to_moderators(io, state, room_id, "moderation.flagged", &flagged);
+    state.moderation.flag(flagged);
+}
+
+/// Sends an event to every connected moderator of a room.
+pub fn emit_to_moderators<T: Serialize + ?Sized>(
+    io: &SocketIo,
+    state: &AppState,
+    room_id: Uuid,
+    event: &str,
+    payload: &T,
+) {
+    let moderators: Vec<Uuid> = state
+        .rooms
+        .get(&room_id)
+        .map(|room| room.moderators.iter().copied().collect())
+        .unwrap_or_default();
+
+    for moderator in moderators
+        .into_iter()
+        .flat_map(|user_key| session::sockets_for(state, user_key))
+    {
+        let Some(socket) = io.get_socket(moderator) else {
+            continue;
+        };
+        if let Err(e) = socket.emit(event, payload) {
+            error!("Failed to send {} to moderator {}: {}", event, moderator, e);
+        }
+    }
+}
+
+fn emit_filters(s: &SocketRef, state: &AppState, room_id: Uuid) {
+    let response = FiltersResponse {
+        room_id,
+        config: state.moderation.config(room_id),
+    };
+
+    if let Err(e) = s.emit("moderation.filters", &response) {
+        error!(
+            "Failed to send filters of room {} to {}: {}",
+            room_id, s.id, e
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    models::{MessagePinEvent, MessageUnpinEvent, RoomEvent, RoomEventData},
+    socket::{emit_error, protocol, session},
+    state::AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct PinMessagePayload {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct UnpinMessagePayload {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct GetPinnedMessagesPayload {
+    pub room_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct PinnedMessagesResponse {
+    pub room_id: Uuid,
+    pub messages: Vec<RoomEvent>,
+}
+
+pub async fn pin_message(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<PinMessagePayload>,
+    State(state): State<AppState>,
+) {
+    let pin_event = {
+        let Some(mut room) = state.rooms.get_mut(&data.room_id) else {
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        if !room.can_pin(&s.id, &session::user_key(&state, s.id)) {
+            error!(
+                "User {} tried to pin message in room {} without permission",
+                s.id, data.room_id
+            );
+            emit_error(&s, "You are not allowed to pin messages in this room");
+            return;
+        }
+
+        let now = chrono::Utc::now();
+        let pinnable = room
+            .events
+            .iter()
+            .any(|event| event.id == data.message_id && event.data.is_live_message(now));
+
+        if !pinnable {
+            emit_error(&s, "Message does not exist");
+            return;
+        }
+
+        if room.pinned.contains(&data.message_id) {
+            emit_error(&s, "Message is already pinned");
+            return;
+        }
+
+        room.pinned.push(data.message_id);
+
+        let pin_event = RoomEvent {
+            id: Uuid::new_v4(),
+            from: s.id,
+            timestamp: chrono::Utc::now(),
+            data: RoomEventData::MessagePin(MessagePinEvent {
+                message_id: data.message_id,
+            }),
+            sender_key: None,
+        };
+        room.events.push(pin_event.clone());
+        pin_event
+    };
+
+    protocol::broadcast_room_event(&io, &state, data.room_id, &pin_event).await;
+
+    info!(
+        "User {} pinned message {} in room {}",
+        s.id, data.message_id, data.room_id
+    );
+}
+
+pub async fn unpin_message(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<UnpinMessagePayload>,
+    State(state): State<AppState>,
+) {
+    let unpin_event = {
+        let Some(mut room) = state.rooms.get_mut(&data.room_id) else {
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        if !room.can_pin(&s.id, &session::user_key(&state, s.id)) {
+            error!(
+                "User {} tried to unpin message in room {} without permission",
+                s.id, data.room_id
+            );
+            emit_error(&s, "You are not allowed to unpin messages in this room");
+            return;
+        }
+
+        let Some(position) = room.pinned.iter().position(|id| *id == data.message_id) else {
+            emit_error(&s, "Message is not pinned");
+            return;
+        };
+
+        room.pinned.remove(position);
+
+        let unpin_event = RoomEvent {
+            id: Uuid::new_v4(),
+            from: s.id,
+            timestamp: chrono::Utc::now(),
+            data: RoomEventData::MessageUnpin(MessageUnpinEvent {
+                message_id: data.message_id,
+            }),
+            sender_key: None,
+        };
+        room.events.push(unpin_event.clone());
+        unpin_event
+    };
+
+    protocol::broadcast_room_event(&io, &state, data.room_id, &unpin_event).await;
+
+    info!(
+        "User {} unpinned message {} in room {}",
+        s.id, data.message_id, data.room_id
+    );
+}
+
+pub async fn get_pinned_messages(
+    s: SocketRef,
+    Data(data): Data<GetPinnedMessagesPayload>,
+    State(state): State<AppState>,
+) {
+    let response = {
+        let Some(room) = state.rooms.get(&data.room_id) else {
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        if !room.members.contains(&s.id) {
+            emit_error(&s, "You are not a member of this room");
+            return;
+        }
+
+        let client = protocol::client_protocol(&state, s.id);
+        let now = chrono::Utc::now();
+        // Expired messages are unpinned once the expiry task redacts them.
+        let messages = room
+            .pinned
+            .iter()
+            .filter_map(|id| room.events.iter().find(|event| event.id == *id))
+            .filter(|event| event.data.is_live_message(now))
+            .filter_map(|event| protocol::represent(event, client.as_ref()))
+            .map(|event| event.into_owned())
+            .collect();
+
+        PinnedMessagesResponse {
+            room_id: data.room_id,
+            messages,
+        }
+    };
+
+    if let Err(e) = s.emit("pinned_messages.list", &response) {
+        error!("Failed to emit pinned messages list: {}", e);
+    }
+}
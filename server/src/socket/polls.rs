@@ -0,0 +1,240 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::info;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    models::{PollBallot, PollEvent, RoomEvent, RoomEventData},
+    socket::{emit_error, protocol, session},
+    state::AppState,
+};
+
+const MAX_QUESTION_CHARS: usize = 300;
+const MAX_OPTION_CHARS: usize = 100;
+const MIN_OPTIONS: usize = 2;
+const MAX_OPTIONS: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct VotePollPayload {
+    pub room_id: Uuid,
+    pub poll_id: Uuid,
+    /// The chosen option indexes. An empty list retracts the vote.
+    pub options: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ClosePollPayload {
+    pub room_id: Uuid,
+    pub poll_id: Uuid,
+}
+
+/// Validates a newly sent poll and resets everything the client must not
+/// control: tallies, voters and the closed flag.
+pub fn prepare(poll: &mut PollEvent, now: DateTime<Utc>) -> Result<(), &'static str> {
+    poll.question = poll.question.trim().to_string();
+    if poll.question.is_empty() {
+        return Err("Poll question cannot be empty");
+    }
+    if poll.question.chars().count() > MAX_QUESTION_CHARS {
+        return Err("Poll question is too long");
+    }
+
+    if !(MIN_OPTIONS..=MAX_OPTIONS).contains(&poll.options.len()) {
+        return Err("Polls need between 2 and 10 options");
+    }
+
+    for option in &mut poll.options {
+        option.text = option.text.trim().to_string();
+        if option.text.is_empty() {
+            return Err("Poll options cannot be empty");
+        }
+        if option.text.chars().count() > MAX_OPTION_CHARS {
+            return Err("Poll option is too long");
+        }
+    }
+
+    if poll.closes_at.is_some_and(|closes_at| closes_at <= now) {
+        return Err("Poll close time must be in the future");
+    }
+
+    poll.closed = false;
+    poll.ballots.clear();
+    poll.recount();
+
+    Ok(())
+}
+
+pub async fn vote(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<VotePollPayload>,
+    State(state): State<AppState>,
+) {
+    let update = {
+        let Some(mut room) = state.rooms.get_mut(&data.room_id) else {
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        if !room.members.contains(&s.id) {
+            emit_error(&s, "You are not a member of this room");
+            return;
+        }
+
+        let Some((author, poll)) = find_poll(&mut room.events, data.poll_id) else {
+            emit_error(&s, "Poll does not exist");
+            return;
+        };
+
+        if !poll.is_open(Utc::now()) {
+            emit_error(&s, "Poll is closed");
+            return;
+        }
+
+        let mut choices = data.options.clone();
+        choices.sort_unstable();
+        choices.dedup();
+
+        if choices.iter().any(|index| *index >= poll.options.len()) {
+            emit_error(&s, "Invalid poll option");
+            return;
+        }
+
+        if !poll.multiple_choice && choices.len() > 1 {
+            emit_error(&s, "This poll only allows one choice");
+            return;
+        }
+
+        let user_key = session::user_key(&state, s.id);
+        if choices.is_empty() {
+            poll.ballots.remove(&user_key);
+        } else {
+            poll.ballots.insert(
+                user_key,
+                PollBallot {
+                    voter: s.id,
+                    choices,
+                },
+            );
+        }
+        poll.recount();
+
+        // Sent as the poll's author so anonymous votes cannot be attributed.
+        update_event(author, data.poll_id, poll)
+    };
+
+    protocol::broadcast_room_event(&io, &state, data.room_id, &update).await;
+
+    info!(
+        "User {} voted on poll {} in room {}",
+        s.id, data.poll_id, data.room_id
+    );
+}
+
+pub async fn close(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<ClosePollPayload>,
+    State(state): State<AppState>,
+) {
+    let update = {
+        let Some(mut room) = state.rooms.get_mut(&data.room_id) else {
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        let is_moderator = room.is_moderator(&session::user_key(&state, s.id));
+
+        let Some(event) = room
+            .events
+            .iter_mut()
+            .find(|event| event.id == data.poll_id)
+        else {
+            emit_error(&s, "Poll does not exist");
+            return;
+        };
+
+        if event.from != s.id && !is_moderator {
+            emit_error(&s, "Only the poll creator or a moderator can close it");
+            return;
+        }
+
+        let RoomEventData::Poll(poll) = &mut event.data else {
+            emit_error(&s, "Poll does not exist");
+            return;
+        };
+
+        if poll.closed {
+            emit_error(&s, "Poll is already closed");
+            return;
+        }
+
+        poll.closed = true;
+        update_event(s.id, data.poll_id, poll)
+    };
+
+    protocol::broadcast_room_event(&io, &state, data.room_id, &update).await;
+
+    info!(
+        "User {} closed poll {} in room {}",
+        s.id, data.poll_id, data.room_id
+    );
+}
+
+/// Closes every poll whose close time has passed and broadcasts the final
+/// results.
+pub async fn close_expired(io: &SocketIo, state: &AppState) {
+    let now = Utc::now();
+    let mut updates = Vec::new();
+
+    for mut entry in state.rooms.iter_mut() {
+        let room = entry.value_mut();
+
+        for event in &mut room.events {
+            let RoomEventData::Poll(poll) = &mut event.data else {
+                continue;
+            };
+
+            if !poll.closed && !poll.is_open(now) {
+                poll.closed = true;
+                updates.push((room.id, update_event(event.from, event.id, poll)));
+            }
+        }
+    }
+
+    for (room_id, update) in updates {
+        protocol::broadcast_room_event(io, state, room_id, &update).await;
+    }
+}
+
+/// The poll and the socket that created it.
+fn find_poll(events: &mut [RoomEvent], poll_id: Uuid) -> Option<(Sid, &mut PollEvent)> {
+    events
+        .iter_mut()
+        .find(|event| event.id == poll_id)
+        .and_then(|event| match &mut event.data {
+            RoomEventData::Poll(poll) => Some((event.from, poll)),
+            _ => None,
+        })
+}
+
+/// Poll updates are only broadcast; the poll event itself holds the current
+/// results for anyone loading the history later.
+fn update_event(from: Sid, poll_id: Uuid, poll: &PollEvent) -> RoomEvent {
+    RoomEvent {
+        id: Uuid::new_v4(),
+        from,
+        timestamp: Utc::now(),
+        data: RoomEventData::PollUpdate(poll.update_event(poll_id)),
+        sender_key: None,
+    }
+}
@@ -0,0 +1,284 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    models::UserProfile,
+    socket::{ErrorResponse, HandlerError, emit_error, session, user_management, validation},
+    state::AppState,
+};
+
+const MAX_DISPLAY_NAME_CHARS: usize = 64;
+const MAX_BIO_CHARS: usize = 300;
+const MAX_TIMEZONE_CHARS: usize = 64;
+const MAX_AVATAR_BYTES: usize = 512 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SetProfilePayload {
+    /// Replaces the whole profile. Upload a new avatar with `media.upload`
+    /// first and pass its blob id.
+    pub profile: UserProfile,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct GetProfilePayload {
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ProfileResponse {
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+    pub username: Option<String>,
+    pub profile: UserProfile,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct AvatarResponse {
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+    pub avatar_id: Uuid,
+    pub mime_type: String,
+    /// Base64 encoded image.
+    pub data: String,
+}
+
+pub async fn set_profile(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<SetProfilePayload>,
+    State(state): State<AppState>,
+) {
+    let user_key = session::user_key(&state, s.id);
+
+    let profile = match update_profile(&state, user_key, data.profile) {
+        Ok(profile) => profile,
+        Err(e) => {
+            let _ = s.emit("error", &ErrorResponse::from(e));
+            return;
+        }
+    };
+
+    info!("User {} updated their profile", s.id);
+
+    emit_profile(&s, &state, s.id, profile);
+
+    // Member lists show the display name and avatar of every socket the
+    // user has open.
+    let sockets = session::sockets_for(&state, user_key);
+    let rooms: Vec<Uuid> = state
+        .rooms
+        .iter()
+        .filter(|room| sockets.iter().any(|sid| room.members.contains(sid)))
+        .map(|room| room.id)
+        .collect();
+    for room_id in rooms {
+        user_management::send_updated_members_to_room(&io, &state, room_id).await;
+    }
+}
+
+pub async fn get_profile(
+    s: SocketRef,
+    Data(data): Data<GetProfilePayload>,
+    State(state): State<AppState>,
+) {
+    let Some(profile) = profile_of(&state, data.user_id) else {
+        emit_error(&s, "User does not exist");
+        return;
+    };
+
+    emit_profile(&s, &state, data.user_id, profile);
+}
+
+pub async fn get_avatar(
+    s: SocketRef,
+    Data(data): Data<GetProfilePayload>,
+    State(state): State<AppState>,
+) {
+    let Some(avatar_id) = profile_of(&state, data.user_id).and_then(|profile| profile.avatar_id)
+    else {
+        emit_error(&s, "User has no avatar");
+        return;
+    };
+
+    let Some(blob) = state.media.get(&avatar_id) else {
+        emit_error(&s, "User has no avatar");
+        return;
+    };
+
+    let response = AvatarResponse {
+        user_id: data.user_id,
+        avatar_id,
+        mime_type: blob.mime_type,
+        data: STANDARD.encode(&blob.data),
+    };
+
+    if let Err(e) = s.emit("user.avatar", &response) {
+        error!("Failed to send avatar {} to {}: {}", avatar_id, s.id, e);
+    }
+}
+
+/// The profile of the user behind `user_id`, which is empty if they never set
+/// one. Returns `None` for ids that belong to nobody.
+pub fn profile_of(state: &AppState, user_id: Sid) -> Option<UserProfile> {
+    let user_key = *state.user_keys.get(&user_id)?;
+    Some(
+        state
+            .profiles
+            .get(&user_key)
+            .map(|profile| profile.clone())
+            .unwrap_or_default(),
+    )
+}
+
+fn update_profile(
+    state: &AppState,
+    user_key: Uuid,
+    profile: UserProfile,
+) -> Result<UserProfile, HandlerError> {
+    let display_name = optional_text(
+        profile.display_name.as_deref().map(validation::clean_line),
+        "Display names",
+        MAX_DISPLAY_NAME_CHARS,
+    )?;
+    let bio = optional_text(
+        profile.bio.as_deref().map(validation::clean_text),
+        "Bios",
+        MAX_BIO_CHARS,
+    )?;
+    let timezone = profile
+        .timezone
+        .as_deref()
+        .map(validation::clean_line)
+        .filter(|timezone| !timezone.is_empty());
+    if let Some(display_name) = &display_name
+        && state
+            .username_registry
+            .check(user_key, display_name)
+            .is_err()
+    {
+        return Err(HandlerError::Invalid(
+            "Display names cannot be a reserved name or someone else's username".to_string(),
+        ));
+    }
+    if let Some(timezone) = &timezone
+        && !is_timezone(timezone)
+    {
+        return Err(HandlerError::Invalid(format!(
+            "{timezone} is not a time zone name"
+        )));
+    }
+
+    let previous_avatar = state
+        .profiles
+        .get(&user_key)
+        .and_then(|profile| profile.avatar_id);
+
+    if let Some(avatar_id) = profile.avatar_id
+        && Some(avatar_id) != previous_avatar
+    {
+        claim_avatar(state, user_key, avatar_id)?;
+    }
+
+    let profile = UserProfile {
+        display_name,
+        avatar_id: profile.avatar_id,
+        bio,
+        timezone,
+    };
+    state.profiles.insert(user_key, profile.clone());
+
+    if let Some(previous_avatar) = previous_avatar
+        && profile.avatar_id != Some(previous_avatar)
+    {
+        state.media.remove(&previous_avatar);
+    }
+
+    Ok(profile)
+}
+
+fn claim_avatar(state: &AppState, user_key: Uuid, avatar_id: Uuid) -> Result<(), HandlerError> {
+    let blob = state
+        .media
+        .get(&avatar_id)
+        .ok_or(HandlerError::NotFound("Avatar upload does not exist"))?;
+
+    if !blob.mime_type.starts_with("image/") {
+        return Err(HandlerError::Invalid("Avatars must be images".to_string()));
+    }
+    if blob.data.len() > MAX_AVATAR_BYTES {
+        return Err(HandlerError::Invalid(format!(
+            "Avatars are limited to {} KB",
+            MAX_AVATAR_BYTES / 1024
+        )));
+    }
+
+    state
+        .media
+        .claim(&avatar_id, user_key)
+        .map(|_| ())
+        .map_err(|e| HandlerError::Invalid(e.to_string()))
+}
+
+/// Empty values clear the field.
+fn optional_text(
+    text: Option<String>,
+    what: &str,
+    max_chars: usize,
+) -> Result<Option<String>, HandlerError> {
+    let text = text.filter(|text| !text.trim().is_empty());
+    if text
+        .as_ref()
+        .is_some_and(|text| text.chars().count() > max_chars)
+    {
+        return Err(HandlerError::Invalid(format!(
+            "{what} are limited to {max_chars} characters"
+        )));
+    }
+    Ok(text)
+}
+
+/// Accepts `UTC` and names shaped like `Area/Location`, such as
+/// `America/Argentina/Buenos_Aires` or `Etc/GMT+5`.
+fn is_timezone(name: &str) -> bool {
+    if name == "UTC" {
+        return true;
+    }
+
+    name.chars().count() <= MAX_TIMEZONE_CHARS
+        && name.contains('/')
+        && name.split('/').all(|part| {
+            part.starts_with(|c: char| c.is_ascii_uppercase())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+        })
+}
+
+fn emit_profile(s: &SocketRef, state: &AppState, user_id: Sid, profile: UserProfile) {
+    let response = ProfileResponse {
+        user_id,
+        username: state.usernames.get(&user_id).map(|name| name.clone()),
+        profile,
+    };
+
+    if let Err(e) = s.emit("user.profile", &response) {
+        error!("Failed to send profile to {}: {}", s.id, e);
+    }
+}
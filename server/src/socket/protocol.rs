@@ -0,0 +1,369 @@
+//! Protocol versioning and capability negotiation.
+//!
+//! Clients announce the protocol version they were built against, and
+//! optionally the capabilities they understand, in the connect handshake.
+//! Room events that depend on a capability the client lacks are downgraded
+//! to something an older client can decode, usually a plain text message,
+//! or left out when they only add detail to an earlier event.
+
+use std::{borrow::Cow, collections::HashSet};
+
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{SocketIo, extract::SocketRef, socket::Sid};
+use tracing::{error, warn};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    models::{ReplyMessageType, RoomEvent, RoomEventData, TextMessageEvent},
+    socket::blocking,
+    state::AppState,
+};
+
+/// The protocol version this server speaks.
+pub const PROTOCOL_VERSION: u32 = 3;
+/// The oldest protocol version still accepted. Version 1 is the original
+/// protocol, spoken by clients that do not send a version at all.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Optional protocol features.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, TS, JsonSchema)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Markdown,
+    Mentions,
+    LinkPreviews,
+    Pins,
+    Polls,
+    Audio,
+    DirectMessages,
+    ScheduledMessages,
+    RoomTopics,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 9] = [
+        Capability::Markdown,
+        Capability::Mentions,
+        Capability::LinkPreviews,
+        Capability::Pins,
+        Capability::Polls,
+        Capability::Audio,
+        Capability::DirectMessages,
+        Capability::ScheduledMessages,
+        Capability::RoomTopics,
+    ];
+
+    /// The protocol version that introduced the capability.
+    pub fn introduced_in(self) -> u32 {
+        match self {
+            Capability::RoomTopics => 3,
+            _ => 2,
+        }
+    }
+
+    /// Parses a capability name, ignoring names this server does not know so
+    /// newer clients can still connect.
+    fn parse(name: &str) -> Option<Capability> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+}
+
+/// What a connected client has said it understands.
+#[derive(Debug, Clone)]
+pub struct ClientProtocol {
+    pub version: u32,
+    pub capabilities: HashSet<Capability>,
+}
+
+impl ClientProtocol {
+    /// Negotiates the protocol for a client. Returns `None` if the client is
+    /// too old to be served.
+    pub fn negotiate(version: Option<u32>, capabilities: Option<&[String]>) -> Option<Self> {
+        let requested = version.unwrap_or(MIN_PROTOCOL_VERSION);
+        if requested < MIN_PROTOCOL_VERSION {
+            return None;
+        }
+        let version = requested.min(PROTOCOL_VERSION);
+
+        let capabilities = match capabilities {
+            Some(names) => names
+                .iter()
+                .filter_map(|name| Capability::parse(name))
+                .filter(|capability| capability.introduced_in() <= version)
+                .collect(),
+            None => Capability::ALL
+                .into_iter()
+                .filter(|capability| capability.introduced_in() <= version)
+                .collect(),
+        };
+
+        Some(Self {
+            version,
+            capabilities,
+        })
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// The capability a room event needs, if any.
+fn required_capability(data: &RoomEventData) -> Option<Capability> {
+    match data {
+        RoomEventData::Audio(_) => Some(Capability::Audio),
+        RoomEventData::Poll(_) | RoomEventData::PollUpdate(_) => Some(Capability::Polls),
+        RoomEventData::LinkPreview(_) => Some(Capability::LinkPreviews),
+        RoomEventData::MessagePin(_) | RoomEventData::MessageUnpin(_) => Some(Capability::Pins),
+        RoomEventData::TopicChange(_) => Some(Capability::RoomTopics),
+        RoomEventData::Message(message) => message
+            .reply_to
+            .as_ref()
+            .filter(|reply| matches!(reply.message_type, ReplyMessageType::Audio))
+            .map(|_| Capability::Audio),
+        RoomEventData::Image(image) => image
+            .reply_to
+            .as_ref()
+            .filter(|reply| matches!(reply.message_type, ReplyMessageType::Audio))
+            .map(|_| Capability::Audio),
+        _ => None,
+    }
+}
+
+/// Returns how `event` should look to `client`, or `None` if the client
+/// should not receive it at all.
+pub fn represent<'a>(
+    event: &'a RoomEvent,
+    client: Option<&ClientProtocol>,
+) -> Option<Cow<'a, RoomEvent>> {
+    let Some(client) = client else {
+        return Some(Cow::Borrowed(event));
+    };
+
+    match required_capability(&event.data) {
+        Some(capability) if !client.supports(capability) => downgrade(event).map(Cow::Owned),
+        _ => Some(Cow::Borrowed(event)),
+    }
+}
+
+fn downgrade(event: &RoomEvent) -> Option<RoomEvent> {
+    let data = match &event.data {
+        RoomEventData::Audio(audio) => {
+            let content = if audio.deleted {
+                String::new()
+            } else {
+                let seconds = audio.duration_ms / 1000;
+                format!(
+                    "🎤 Voice message ({}:{:02}). Update the app to play it.",
+                    seconds / 60,
+                    seconds % 60
+                )
+            };
+            RoomEventData::Message(TextMessageEvent {
+                deleted: audio.deleted,
+                reply_to: audio.reply_to.clone(),
+                expires_at: audio.expires_at,
+                ..fallback_message(content)
+            })
+        }
+        RoomEventData::Poll(poll) => {
+            let mut content = format!("📊 Poll: {}", poll.question);
+            for (index, option) in poll.options.iter().enumerate() {
+                content.push_str(&format!(
+                    "\n{}. {} ({})",
+                    index + 1,
+                    option.text,
+                    option.votes
+                ));
+            }
+            if poll.closed {
+                content.push_str("\nThis poll is closed.");
+            } else {
+                content.push_str("\nUpdate the app to vote.");
+            }
+            RoomEventData::Message(fallback_message(content))
+        }
+        RoomEventData::TopicChange(change) => RoomEventData::Message(fallback_message(format!(
+            "📝 Topic changed to: {}",
+            change.topic
+        ))),
+        RoomEventData::Message(message) => {
+            let mut message = message.clone();
+            if let Some(reply) = &mut message.reply_to {
+                reply.message_type = ReplyMessageType::Text;
+            }
+            RoomEventData::Message(message)
+        }
+        RoomEventData::Image(image) => {
+            let mut image = image.clone();
+            if let Some(reply) = &mut image.reply_to {
+                reply.message_type = ReplyMessageType::Text;
+            }
+            RoomEventData::Image(image)
+        }
+        // These only add detail to events the client has already seen.
+        _ => return None,
+    };
+
+    Some(RoomEvent {
+        data,
+        ..event.clone()
+    })
+}
+
+fn fallback_message(content: String) -> TextMessageEvent {
+    TextMessageEvent {
+        content,
+        edited: false,
+        deleted: false,
+        reply_to: None,
+        expires_at: None,
+        mentions: Vec::new(),
+        mentions_room: false,
+        formatted: None,
+        link_previews: Vec::new(),
+        emote: false,
+    }
+}
+
+/// A room's history as the user with `viewer_key` should see it. Messages
+/// whose TTL has run out read as redacted even before the expiry task gets to
+/// them, and messages from users the viewer blocked are left out.
+pub fn history<'a>(
+    state: &AppState,
+    events: &'a [RoomEvent],
+    viewer_key: Uuid,
+) -> Vec<Cow<'a, RoomEvent>> {
+    let now = Utc::now();
+    let blocked = state
+        .blocks
+        .get(&viewer_key)
+        .map(|blocked| blocked.clone())
+        .unwrap_or_default();
+
+    events
+        .iter()
+        .filter(|event| {
+            blocked.is_empty()
+                || !blocking::hides(&event.data)
+                || event.sender_key.is_none_or(|from| !blocked.contains(&from))
+        })
+        .map(|event| match event.data.expires_at() {
+            Some(expires_at) if expires_at <= now => {
+                let mut event = event.clone();
+                event.data.redact();
+                Cow::Owned(event)
+            }
+            _ => Cow::Borrowed(event),
+        })
+        .collect()
+}
+
+pub fn client_protocol(state: &AppState, sid: Sid) -> Option<ClientProtocol> {
+    state
+        .client_protocols
+        .get(&sid)
+        .map(|protocol| protocol.clone())
+}
+
+/// Sends a room event to one socket in the representation it understands.
+pub fn emit_room_event(s: &SocketRef, state: &AppState, event: &RoomEvent) {
+    let client = client_protocol(state, s.id);
+    let Some(event) = represent(event, client.as_ref()) else {
+        return;
+    };
+
+    if let Err(e) = s.emit("room.event", &*event) {
+        warn!("Failed to send room event to {}: {}", s.id, e);
+    }
+}
+
+/// Broadcasts a new room event to everyone in the room, downgrading it for
+/// clients that do not support it, and hands it to the room's webhooks.
+pub async fn broadcast_room_event(
+    io: &SocketIo,
+    state: &AppState,
+    room_id: Uuid,
+    event: &RoomEvent,
+) {
+    state.webhooks.dispatch(room_id, event);
+
+    let blockers = if blocking::hides(&event.data) {
+        event
+            .sender_key
+            .map(|from| blocking::blockers_of_key(state, from))
+            .unwrap_or_default()
+    } else {
+        HashSet::new()
+    };
+
+    let everyone_supports =
+        match required_capability(&event.data) {
+            None => true,
+            Some(capability) => io.to(room_id.to_string()).sockets().iter().all(|s| {
+                client_protocol(state, s.id).is_none_or(|client| client.supports(capability))
+            }),
+        };
+
+    if everyone_supports && blockers.is_empty() {
+        if let Err(e) = io.to(room_id.to_string()).emit("room.event", event).await {
+            error!("Failed to broadcast event to room {}: {}", room_id, e);
+        }
+        return;
+    }
+
+    for s in io.to(room_id.to_string()).sockets() {
+        if !blockers.contains(&s.id) {
+            emit_room_event(&s, state, event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::Room,
+        socket::{
+            send_event::{self, SendEventPayload},
+            test_io,
+        },
+    };
+
+    #[tokio::test]
+    async fn hides_blocked_senders_after_they_disconnect() {
+        let state = AppState::from_env();
+        let sender = Sid::new();
+        let sender_key = Uuid::new_v4();
+        state.user_keys.insert(sender, sender_key);
+        let mut room = Room::new(Uuid::new_v4(), "room".to_string());
+        room.members.insert(sender);
+        let room_id = room.id;
+        state.rooms.insert(room_id, room);
+
+        send_event::deliver(
+            &test_io(),
+            &state,
+            sender,
+            SendEventPayload {
+                room: room_id,
+                payload: RoomEventData::Message(fallback_message("hello".to_string())),
+                ttl_secs: None,
+            },
+        )
+        .await
+        .unwrap();
+        state.user_keys.remove(&sender);
+
+        let viewer_key = Uuid::new_v4();
+        state.blocks.insert(viewer_key, HashSet::from([sender_key]));
+
+        let room = state.rooms.get(&room_id).unwrap();
+        assert!(history(&state, &room.events, viewer_key).is_empty());
+        assert_eq!(history(&state, &room.events, Uuid::new_v4()).len(), 1);
+    }
+}
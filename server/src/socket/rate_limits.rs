@@ -0,0 +1,167 @@
+use chrono::{Duration, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    models::SlowMode,
+    rate_limit::{MAX_SLOW_MODE_SECS, RateLimited},
+    socket::{ErrorResponse, HandlerError, audit, session},
+    state::AppState,
+};
+
+/// The longest slow mode may be switched on for at once.
+const MAX_SLOW_MODE_DURATION_SECS: u32 = 24 * 60 * 60;
+
+/// Sent instead of `error` when a request is refused for being too frequent.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct RateLimitError {
+    /// The event that was refused.
+    pub event: String,
+    pub message: String,
+    /// How long to wait before the request will be accepted.
+    pub retry_after_ms: u64,
+    #[serde(default)]
+    pub room_id: Option<Uuid>,
+    /// Refused by the room's slow mode rather than the user's own limit.
+    #[serde(default)]
+    pub slow_mode: bool,
+}
+
+impl RateLimitError {
+    pub fn new(limited: &RateLimited, room_id: Option<Uuid>) -> Self {
+        Self {
+            event: limited.action.event().to_string(),
+            message: limited.to_string(),
+            retry_after_ms: limited.retry_after.as_millis() as u64,
+            room_id,
+            slow_mode: limited.slow_mode,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SetSlowModePayload {
+    pub room_id: Uuid,
+    /// Seconds each member must wait between messages. 0 turns slow mode
+    /// off.
+    pub interval_secs: u32,
+    /// Turn slow mode off again after this many seconds.
+    #[serde(default)]
+    pub duration_secs: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SlowModeResponse {
+    pub room_id: Uuid,
+    pub slow_mode: Option<SlowMode>,
+}
+
+pub fn emit_rate_limited(s: &SocketRef, room_id: Option<Uuid>, limited: &RateLimited) {
+    let _ = s.emit("rate_limited", &RateLimitError::new(limited, room_id));
+}
+
+pub async fn set_slow_mode(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<SetSlowModePayload>,
+    State(state): State<AppState>,
+) {
+    let slow_mode = match update_slow_mode(&state, &data, s.id) {
+        Ok(slow_mode) => slow_mode,
+        Err(e) => {
+            let _ = s.emit("error", &ErrorResponse::from(e));
+            return;
+        }
+    };
+
+    match &slow_mode {
+        Some(slow_mode) => info!(
+            "User {} set slow mode of room {} to {}s",
+            s.id, data.room_id, slow_mode.interval_secs
+        ),
+        None => info!(
+            "User {} turned off slow mode in room {}",
+            s.id, data.room_id
+        ),
+    }
+
+    broadcast(&io, data.room_id, slow_mode).await;
+}
+
+fn update_slow_mode(
+    state: &AppState,
+    data: &SetSlowModePayload,
+    user: Sid,
+) -> Result<Option<SlowMode>, HandlerError> {
+    let user_key = session::user_key(state, user);
+    let mut room = state
+        .rooms
+        .get_mut(&data.room_id)
+        .ok_or(HandlerError::NotFound("Room does not exist"))?;
+
+    if !room.is_moderator(&user_key) {
+        return Err(HandlerError::Forbidden(
+            "Only moderators can change slow mode",
+        ));
+    }
+
+    if data.interval_secs > MAX_SLOW_MODE_SECS {
+        return Err(HandlerError::Invalid(format!(
+            "Slow mode interval is limited to {MAX_SLOW_MODE_SECS} seconds"
+        )));
+    }
+    if data
+        .duration_secs
+        .is_some_and(|secs| secs == 0 || secs > MAX_SLOW_MODE_DURATION_SECS)
+    {
+        return Err(HandlerError::Invalid(format!(
+            "Slow mode duration must be between 1 and {MAX_SLOW_MODE_DURATION_SECS} seconds"
+        )));
+    }
+
+    let before = std::mem::replace(
+        &mut room.slow_mode,
+        (data.interval_secs > 0).then(|| SlowMode {
+            interval_secs: data.interval_secs,
+            until: data
+                .duration_secs
+                .map(|secs| Utc::now() + Duration::seconds(i64::from(secs))),
+        }),
+    );
+    state.audit.record(
+        audit::entry(state, AuditAction::SlowModeChanged, user)
+            .in_room(data.room_id)
+            .target(data.room_id)
+            .change(
+                before.as_ref().and_then(audit::describe),
+                room.slow_mode.as_ref().and_then(audit::describe),
+            ),
+    );
+
+    Ok(room.slow_mode.clone())
+}
+
+/// Tells the members of a room that its slow mode changed.
+pub async fn broadcast(io: &SocketIo, room_id: Uuid, slow_mode: Option<SlowMode>) {
+    let response = SlowModeResponse { room_id, slow_mode };
+
+    if let Err(e) = io
+        .to(room_id.to_string())
+        .emit("room.slow_mode", &response)
+        .await
+    {
+        error!("Failed to broadcast slow mode of room {}: {}", room_id, e);
+    }
+}
@@ -0,0 +1,472 @@
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    models::{RoomEvent, RoomEventData},
+    moderation::{MAX_REASON_CHARS, ModerationLogEntry, ModeratorAction, Report, Resolution},
+    socket::{
+        ErrorResponse, HandlerError, audit, message_management,
+        moderation::{ModerationRoomPayload, emit_to_moderators},
+        send_event, session, user_management, validation,
+    },
+    state::AppState,
+};
+
+/// Reports name who filed them, so only moderators see the queue.
+const NOT_MODERATOR: &str = "Only moderators can review reports";
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ReportMessagePayload {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    pub reason: String,
+}
+
+/// Confirms a report to the member who filed it.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ReportReceipt {
+    pub report_id: Uuid,
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct GetReportsPayload {
+    pub room_id: Uuid,
+    /// Also list reports moderators have already dealt with.
+    #[serde(default)]
+    pub include_closed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ReportListResponse {
+    pub room_id: Uuid,
+    /// Oldest first.
+    pub reports: Vec<Report>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ResolveReportPayload {
+    pub room_id: Uuid,
+    pub report_id: Uuid,
+    pub action: ModeratorAction,
+    /// Shown to the author with a warning or ban, and kept in the log.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ModerationLogResponse {
+    pub room_id: Uuid,
+    /// Oldest first.
+    pub entries: Vec<ModerationLogEntry>,
+}
+
+/// Sent to every socket of a user a moderator warned.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ModerationWarning {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    pub reason: String,
+}
+
+/// Sent to every socket of a user banned from a room.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct RoomBannedNotice {
+    pub room_id: Uuid,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+pub async fn report_message(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<ReportMessagePayload>,
+    State(state): State<AppState>,
+) {
+    let report = match file_report(&state, s.id, data) {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = s.emit("error", &ErrorResponse::from(e));
+            return;
+        }
+    };
+
+    info!(
+        "User {} reported message {} in room {}",
+        s.id, report.message_id, report.room_id
+    );
+
+    let receipt = ReportReceipt {
+        report_id: report.id,
+        room_id: report.room_id,
+        message_id: report.message_id,
+    };
+    if let Err(e) = s.emit("message.reported", &receipt) {
+        error!("Failed to confirm report to {}: {}", s.id, e);
+    }
+
+    emit_to_moderators(&io, &state, report.room_id, "moderation.report", &report);
+}
+
+pub async fn get_reports(
+    s: SocketRef,
+    Data(data): Data<GetReportsPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let response = ReportListResponse {
+        room_id: data.room_id,
+        reports: state.reports.for_room(data.room_id, data.include_closed),
+    };
+    if let Err(e) = s.emit("moderation.reports", &response) {
+        error!("Failed to send reports to {}: {}", s.id, e);
+    }
+}
+
+pub async fn resolve_report(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<ResolveReportPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = resolve(&io, &state, s.id, data).await {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+    }
+}
+
+pub async fn get_log(
+    s: SocketRef,
+    Data(data): Data<ModerationRoomPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let response = ModerationLogResponse {
+        room_id: data.room_id,
+        entries: state.reports.log_for(data.room_id),
+    };
+    if let Err(e) = s.emit("moderation.log", &response) {
+        error!("Failed to send moderation log to {}: {}", s.id, e);
+    }
+}
+
+fn file_report(
+    state: &AppState,
+    reporter: Sid,
+    data: ReportMessagePayload,
+) -> Result<Report, HandlerError> {
+    send_event::ensure_member(state, data.room_id, reporter)?;
+
+    let reason = validation::clean_text(&data.reason).trim().to_string();
+    if reason.is_empty() {
+        return Err(HandlerError::Invalid("Reports need a reason".to_string()));
+    }
+    if reason.chars().count() > MAX_REASON_CHARS {
+        return Err(HandlerError::Invalid(format!(
+            "Report reasons are limited to {MAX_REASON_CHARS} characters"
+        )));
+    }
+
+    let message = state
+        .rooms
+        .get(&data.room_id)
+        .and_then(|room| {
+            room.events
+                .iter()
+                .find(|event| event.id == data.message_id)
+                .cloned()
+        })
+        .filter(is_reportable)
+        .ok_or(HandlerError::NotFound("Message does not exist"))?;
+
+    if message.from == reporter {
+        return Err(HandlerError::Invalid(
+            "You cannot report your own message".to_string(),
+        ));
+    }
+
+    let report = Report {
+        id: Uuid::new_v4(),
+        room_id: data.room_id,
+        message_id: data.message_id,
+        reported_user: message.from,
+        reported_username: state.usernames.get(&message.from).map(|name| name.clone()),
+        reporter,
+        reporter_username: state.usernames.get(&reporter).map(|name| name.clone()),
+        reason,
+        created_at: Utc::now(),
+        resolution: None,
+        reported_user_key: state.user_keys.get(&message.from).map(|key| *key),
+        reporter_key: session::user_key(state, reporter),
+        message,
+    };
+
+    state
+        .reports
+        .file(report.clone())
+        .map_err(|e| HandlerError::Invalid(e.to_string()))?;
+
+    Ok(report)
+}
+
+/// Messages members wrote themselves that have not been deleted yet.
+fn is_reportable(event: &RoomEvent) -> bool {
+    match &event.data {
+        RoomEventData::Message(message) => !message.deleted,
+        RoomEventData::Image(image) => !image.deleted,
+        RoomEventData::Audio(audio) => !audio.deleted,
+        RoomEventData::Poll(_) => true,
+        _ => false,
+    }
+}
+
+/// A short description of a reported message for the audit log.
+fn snapshot_text(event: &RoomEvent) -> Option<String> {
+    match &event.data {
+        RoomEventData::Message(message) => Some(message.content.clone()),
+        RoomEventData::Image(image) => Some(format!("Image {}", image.filename)),
+        RoomEventData::Audio(_) => Some("Voice message".to_string()),
+        RoomEventData::Poll(poll) => Some(format!("Poll: {}", poll.question)),
+        _ => None,
+    }
+}
+
+/// Closes a report with a moderator action, carries the action out and logs
+/// it.
+async fn resolve(
+    io: &SocketIo,
+    state: &AppState,
+    moderator: Sid,
+    data: ResolveReportPayload,
+) -> Result<(), HandlerError> {
+    session::ensure_moderator(state, data.room_id, moderator, NOT_MODERATOR)?;
+
+    let note = data
+        .note
+        .as_deref()
+        .map(validation::clean_text)
+        .map(|note| note.trim().to_string())
+        .filter(|note| !note.is_empty());
+    if note
+        .as_ref()
+        .is_some_and(|note| note.chars().count() > MAX_REASON_CHARS)
+    {
+        return Err(HandlerError::Invalid(format!(
+            "Notes are limited to {MAX_REASON_CHARS} characters"
+        )));
+    }
+
+    let report = state
+        .reports
+        .get(data.room_id, data.report_id)
+        .ok_or(HandlerError::NotFound("Report does not exist"))?;
+    if data.action == ModeratorAction::Ban {
+        match report.reported_user_key {
+            None => {
+                return Err(HandlerError::Invalid(
+                    "The author left before the report was filed and cannot be banned".to_string(),
+                ));
+            }
+            Some(key) if key == session::user_key(state, moderator) => {
+                return Err(HandlerError::Invalid("You cannot ban yourself".to_string()));
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Closing first keeps two moderators from acting on the same report.
+    let closed = state
+        .reports
+        .close(
+            data.room_id,
+            data.report_id,
+            Resolution {
+                action: data.action,
+                moderator,
+                note: note.clone(),
+                closed_at: Utc::now(),
+            },
+        )
+        .map_err(|e| HandlerError::Invalid(e.to_string()))?;
+
+    match data.action {
+        ModeratorAction::Dismiss => {}
+        ModeratorAction::DeleteMessage => {
+            delete_reported_message(io, state, &report, moderator).await;
+        }
+        ModeratorAction::Warn => warn_author(io, state, &report, note.clone()),
+        ModeratorAction::Ban => ban_author(io, state, &report, moderator, note.clone()).await,
+    }
+
+    info!(
+        "Moderator {} resolved report {} in room {} with {:?}",
+        moderator, report.id, data.room_id, data.action
+    );
+
+    state.reports.log(ModerationLogEntry {
+        id: Uuid::new_v4(),
+        room_id: data.room_id,
+        report_id: report.id,
+        message_id: report.message_id,
+        action: data.action,
+        moderator,
+        moderator_username: state.usernames.get(&moderator).map(|name| name.clone()),
+        target: report.reported_user,
+        target_username: report.reported_username.clone(),
+        note,
+        timestamp: Utc::now(),
+    });
+
+    for report in &closed {
+        emit_to_moderators(io, state, data.room_id, "moderation.report", report);
+    }
+
+    Ok(())
+}
+
+async fn delete_reported_message(io: &SocketIo, state: &AppState, report: &Report, moderator: Sid) {
+    let redacted = state
+        .rooms
+        .get_mut(&report.room_id)
+        .is_some_and(|mut room| {
+            let redacted = room
+                .events
+                .iter_mut()
+                .find(|event| event.id == report.message_id)
+                .filter(|event| is_reportable(event))
+                .is_some_and(|event| {
+                    let redacted = event.data.redact();
+                    state.media.release(&event.data);
+                    redacted
+                });
+            if redacted {
+                room.forget_redacted(report.message_id);
+            }
+            redacted
+        });
+
+    if redacted {
+        state.audit.record(
+            audit::entry(state, AuditAction::MessageDeleted, moderator)
+                .in_room(report.room_id)
+                .target(report.message_id)
+                .change(snapshot_text(&report.message), None),
+        );
+        message_management::announce_deletion(
+            io,
+            state,
+            report.room_id,
+            report.message_id,
+            moderator,
+        )
+        .await;
+    }
+}
+
+fn warn_author(io: &SocketIo, state: &AppState, report: &Report, note: Option<String>) {
+    let warning = ModerationWarning {
+        room_id: report.room_id,
+        message_id: report.message_id,
+        reason: note.unwrap_or_else(|| report.reason.clone()),
+    };
+
+    let Some(user_key) = report.reported_user_key else {
+        return;
+    };
+
+    for sid in session::sockets_for(state, user_key) {
+        let Some(socket) = io.get_socket(sid) else {
+            continue;
+        };
+        if let Err(e) = socket.emit("moderation.warning", &warning) {
+            error!("Failed to send warning to {}: {}", sid, e);
+        }
+    }
+}
+
+/// Removes every socket of the author from the room and keeps them from
+/// joining again.
+async fn ban_author(
+    io: &SocketIo,
+    state: &AppState,
+    report: &Report,
+    moderator: Sid,
+    note: Option<String>,
+) {
+    let Some(user_key) = report.reported_user_key else {
+        return;
+    };
+    let sockets = session::sockets_for(state, user_key);
+
+    let (removed, demoted): (Vec<Sid>, bool) = {
+        let Some(mut room) = state.rooms.get_mut(&report.room_id) else {
+            return;
+        };
+        room.banned.insert(user_key);
+        room.member_keys.remove(&user_key);
+        let demoted = room.moderators.remove(&user_key);
+        let removed = sockets
+            .into_iter()
+            .filter(|sid| room.members.remove(sid))
+            .collect();
+        (removed, demoted)
+    };
+
+    state.audit.record(
+        audit::entry(state, AuditAction::UserBanned, moderator)
+            .in_room(report.room_id)
+            .target(report.reported_user)
+            .change(None, note.clone()),
+    );
+    if demoted {
+        state.audit.record(
+            audit::entry(state, AuditAction::RoleChanged, moderator)
+                .in_room(report.room_id)
+                .target(report.reported_user)
+                .change(Some("moderator".to_string()), None),
+        );
+    }
+
+    let notice = RoomBannedNotice {
+        room_id: report.room_id,
+        reason: note,
+    };
+
+    for sid in removed {
+        if let Some(socket) = io.get_socket(sid) {
+            socket.leave(report.room_id.to_string());
+            if let Err(e) = socket.emit("room.banned", &notice) {
+                error!("Failed to send ban notice to {}: {}", sid, e);
+            }
+        }
+
+        user_management::handle_user_leave_room(sid, io.clone(), report.room_id, state.clone())
+            .await;
+    }
+}
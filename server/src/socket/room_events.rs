@@ -1,22 +1,32 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::{
     SocketIo,
     extract::{Data, SocketRef, State},
 };
-use std::collections::HashSet;
 use ts_rs::TS;
 use uuid::Uuid;
 
-use crate::{models::Room, socket::user_management, state::AppState};
+use crate::{
+    audit::AuditAction,
+    models::Room,
+    rate_limit::Action,
+    socket::{
+        ErrorResponse, audit, emit_error, protocol,
+        rate_limits::{self, SlowModeResponse},
+        session, user_management,
+    },
+    state::AppState,
+};
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct JoinRoomPayload {
     pub room_id: Uuid,
     pub room_name: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct LeaveRoomPayload {
     pub room_id: Uuid,
@@ -28,21 +38,67 @@ pub async fn join_room(
     Data(data): Data<JoinRoomPayload>,
     State(state): State<AppState>,
 ) {
+    if let Some(room) = state.rooms.get(&data.room_id)
+        && room.is_banned(&session::user_key(&state, s.id))
+    {
+        println!("User {} is banned from room {}", s.id, data.room_id);
+        emit_error(&s, "You are banned from this room");
+        return;
+    }
+
+    if let Some(room) = state.rooms.get(&data.room_id)
+        && !room.can_join(&session::user_key(&state, s.id))
+    {
+        println!("User {} may not join private room {}", s.id, data.room_id);
+        emit_error(&s, "You are not a participant of this conversation");
+        return;
+    }
+
     if !state.rooms.contains_key(&data.room_id) {
-        let room = Room {
-            id: data.room_id,
-            name: data
-                .room_name
-                .unwrap_or_else(|| format!("Room {}", data.room_id)),
-            members: HashSet::new(),
-            events: Vec::new(),
+        let name = match data
+            .room_name
+            .as_deref()
+            .map(|name| state.limits.room_name(name))
+        {
+            Some(Ok(name)) => name,
+            Some(Err(e)) => {
+                let _ = s.emit("error", &ErrorResponse::from(e));
+                return;
+            }
+            None => format!("Room {}", data.room_id),
         };
+        // Joining a room that does not exist yet creates it, so it counts
+        // against the same limit as `room.create`.
+        if let Err(limited) = state.rate_limits.check(
+            session::user_key(&state, s.id),
+            session::address(&s),
+            Action::RoomCreate,
+        ) {
+            rate_limits::emit_rate_limited(&s, None, &limited);
+            return;
+        }
+        let mut room = Room::new(data.room_id, name.clone());
+        room.moderators.insert(session::user_key(&state, s.id));
         state.rooms.insert(data.room_id, room);
+        state.audit.record(
+            audit::entry(&state, AuditAction::RoomCreated, s.id)
+                .in_room(data.room_id)
+                .target(data.room_id)
+                .change(None, Some(name)),
+        );
+        // The creator becomes the room's first moderator.
+        state.audit.record(
+            audit::entry(&state, AuditAction::RoleChanged, s.id)
+                .in_room(data.room_id)
+                .target(s.id)
+                .change(None, Some("moderator".to_string())),
+        );
         println!("Created new room: {}", data.room_id);
     }
 
     if let Some(mut room) = state.rooms.get_mut(&data.room_id) {
         room.members.insert(s.id);
+        room.member_keys.insert(session::user_key(&state, s.id));
         println!(
             "User {} joined room {} ({} members)",
             s.id,
@@ -54,14 +110,21 @@ pub async fn join_room(
     s.join(data.room_id.to_string());
 
     if let Some(room) = state.rooms.get(&data.room_id) {
-        for event in &room.events {
-            if let Err(e) = s.emit("room.event", event) {
-                eprintln!("Failed to send event to user: {}", e);
-            }
+        let viewer_key = session::user_key(&state, s.id);
+        for event in protocol::history(&state, &room.events, viewer_key) {
+            protocol::emit_room_event(&s, &state, &event);
+        }
+
+        if let Some(slow_mode) = &room.slow_mode {
+            let response = SlowModeResponse {
+                room_id: data.room_id,
+                slow_mode: Some(slow_mode.clone()),
+            };
+            s.emit("room.slow_mode", &response).ok();
         }
     }
 
-    user_management::handle_user_join_room(s.clone(), io, data.room_id, state).await;
+    user_management::handle_user_join_room(s.id, io, data.room_id, state).await;
 }
 
 pub async fn leave_room(
@@ -71,7 +134,7 @@ pub async fn leave_room(
     State(state): State<AppState>,
 ) {
     if let Some(mut room) = state.rooms.get_mut(&data.room_id) {
-        room.members.remove(&s.id);
+        user_management::remove_member(&state, &mut room, s.id);
         println!(
             "User {} left room {} ({} members remaining)",
             s.id,
@@ -82,5 +145,5 @@ pub async fn leave_room(
 
     s.leave(data.room_id.to_string());
 
-    user_management::handle_user_leave_room(s, io, data.room_id, state).await;
+    user_management::handle_user_leave_room(s.id, io, data.room_id, state).await;
 }
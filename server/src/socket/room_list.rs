@@ -1,49 +1,45 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::{
     SocketIo,
     extract::{Data, SocketRef, State},
+    socket::Sid,
 };
-use std::collections::HashSet;
 use ts_rs::TS;
 use uuid::Uuid;
 
-use crate::{models::Room, state::AppState};
+use crate::{
+    audit::AuditAction,
+    models::Room,
+    rate_limit::Action,
+    socket::{ErrorResponse, HandlerError, audit, rate_limits, session},
+    state::AppState,
+};
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct CreateRoomPayload {
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct RoomListItem {
     pub id: String,
     pub name: String,
     pub member_count: usize,
+    #[serde(default)]
+    pub topic: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct RoomListResponse {
     pub rooms: Vec<RoomListItem>,
 }
 
 pub async fn list_rooms(s: SocketRef, _io: SocketIo, State(state): State<AppState>) {
-    let rooms: Vec<RoomListItem> = state
-        .rooms
-        .iter()
-        .map(|entry| {
-            let room = entry.value();
-            RoomListItem {
-                id: room.id.to_string(),
-                name: room.name.clone(),
-                member_count: room.members.len(),
-            }
-        })
-        .collect();
-
-    let response = RoomListResponse { rooms };
+    let response = public_rooms(&state);
 
     if let Err(e) = s.emit("room.list", &response) {
         println!("Failed to send room list to user {}: {}", s.id, e);
@@ -51,58 +47,89 @@ pub async fn list_rooms(s: SocketRef, _io: SocketIo, State(state): State<AppStat
 }
 
 pub async fn create_room(
+    s: SocketRef,
     io: SocketIo,
     Data(data): Data<CreateRoomPayload>,
     State(state): State<AppState>,
 ) {
-    let room_id = Uuid::new_v4();
-    let room = Room {
-        id: room_id,
-        name: data.name.clone(),
-        members: HashSet::new(),
-        events: Vec::new(),
-    };
+    if let Err(limited) = state.rate_limits.check(
+        session::user_key(&state, s.id),
+        session::address(&s),
+        Action::RoomCreate,
+    ) {
+        rate_limits::emit_rate_limited(&s, None, &limited);
+        return;
+    }
 
-    state.rooms.insert(room_id, room);
+    if let Err(e) = create(&io, &state, &data.name, s.id).await {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+    }
+}
 
-    let Some(_) = state.rooms.get(&room_id) else {
-        println!("ERROR: Room was NOT inserted!");
-        return;
-    };
+/// Creates a public room with `creator` as its moderator and sends the new
+/// room list to everyone.
+pub async fn create(
+    io: &SocketIo,
+    state: &AppState,
+    name: &str,
+    creator: Sid,
+) -> Result<RoomListItem, HandlerError> {
+    let name = state.limits.room_name(name)?;
 
-    let rooms: Vec<RoomListItem> = state
-        .rooms
-        .iter()
-        .map(|entry| {
-            let room = entry.value();
-            RoomListItem {
-                id: room.id.to_string(),
-                name: room.name.clone(),
-                member_count: room.members.len(),
-            }
-        })
-        .collect();
+    let room_id = Uuid::new_v4();
+    let mut room = Room::new(room_id, name.clone());
+    room.moderators.insert(session::user_key(state, creator));
 
-    let response = RoomListResponse { rooms };
+    state.rooms.insert(room_id, room);
+    state.audit.record(
+        audit::entry(state, AuditAction::RoomCreated, creator)
+            .in_room(room_id)
+            .target(room_id)
+            .change(None, Some(name.clone())),
+    );
+    // The creator becomes the room's first moderator.
+    state.audit.record(
+        audit::entry(state, AuditAction::RoleChanged, creator)
+            .in_room(room_id)
+            .target(creator)
+            .change(None, Some("moderator".to_string())),
+    );
+
+    let response = public_rooms(state);
 
     io.emit("room.list", &response).await.ok();
+
+    Ok(RoomListItem {
+        id: room_id.to_string(),
+        name,
+        member_count: 0,
+        topic: None,
+    })
 }
 
 pub fn send_room_list_on_connect(s: SocketRef, state: AppState) {
-    let rooms: Vec<RoomListItem> = state
+    let response = public_rooms(&state);
+
+    s.emit("room.list", &response).ok();
+}
+
+/// Lists every room except direct conversations, which stay private to their
+/// participants.
+pub fn public_rooms(state: &AppState) -> RoomListResponse {
+    let rooms = state
         .rooms
         .iter()
+        .filter(|entry| !entry.value().is_direct())
         .map(|entry| {
             let room = entry.value();
             RoomListItem {
                 id: room.id.to_string(),
                 name: room.name.clone(),
                 member_count: room.members.len(),
+                topic: room.topic.clone(),
             }
         })
         .collect();
 
-    let response = RoomListResponse { rooms };
-
-    s.emit("room.list", &response).ok();
+    RoomListResponse { rooms }
 }
@@ -0,0 +1,123 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    models::RoomPolicy,
+    socket::{audit, emit_error, session},
+    state::AppState,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SetRoomPolicyPayload {
+    pub room_id: Uuid,
+    pub policy: RoomPolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct GetRoomPolicyPayload {
+    pub room_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct RoomPolicyResponse {
+    pub room_id: Uuid,
+    pub policy: RoomPolicy,
+}
+
+pub async fn set_room_policy(
+    s: SocketRef,
+    io: SocketIo,
+    Data(data): Data<SetRoomPolicyPayload>,
+    State(state): State<AppState>,
+) {
+    {
+        let Some(mut room) = state.rooms.get_mut(&data.room_id) else {
+            error!(
+                "User {} tried to set policy of non-existent room {}",
+                s.id, data.room_id
+            );
+            emit_error(&s, "Room does not exist");
+            return;
+        };
+
+        if !room.members.contains(&s.id) {
+            error!(
+                "User {} tried to set policy of room {} they're not a member of",
+                s.id, data.room_id
+            );
+            emit_error(&s, "You are not a member of this room");
+            return;
+        }
+
+        // Retention settings can wipe the room's history, so only moderators
+        // may change them. Direct conversations have no moderators.
+        if !room.is_direct() && !room.is_moderator(&session::user_key(&state, s.id)) {
+            error!(
+                "User {} tried to set policy of room {} without being a moderator",
+                s.id, data.room_id
+            );
+            emit_error(&s, "Only moderators can change the room policy");
+            return;
+        }
+
+        let before = std::mem::replace(&mut room.policy, data.policy.clone());
+        if !room.is_direct() {
+            state.audit.record(
+                audit::entry(&state, AuditAction::RoomPolicyChanged, s.id)
+                    .in_room(data.room_id)
+                    .target(data.room_id)
+                    .change(audit::describe(&before), audit::describe(&data.policy)),
+            );
+        }
+    }
+
+    let response = RoomPolicyResponse {
+        room_id: data.room_id,
+        policy: data.policy,
+    };
+
+    if let Err(e) = io
+        .to(data.room_id.to_string())
+        .emit("room.policy", &response)
+        .await
+    {
+        error!("Failed to broadcast room policy: {}", e);
+    }
+
+    info!("User {} updated policy of room {}", s.id, data.room_id);
+}
+
+pub async fn get_room_policy(
+    s: SocketRef,
+    Data(data): Data<GetRoomPolicyPayload>,
+    State(state): State<AppState>,
+) {
+    let Some(policy) = state
+        .rooms
+        .get(&data.room_id)
+        .map(|room| room.policy.clone())
+    else {
+        emit_error(&s, "Room does not exist");
+        return;
+    };
+
+    let response = RoomPolicyResponse {
+        room_id: data.room_id,
+        policy,
+    };
+
+    if let Err(e) = s.emit("room.policy", &response) {
+        error!("Failed to send room policy: {}", e);
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    SocketIo,
+    extract::{Data, SocketRef, State},
+    socket::Sid,
+};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    socket::{
+        HandlerError, emit_error,
+        send_event::{self, SendEventPayload},
+        session,
+    },
+    state::AppState,
+};
+
+/// Messages one user may have scheduled at once.
+const MAX_SCHEDULED_PER_USER: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ScheduledMessage {
+    pub id: Uuid,
+    /// Identifies the author across reconnects and restarts. Only ever sent
+    /// to the author themselves.
+    pub author_key: Uuid,
+    pub send_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub message: SendEventPayload,
+    /// Why the message could not be sent when it was due. Failed messages
+    /// are kept until the author reschedules or cancels them.
+    #[serde(default)]
+    pub failure: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ScheduleMessagePayload {
+    pub send_at: DateTime<Utc>,
+    pub message: SendEventPayload,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct EditScheduledMessagePayload {
+    pub id: Uuid,
+    #[serde(default)]
+    pub send_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub message: Option<SendEventPayload>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CancelScheduledMessagePayload {
+    pub id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ScheduledMessagesResponse {
+    pub scheduled_messages: Vec<ScheduledMessage>,
+}
+
+pub async fn schedule_message(
+    socket: SocketRef,
+    Data(mut data): Data<ScheduleMessagePayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(message) = validate(&state, socket.id, &mut data.message, data.send_at) {
+        emit_error(&socket, &message);
+        return;
+    }
+
+    let author_key = session::user_key(&state, socket.id);
+    let pending = state
+        .scheduled_messages
+        .iter()
+        .filter(|entry| entry.author_key == author_key)
+        .count();
+    if pending >= MAX_SCHEDULED_PER_USER {
+        emit_error(
+            &socket,
+            &format!("You can have at most {MAX_SCHEDULED_PER_USER} scheduled messages"),
+        );
+        return;
+    }
+
+    let scheduled = ScheduledMessage {
+        id: Uuid::new_v4(),
+        author_key,
+        send_at: data.send_at,
+        created_at: Utc::now(),
+        message: data.message,
+        failure: None,
+    };
+
+    info!(
+        "User {} scheduled message {} for {} in room {}",
+        socket.id, scheduled.id, scheduled.send_at, scheduled.message.room
+    );
+
+    state.scheduled_messages.insert(scheduled.id, scheduled);
+    persist(&state).await;
+
+    send_scheduled_list(&socket, &state);
+}
+
+pub async fn edit_scheduled_message(
+    socket: SocketRef,
+    Data(data): Data<EditScheduledMessagePayload>,
+    State(state): State<AppState>,
+) {
+    let author_key = session::user_key(&state, socket.id);
+    let Some(mut updated) = state
+        .scheduled_messages
+        .get(&data.id)
+        .filter(|scheduled| scheduled.author_key == author_key)
+        .map(|scheduled| scheduled.clone())
+    else {
+        emit_error(&socket, "Scheduled message does not exist");
+        return;
+    };
+
+    if let Some(send_at) = data.send_at {
+        updated.send_at = send_at;
+    }
+    if let Some(message) = data.message {
+        updated.message = message;
+    }
+    // Rescheduling a failed message gives it another try.
+    updated.failure = None;
+
+    if let Err(message) = validate(&state, socket.id, &mut updated.message, updated.send_at) {
+        emit_error(&socket, &message);
+        return;
+    }
+
+    // The scheduler may have delivered the message while we were validating.
+    let Some(mut entry) = state.scheduled_messages.get_mut(&data.id) else {
+        emit_error(&socket, "Scheduled message was already sent");
+        return;
+    };
+    *entry = updated;
+    drop(entry);
+
+    persist(&state).await;
+
+    send_scheduled_list(&socket, &state);
+}
+
+pub async fn cancel_scheduled_message(
+    socket: SocketRef,
+    Data(data): Data<CancelScheduledMessagePayload>,
+    State(state): State<AppState>,
+) {
+    let author_key = session::user_key(&state, socket.id);
+    let removed = state
+        .scheduled_messages
+        .remove_if(&data.id, |_, scheduled| scheduled.author_key == author_key);
+
+    if removed.is_none() {
+        emit_error(&socket, "Scheduled message does not exist");
+        return;
+    }
+
+    persist(&state).await;
+
+    info!("User {} cancelled scheduled message {}", socket.id, data.id);
+
+    send_scheduled_list(&socket, &state);
+}
+
+pub async fn get_scheduled_messages(socket: SocketRef, State(state): State<AppState>) {
+    send_scheduled_list(&socket, &state);
+}
+
+fn validate(
+    state: &AppState,
+    author: Sid,
+    message: &mut SendEventPayload,
+    send_at: DateTime<Utc>,
+) -> Result<(), String> {
+    send_event::ensure_member(state, message.room, author).map_err(|e| e.to_string())?;
+
+    if send_at <= Utc::now() {
+        return Err("Scheduled time must be in the future".to_string());
+    }
+
+    state
+        .limits
+        .event(&mut message.payload)
+        .map_err(|e| e.to_string())
+}
+
+/// Checks that the author may still post a due message, so scheduling never
+/// lets anyone post where they no longer could. The author need not be
+/// online: room membership survives disconnects. Returns the author's socket
+/// in the room, if they have one.
+pub fn sender(state: &AppState, scheduled: &ScheduledMessage) -> Result<Option<Sid>, HandlerError> {
+    let room = state
+        .rooms
+        .get(&scheduled.message.room)
+        .ok_or(HandlerError::NotFound("Room does not exist"))?;
+
+    if room.is_banned(&scheduled.author_key) {
+        return Err(HandlerError::Forbidden("You are banned from this room"));
+    }
+
+    if !room.can_join(&scheduled.author_key) || !room.member_keys.contains(&scheduled.author_key) {
+        return Err(HandlerError::Forbidden("You are not a member of this room"));
+    }
+
+    Ok(session::sockets_for(state, scheduled.author_key)
+        .into_iter()
+        .find(|sid| room.members.contains(sid)))
+}
+
+/// Sends the author's scheduled messages to all of their sockets, for
+/// example after one of them failed.
+pub fn notify_author(io: &SocketIo, state: &AppState, author_key: Uuid) {
+    let response = scheduled_list(state, author_key);
+
+    for sid in session::sockets_for(state, author_key) {
+        if let Some(socket) = io.get_socket(sid)
+            && let Err(e) = socket.emit("scheduled_messages.list", &response)
+        {
+            error!("Failed to emit scheduled messages list: {}", e);
+        }
+    }
+}
+
+fn send_scheduled_list(socket: &SocketRef, state: &AppState) {
+    let author_key = session::user_key(state, socket.id);
+    let response = scheduled_list(state, author_key);
+
+    if let Err(e) = socket.emit("scheduled_messages.list", &response) {
+        error!("Failed to emit scheduled messages list: {}", e);
+    }
+}
+
+fn scheduled_list(state: &AppState, author_key: Uuid) -> ScheduledMessagesResponse {
+    let mut scheduled_messages: Vec<ScheduledMessage> = state
+        .scheduled_messages
+        .iter()
+        .filter(|entry| entry.author_key == author_key)
+        .map(|entry| entry.value().clone())
+        .collect();
+    scheduled_messages.sort_by_key(|scheduled| scheduled.send_at);
+
+    ScheduledMessagesResponse { scheduled_messages }
+}
+
+/// Serializes writers so concurrent updates cannot interleave on disk.
+static PERSIST_LOCK: Mutex<()> = Mutex::const_new(());
+
+fn store_path() -> Option<String> {
+    std::env::var("SCHEDULED_MESSAGES_FILE").ok()
+}
+
+/// Loads scheduled messages persisted by a previous run, if persistence is enabled.
+pub fn load(state: &AppState) {
+    let Some(path) = store_path() else {
+        return;
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            error!("Failed to read scheduled messages from {}: {}", path, e);
+            return;
+        }
+    };
+
+    match serde_json::from_str::<Vec<ScheduledMessage>>(&contents) {
+        Ok(scheduled_messages) => {
+            info!(
+                "Loaded {} scheduled messages from {}",
+                scheduled_messages.len(),
+                path
+            );
+            for scheduled in scheduled_messages {
+                state.scheduled_messages.insert(scheduled.id, scheduled);
+            }
+        }
+        Err(e) => warn!("Ignoring malformed scheduled messages file {}: {}", path, e),
+    }
+}
+
+/// Writes all pending scheduled messages to disk, if persistence is enabled.
+pub async fn persist(state: &AppState) {
+    let Some(path) = store_path() else {
+        return;
+    };
+
+    let _guard = PERSIST_LOCK.lock().await;
+
+    let scheduled_messages: Vec<ScheduledMessage> = state
+        .scheduled_messages
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+
+    let json = match serde_json::to_string(&scheduled_messages) {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to serialize scheduled messages: {}", e);
+            return;
+        }
+    };
+
+    let tmp_path = format!("{}.tmp", path);
+    if let Err(e) = tokio::fs::write(&tmp_path, json).await {
+        error!("Failed to write scheduled messages to {}: {}", tmp_path, e);
+        return;
+    }
+    if let Err(e) = tokio::fs::rename(&tmp_path, &path).await {
+        error!("Failed to replace scheduled messages file {}: {}", path, e);
+    }
+}
@@ -0,0 +1,841 @@
+//! Machine-readable description of the socket protocol for clients that are
+//! not written in TypeScript: an AsyncAPI document listing every event, with
+//! JSON Schemas generated from the same types as the ts-rs bindings.
+//!
+//! The document is served at `/api/asyncapi.json`, the payload schemas at
+//! `/api/schemas`, and both are exported to `SCHEMA_EXPORT_DIR` by
+//! `cargo test`. Keep [`events`] in step with the handlers registered in
+//! [`super::init_io`].
+
+use std::borrow::Cow;
+
+use schemars::{JsonSchema, Schema, SchemaGenerator, generate::SchemaSettings};
+use serde_json::{Map, Value, json};
+
+use super::{
+    ErrorResponse,
+    audit::AuditLogResponse,
+    blocking::{BlockUserPayload, BlockedUsersResponse, UnblockUserPayload},
+    bots::{
+        BotListResponse, CommandRespondPayload, CreateBotPayload, DeleteBotPayload,
+        RegisterCommandsPayload,
+    },
+    commands::{CommandInvocation, CommandResponse},
+    direct_messages::{DirectConversation, DirectConversationsResponse, OpenDirectMessagePayload},
+    media::{GetMediaPayload, MediaBlobResponse, MediaUploadedResponse, UploadMediaPayload},
+    mentions::{ClearMentionsPayload, MentionCountsResponse, MentionNotification},
+    message_management::{DeleteMessagePayload, EditMessagePayload},
+    moderation::{FiltersResponse, FlaggedListResponse, ModerationRoomPayload, SetFiltersPayload},
+    pinned_messages::{
+        GetPinnedMessagesPayload, PinMessagePayload, PinnedMessagesResponse, UnpinMessagePayload,
+    },
+    polls::{ClosePollPayload, VotePollPayload},
+    profiles::{AvatarResponse, GetProfilePayload, ProfileResponse, SetProfilePayload},
+    rate_limits::{RateLimitError, SetSlowModePayload, SlowModeResponse},
+    reports::{
+        GetReportsPayload, ModerationLogResponse, ModerationWarning, ReportListResponse,
+        ReportMessagePayload, ReportReceipt, ResolveReportPayload, RoomBannedNotice,
+    },
+    room_events::{JoinRoomPayload, LeaveRoomPayload},
+    room_list::{CreateRoomPayload, RoomListResponse},
+    room_policy::{GetRoomPolicyPayload, RoomPolicyResponse, SetRoomPolicyPayload},
+    scheduled_messages::{
+        CancelScheduledMessagePayload, EditScheduledMessagePayload, ScheduleMessagePayload,
+        ScheduledMessagesResponse,
+    },
+    send_event::SendEventPayload,
+    session::{ConnectAuth, SessionInfo},
+    starred_messages::{
+        GetStarredMessagesRequest, StarMessageRequest, StarredMessagesResponse,
+        UnstarMessageRequest,
+    },
+    typing::{StartTypingPayload, StopTypingPayload, TypingIndicator},
+    user_management::{GetMembersPayload, SetUsernamePayload, UserRenamedEvent},
+    webhooks::{
+        CreateIncomingWebhookPayload, CreateWebhookPayload, DeleteWebhookPayload,
+        GetWebhookDeliveriesPayload, IncomingWebhookListResponse, ListWebhooksPayload,
+        RedeliverWebhookPayload, WebhookDeliveriesResponse, WebhookListResponse,
+    },
+};
+use crate::{
+    audit::AuditQuery,
+    bots::BotInfo,
+    models::{RoomEvent, RoomMembersResponse},
+    moderation::{FlaggedMessage, Report},
+    webhooks::{IncomingWebhookInfo, WebhookInfo},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+struct Payload {
+    name: fn() -> Cow<'static, str>,
+    /// Primitives such as the `username.set` string get no schema file.
+    inline: fn() -> bool,
+    subschema: fn(&mut SchemaGenerator) -> Schema,
+    root: fn() -> Schema,
+}
+
+struct EventDoc {
+    name: &'static str,
+    direction: Direction,
+    summary: &'static str,
+    payload: Option<Payload>,
+}
+
+fn payload<T: JsonSchema>() -> Option<Payload> {
+    Some(Payload {
+        name: T::schema_name,
+        inline: T::inline_schema,
+        subschema: SchemaGenerator::subschema_for::<T>,
+        root: || schemars::schema_for!(T),
+    })
+}
+
+fn event(
+    name: &'static str,
+    direction: Direction,
+    summary: &'static str,
+    payload: Option<Payload>,
+) -> EventDoc {
+    EventDoc {
+        name,
+        direction,
+        summary,
+        payload,
+    }
+}
+
+fn events() -> Vec<EventDoc> {
+    use Direction::{ClientToServer as Client, ServerToClient as Server};
+
+    vec![
+        event(
+            "room.send",
+            Client,
+            "Send an event into a room.",
+            payload::<SendEventPayload>(),
+        ),
+        event(
+            "room.join",
+            Client,
+            "Join a room, creating it if needed.",
+            payload::<JoinRoomPayload>(),
+        ),
+        event(
+            "room.leave",
+            Client,
+            "Leave a room.",
+            payload::<LeaveRoomPayload>(),
+        ),
+        event("room.list", Client, "Request the public room list.", None),
+        event(
+            "room.create",
+            Client,
+            "Create a public room.",
+            payload::<CreateRoomPayload>(),
+        ),
+        event(
+            "room.set_policy",
+            Client,
+            "Change a room's policy. Moderators only, except in direct conversations.",
+            payload::<SetRoomPolicyPayload>(),
+        ),
+        event(
+            "room.get_policy",
+            Client,
+            "Request a room's policy.",
+            payload::<GetRoomPolicyPayload>(),
+        ),
+        event(
+            "room.set_slow_mode",
+            Client,
+            "Limit how often members may post to a room, optionally for a while. Moderators only.",
+            payload::<SetSlowModePayload>(),
+        ),
+        event(
+            "user.set_username",
+            Client,
+            "Set the caller's username.",
+            payload::<SetUsernamePayload>(),
+        ),
+        event(
+            "user.set_profile",
+            Client,
+            "Replace the caller's profile.",
+            payload::<SetProfilePayload>(),
+        ),
+        event(
+            "user.get_profile",
+            Client,
+            "Request a user's profile.",
+            payload::<GetProfilePayload>(),
+        ),
+        event(
+            "user.get_avatar",
+            Client,
+            "Request a user's avatar image.",
+            payload::<GetProfilePayload>(),
+        ),
+        event(
+            "user.block",
+            Client,
+            "Block a user, hiding their messages and typing from the caller.",
+            payload::<BlockUserPayload>(),
+        ),
+        event(
+            "user.unblock",
+            Client,
+            "Unblock a user.",
+            payload::<UnblockUserPayload>(),
+        ),
+        event(
+            "user.get_blocked",
+            Client,
+            "Request the users the caller has blocked.",
+            None,
+        ),
+        event(
+            "room.get_members",
+            Client,
+            "Request a room's members.",
+            payload::<GetMembersPayload>(),
+        ),
+        event(
+            "typing.start",
+            Client,
+            "Start typing in a room.",
+            payload::<StartTypingPayload>(),
+        ),
+        event(
+            "typing.stop",
+            Client,
+            "Stop typing in a room.",
+            payload::<StopTypingPayload>(),
+        ),
+        event(
+            "message.edit",
+            Client,
+            "Edit one of the caller's messages.",
+            payload::<EditMessagePayload>(),
+        ),
+        event(
+            "message.delete",
+            Client,
+            "Delete one of the caller's messages.",
+            payload::<DeleteMessagePayload>(),
+        ),
+        event(
+            "message.star",
+            Client,
+            "Star a message.",
+            payload::<StarMessageRequest>(),
+        ),
+        event(
+            "message.unstar",
+            Client,
+            "Unstar a message.",
+            payload::<UnstarMessageRequest>(),
+        ),
+        event(
+            "starred_messages.get",
+            Client,
+            "Request a page of starred messages.",
+            payload::<GetStarredMessagesRequest>(),
+        ),
+        event(
+            "message.pin",
+            Client,
+            "Pin a message in a room.",
+            payload::<PinMessagePayload>(),
+        ),
+        event(
+            "message.unpin",
+            Client,
+            "Unpin a message.",
+            payload::<UnpinMessagePayload>(),
+        ),
+        event(
+            "pinned_messages.get",
+            Client,
+            "Request a room's pinned messages.",
+            payload::<GetPinnedMessagesPayload>(),
+        ),
+        event(
+            "poll.vote",
+            Client,
+            "Vote on a poll, or retract a vote.",
+            payload::<VotePollPayload>(),
+        ),
+        event(
+            "poll.close",
+            Client,
+            "Close a poll.",
+            payload::<ClosePollPayload>(),
+        ),
+        event(
+            "dm.open",
+            Client,
+            "Open a direct conversation.",
+            payload::<OpenDirectMessagePayload>(),
+        ),
+        event(
+            "dm.list",
+            Client,
+            "Request the caller's direct conversations.",
+            None,
+        ),
+        event(
+            "media.upload",
+            Client,
+            "Upload a media blob.",
+            payload::<UploadMediaPayload>(),
+        ),
+        event(
+            "media.get",
+            Client,
+            "Download the media attached to a message.",
+            payload::<GetMediaPayload>(),
+        ),
+        event(
+            "webhook.create",
+            Client,
+            "Add an outgoing webhook to a room. Moderators only.",
+            payload::<CreateWebhookPayload>(),
+        ),
+        event(
+            "webhook.delete",
+            Client,
+            "Remove an outgoing webhook. Moderators only.",
+            payload::<DeleteWebhookPayload>(),
+        ),
+        event(
+            "webhook.list",
+            Client,
+            "List a room's outgoing webhooks. Moderators only.",
+            payload::<ListWebhooksPayload>(),
+        ),
+        event(
+            "webhook.deliveries",
+            Client,
+            "Fetch delivery logs and dead letters. Moderators only.",
+            payload::<GetWebhookDeliveriesPayload>(),
+        ),
+        event(
+            "webhook.redeliver",
+            Client,
+            "Retry a dead-lettered delivery. Moderators only.",
+            payload::<RedeliverWebhookPayload>(),
+        ),
+        event(
+            "incoming_webhook.create",
+            Client,
+            "Create a token integrations can post into a room with. Moderators only.",
+            payload::<CreateIncomingWebhookPayload>(),
+        ),
+        event(
+            "incoming_webhook.delete",
+            Client,
+            "Revoke an incoming webhook. Moderators only.",
+            payload::<DeleteWebhookPayload>(),
+        ),
+        event(
+            "incoming_webhook.list",
+            Client,
+            "List a room's incoming webhooks. Moderators only.",
+            payload::<ListWebhooksPayload>(),
+        ),
+        event(
+            "moderation.set_filters",
+            Client,
+            "Replace a room's message filter rules. Moderators only.",
+            payload::<SetFiltersPayload>(),
+        ),
+        event(
+            "moderation.get_filters",
+            Client,
+            "Fetch a room's message filter rules. Moderators only.",
+            payload::<ModerationRoomPayload>(),
+        ),
+        event(
+            "moderation.get_flagged",
+            Client,
+            "List messages the room's filters flagged for review. Moderators only.",
+            payload::<ModerationRoomPayload>(),
+        ),
+        event(
+            "message.report",
+            Client,
+            "Report a message to the room's moderators.",
+            payload::<ReportMessagePayload>(),
+        ),
+        event(
+            "moderation.get_reports",
+            Client,
+            "List a room's reports. Moderators only.",
+            payload::<GetReportsPayload>(),
+        ),
+        event(
+            "moderation.resolve_report",
+            Client,
+            "Close a report by dismissing it, deleting the message, warning or banning its author. Moderators only.",
+            payload::<ResolveReportPayload>(),
+        ),
+        event(
+            "moderation.get_log",
+            Client,
+            "List the moderator actions taken in a room. Moderators only.",
+            payload::<ModerationRoomPayload>(),
+        ),
+        event(
+            "audit.query",
+            Client,
+            "Search the server's audit log, newest first. Admins only.",
+            payload::<AuditQuery>(),
+        ),
+        event(
+            "bot.create",
+            Client,
+            "Create a bot account owned by the caller.",
+            payload::<CreateBotPayload>(),
+        ),
+        event("bot.list", Client, "List the caller's bots.", None),
+        event(
+            "bot.delete",
+            Client,
+            "Delete one of the caller's bots, disconnecting it.",
+            payload::<DeleteBotPayload>(),
+        ),
+        event(
+            "bot.register_commands",
+            Client,
+            "Set the slash commands a connected bot handles. Bots only.",
+            payload::<RegisterCommandsPayload>(),
+        ),
+        event(
+            "command.respond",
+            Client,
+            "Answer a command invocation. Bots only.",
+            payload::<CommandRespondPayload>(),
+        ),
+        event(
+            "mentions.get",
+            Client,
+            "Request unread mention counts.",
+            None,
+        ),
+        event(
+            "mentions.clear",
+            Client,
+            "Clear unread mentions for a room.",
+            payload::<ClearMentionsPayload>(),
+        ),
+        event(
+            "message.schedule",
+            Client,
+            "Schedule a message for later.",
+            payload::<ScheduleMessagePayload>(),
+        ),
+        event(
+            "scheduled_messages.edit",
+            Client,
+            "Edit a scheduled message.",
+            payload::<EditScheduledMessagePayload>(),
+        ),
+        event(
+            "scheduled_messages.cancel",
+            Client,
+            "Cancel a scheduled message.",
+            payload::<CancelScheduledMessagePayload>(),
+        ),
+        event(
+            "scheduled_messages.get",
+            Client,
+            "Request the caller's scheduled messages.",
+            None,
+        ),
+        event(
+            "room.event",
+            Server,
+            "An event in a room the client has joined.",
+            payload::<RoomEvent>(),
+        ),
+        event(
+            "session.info",
+            Server,
+            "Sent on connect with the user's stable key.",
+            payload::<SessionInfo>(),
+        ),
+        event(
+            "room.list",
+            Server,
+            "The public room list.",
+            payload::<RoomListResponse>(),
+        ),
+        event(
+            "username.set",
+            Server,
+            "Confirms the new username.",
+            payload::<String>(),
+        ),
+        event(
+            "user.renamed",
+            Server,
+            "A member of the room changed their username.",
+            payload::<UserRenamedEvent>(),
+        ),
+        event(
+            "user.profile",
+            Server,
+            "A user's profile.",
+            payload::<ProfileResponse>(),
+        ),
+        event(
+            "user.avatar",
+            Server,
+            "A user's avatar image.",
+            payload::<AvatarResponse>(),
+        ),
+        event(
+            "user.blocked_list",
+            Server,
+            "The users the caller has blocked.",
+            payload::<BlockedUsersResponse>(),
+        ),
+        event(
+            "room.members",
+            Server,
+            "A room's current members.",
+            payload::<RoomMembersResponse>(),
+        ),
+        event(
+            "typing.start",
+            Server,
+            "Someone started typing.",
+            payload::<TypingIndicator>(),
+        ),
+        event(
+            "typing.stop",
+            Server,
+            "Someone stopped typing.",
+            payload::<TypingIndicator>(),
+        ),
+        event(
+            "starred_messages.list",
+            Server,
+            "A page of starred messages.",
+            payload::<StarredMessagesResponse>(),
+        ),
+        event(
+            "room.policy",
+            Server,
+            "A room's policy.",
+            payload::<RoomPolicyResponse>(),
+        ),
+        event(
+            "room.slow_mode",
+            Server,
+            "A room's slow mode changed, or is active when joining it.",
+            payload::<SlowModeResponse>(),
+        ),
+        event(
+            "scheduled_messages.list",
+            Server,
+            "The caller's scheduled messages, also sent when one of them could not be delivered.",
+            payload::<ScheduledMessagesResponse>(),
+        ),
+        event(
+            "pinned_messages.list",
+            Server,
+            "A room's pinned messages.",
+            payload::<PinnedMessagesResponse>(),
+        ),
+        event(
+            "dm.opened",
+            Server,
+            "A direct conversation was opened with the user.",
+            payload::<DirectConversation>(),
+        ),
+        event(
+            "dm.list",
+            Server,
+            "The user's direct conversations.",
+            payload::<DirectConversationsResponse>(),
+        ),
+        event(
+            "media.uploaded",
+            Server,
+            "Confirms an upload.",
+            payload::<MediaUploadedResponse>(),
+        ),
+        event(
+            "media.blob",
+            Server,
+            "Requested media contents.",
+            payload::<MediaBlobResponse>(),
+        ),
+        event(
+            "webhook.created",
+            Server,
+            "A new webhook, including its signing secret.",
+            payload::<WebhookInfo>(),
+        ),
+        event(
+            "webhook.list",
+            Server,
+            "A room's outgoing webhooks.",
+            payload::<WebhookListResponse>(),
+        ),
+        event(
+            "webhook.deliveries",
+            Server,
+            "Recent webhook deliveries and dead letters.",
+            payload::<WebhookDeliveriesResponse>(),
+        ),
+        event(
+            "incoming_webhook.created",
+            Server,
+            "A new incoming webhook, including its token.",
+            payload::<IncomingWebhookInfo>(),
+        ),
+        event(
+            "incoming_webhook.list",
+            Server,
+            "A room's incoming webhooks.",
+            payload::<IncomingWebhookListResponse>(),
+        ),
+        event(
+            "moderation.filters",
+            Server,
+            "A room's message filter rules.",
+            payload::<FiltersResponse>(),
+        ),
+        event(
+            "moderation.flagged_list",
+            Server,
+            "Messages flagged for review in a room.",
+            payload::<FlaggedListResponse>(),
+        ),
+        event(
+            "moderation.flagged",
+            Server,
+            "A message was just flagged by a filter. Sent to the room's moderators.",
+            payload::<FlaggedMessage>(),
+        ),
+        event(
+            "message.reported",
+            Server,
+            "Confirms a report to the member who filed it.",
+            payload::<ReportReceipt>(),
+        ),
+        event(
+            "moderation.report",
+            Server,
+            "A report was filed or closed. Sent to the room's moderators.",
+            payload::<Report>(),
+        ),
+        event(
+            "moderation.reports",
+            Server,
+            "A room's reports.",
+            payload::<ReportListResponse>(),
+        ),
+        event(
+            "moderation.log",
+            Server,
+            "The moderator actions taken in a room.",
+            payload::<ModerationLogResponse>(),
+        ),
+        event(
+            "moderation.warning",
+            Server,
+            "A moderator warned you about one of your messages.",
+            payload::<ModerationWarning>(),
+        ),
+        event(
+            "room.banned",
+            Server,
+            "You were banned from a room and can no longer join it.",
+            payload::<RoomBannedNotice>(),
+        ),
+        event(
+            "audit.log",
+            Server,
+            "Audit log entries matching a query.",
+            payload::<AuditLogResponse>(),
+        ),
+        event(
+            "bot.created",
+            Server,
+            "A new bot, including its token.",
+            payload::<BotInfo>(),
+        ),
+        event(
+            "bot.list",
+            Server,
+            "The caller's bots.",
+            payload::<BotListResponse>(),
+        ),
+        event(
+            "bot.commands",
+            Server,
+            "Confirms the commands a bot registered.",
+            payload::<RegisterCommandsPayload>(),
+        ),
+        event(
+            "command.invoked",
+            Server,
+            "Someone ran one of the bot's commands. Sent to bots.",
+            payload::<CommandInvocation>(),
+        ),
+        event(
+            "command.response",
+            Server,
+            "A reply to a slash command, visible only to the user who ran it.",
+            payload::<CommandResponse>(),
+        ),
+        event(
+            "mention",
+            Server,
+            "The user was mentioned.",
+            payload::<MentionNotification>(),
+        ),
+        event(
+            "mentions.counts",
+            Server,
+            "Unread mention counts per room.",
+            payload::<MentionCountsResponse>(),
+        ),
+        event(
+            "rate_limited",
+            Server,
+            "A request was refused because it was sent too often.",
+            payload::<RateLimitError>(),
+        ),
+        event(
+            "error",
+            Server,
+            "A request was rejected.",
+            payload::<ErrorResponse>(),
+        ),
+    ]
+}
+
+/// AsyncAPI keys may only contain letters, digits, `_` and `-`.
+fn key(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+/// Builds the AsyncAPI document for the socket protocol. Operations are
+/// described from the server's point of view.
+pub fn asyncapi_document() -> Value {
+    let mut generator = SchemaSettings::draft2020_12()
+        .with(|settings| settings.definitions_path = "/components/schemas".into())
+        .into_generator();
+
+    let mut channels = Map::new();
+    let mut operations = Map::new();
+    let mut messages = Map::new();
+
+    for event in events() {
+        let (prefix, action) = match event.direction {
+            Direction::ClientToServer => ("client", "receive"),
+            Direction::ServerToClient => ("server", "send"),
+        };
+        let channel_key = key(event.name);
+        let message_key = format!("{prefix}_{channel_key}");
+
+        let mut message = json!({
+            "name": event.name,
+            "summary": event.summary,
+        });
+        if let Some(payload) = &event.payload {
+            message["payload"] = (payload.subschema)(&mut generator).to_value();
+        }
+        messages.insert(message_key.clone(), message);
+
+        let channel = channels.entry(channel_key.clone()).or_insert_with(|| {
+            json!({
+                "address": event.name,
+                "messages": {},
+            })
+        });
+        channel["messages"][&message_key] =
+            json!({ "$ref": format!("#/components/messages/{message_key}") });
+
+        operations.insert(
+            message_key.clone(),
+            json!({
+                "action": action,
+                "summary": event.summary,
+                "channel": { "$ref": format!("#/channels/{channel_key}") },
+                "messages": [
+                    { "$ref": format!("#/channels/{channel_key}/messages/{message_key}") },
+                ],
+            }),
+        );
+    }
+
+    let auth = generator.subschema_for::<ConnectAuth>().to_value();
+
+    json!({
+        "asyncapi": "3.0.0",
+        "info": {
+            "title": "simple-chat socket protocol",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": format!(
+                "Socket.io events on the default namespace. Clients may send a \
+                 handshake auth payload matching {}.",
+                auth["$ref"].as_str().unwrap_or("ConnectAuth")
+            ),
+        },
+        "channels": channels,
+        "operations": operations,
+        "components": {
+            "messages": messages,
+            "schemas": generator.take_definitions(true),
+        },
+    })
+}
+
+/// Standalone JSON Schemas for every payload, keyed by type name.
+pub fn payload_schemas() -> Vec<(String, Schema)> {
+    let mut schemas: Vec<(String, Schema)> = Vec::new();
+
+    for payload in events()
+        .into_iter()
+        .filter_map(|event| event.payload)
+        .chain(payload::<ConnectAuth>())
+        .filter(|payload| !(payload.inline)())
+    {
+        let name = (payload.name)().into_owned();
+        if !schemas.iter().any(|(existing, _)| *existing == name) {
+            schemas.push((name, (payload.root)()));
+        }
+    }
+
+    schemas
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    #[test]
+    fn export_protocol_schema() {
+        let dir = Path::new(env!("SCHEMA_EXPORT_DIR"));
+        let schemas_dir = dir.join("schemas");
+        fs::create_dir_all(&schemas_dir).unwrap();
+
+        let document = serde_json::to_string_pretty(&super::asyncapi_document()).unwrap();
+        fs::write(dir.join("asyncapi.json"), document + "\n").unwrap();
+
+        for (name, schema) in super::payload_schemas() {
+            let schema = serde_json::to_string_pretty(&schema).unwrap();
+            fs::write(schemas_dir.join(format!("{name}.json")), schema + "\n").unwrap();
+        }
+    }
+}
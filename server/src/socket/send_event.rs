@@ -1,54 +1,213 @@
-use chrono::Utc;
+use std::{collections::HashSet, net::IpAddr};
+
+use chrono::{Duration, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::{
     SocketIo,
-    extract::{Data, SocketRef, State},
+    extract::{SocketRef, State, TryData},
+    socket::Sid,
 };
 use ts_rs::TS;
 use uuid::Uuid;
 
 use crate::{
+    formatting, link_preview,
     models::{MessageReply, ReplyMessageType, RoomEvent, RoomEventData},
+    rate_limit::{Action, RateLimited},
+    socket::{
+        ErrorResponse, HandlerError,
+        commands::{self, Parsed},
+        emit_error, media,
+        mentions::{self, Mentions},
+        moderation, polls, protocol, rate_limits, session,
+    },
     state::AppState,
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct SendEventPayload {
     pub room: Uuid,
     pub payload: RoomEventData,
+    /// Seconds until the message disappears, overriding the room default.
+    #[serde(default)]
+    pub ttl_secs: Option<u32>,
 }
 
 pub async fn handle(
     s: SocketRef,
     io: SocketIo,
-    Data(data): Data<SendEventPayload>,
+    TryData(data): TryData<SendEventPayload>,
     State(state): State<AppState>,
 ) {
-    {
-        let Some(room) = state.rooms.get(&data.room) else {
-            println!("Room {} not found for user {}", data.room, s.id);
+    // Newer clients may send event types this server does not know yet.
+    let mut data = match data {
+        Ok(data) => data,
+        Err(e) => {
+            println!(
+                "User {} sent an event that could not be decoded: {}",
+                s.id, e
+            );
+            emit_error(&s, "Unsupported or malformed event");
             return;
-        };
+        }
+    };
 
-        if !room.members.contains(&s.id) {
-            println!("User {} not a member of room {}", s.id, data.room);
-            return;
-        };
+    if let Err(e) = ensure_member(&state, data.room, s.id) {
+        println!("User {} cannot post to room {}: {}", s.id, data.room, e);
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
     }
 
-    let id = Uuid::new_v4();
+    let user_key = session::user_key(&state, s.id);
+    if let Err(limited) = check_rate_limit(&state, data.room, user_key, session::address(&s)) {
+        rate_limits::emit_rate_limited(&s, Some(data.room), &limited);
+        return;
+    }
+
+    if let RoomEventData::Message(message_event) = &mut data.payload {
+        match commands::parse(&message_event.content) {
+            Parsed::Command { name, args } => {
+                commands::run(&s, &io, &state, data.room, &name, &args).await;
+                return;
+            }
+            Parsed::Escaped => commands::unescape(message_event),
+            Parsed::Message => {}
+        }
+    }
+
+    if let Err(message) = deliver(&io, &state, s.id, data).await {
+        emit_error(&s, &message);
+    }
+}
+
+pub fn ensure_member(state: &AppState, room_id: Uuid, user: Sid) -> Result<(), HandlerError> {
+    let room = state
+        .rooms
+        .get(&room_id)
+        .ok_or(HandlerError::NotFound("Room does not exist"))?;
+
+    if !room.members.contains(&user) {
+        return Err(HandlerError::Forbidden("You are not a member of this room"));
+    }
+
+    Ok(())
+}
+
+/// Applies the room's slow mode, which moderators are exempt from, and the
+/// sender's `room.send` limit. Slow mode is checked first so that a message it
+/// refuses does not also use up one of the sender's tokens.
+pub fn check_rate_limit(
+    state: &AppState,
+    room_id: Uuid,
+    user_key: Uuid,
+    address: Option<IpAddr>,
+) -> Result<(), RateLimited> {
+    let slow_mode_interval = state.rooms.get(&room_id).and_then(|room| {
+        room.slow_mode
+            .as_ref()
+            .filter(|slow_mode| slow_mode.is_active(Utc::now()))
+            .filter(|_| !room.is_moderator(&user_key))
+            .map(|slow_mode| std::time::Duration::from_secs(u64::from(slow_mode.interval_secs)))
+    });
+
+    if let Some(interval) = slow_mode_interval {
+        state
+            .rate_limits
+            .check_slow_mode(room_id, user_key, interval)?;
+    }
+
+    state
+        .rate_limits
+        .check(user_key, address, Action::RoomSend)?;
+
+    if slow_mode_interval.is_some() {
+        state.rate_limits.record_post(room_id, user_key);
+    }
+
+    Ok(())
+}
 
+/// Stores and broadcasts an event on behalf of `from`. Callers are
+/// responsible for checking that `from` may post into the room. Returns a
+/// message for the sender if the event is rejected.
+pub async fn deliver(
+    io: &SocketIo,
+    state: &AppState,
+    from: Sid,
+    data: SendEventPayload,
+) -> Result<RoomEvent, String> {
     let mut event_data = data.payload.clone();
 
+    // Everything else records a change to an earlier event, and is only ever
+    // created by the server once it has checked the change is allowed.
+    // Reactions have no handler of their own and are sent as they are.
+    if !matches!(
+        event_data,
+        RoomEventData::Message(_)
+            | RoomEventData::Image(_)
+            | RoomEventData::Audio(_)
+            | RoomEventData::Poll(_)
+            | RoomEventData::Reaction(_)
+            | RoomEventData::ReactionRemove(_)
+    ) {
+        println!("Rejected server-only event from user {}", from);
+        return Err("This event type cannot be sent".to_string());
+    }
+
+    if let Err(e) = state.limits.event(&mut event_data) {
+        println!("Rejected event from user {}: {}", from, e);
+        return Err(e.to_string());
+    }
+
+    let mut flags = Vec::new();
+    if let RoomEventData::Message(message_event) = &mut event_data {
+        let content = std::mem::take(&mut message_event.content);
+        (message_event.content, message_event.formatted) =
+            formatting::apply(content, message_event.formatted.as_ref());
+
+        flags = moderation::screen(
+            state,
+            data.room,
+            from,
+            &mut message_event.content,
+            &mut message_event.formatted,
+        )?;
+    }
+
+    let (ttl_secs, mentions) = {
+        let Some(room) = state.rooms.get(&data.room) else {
+            println!("Room {} not found for user {}", data.room, from);
+            return Err("Room does not exist".to_string());
+        };
+
+        let mentions = match &event_data {
+            RoomEventData::Message(message_event) => {
+                mentions::resolve(state, &room.members, &message_event.content)
+            }
+            _ => Mentions::default(),
+        };
+
+        (data.ttl_secs.or(room.policy.message_ttl_secs), mentions)
+    };
+
+    let id = Uuid::new_v4();
+    let timestamp = Utc::now();
+    let expires_at = ttl_secs.map(|secs| timestamp + Duration::seconds(i64::from(secs)));
+
     match &mut event_data {
         RoomEventData::Message(message_event) => {
             message_event.edited = false;
             message_event.deleted = false;
+            message_event.expires_at = expires_at;
+            message_event.mentions = mentions.users.clone();
+            message_event.mentions_room = mentions.room;
+            message_event.link_previews = Vec::new();
 
             if let Some(reply) = &mut message_event.reply_to {
                 if let Some(reply_info) =
-                    validate_and_enrich_reply(&reply.message_id, &data.room, &state)
+                    validate_and_enrich_reply(&reply.message_id, &data.room, state)
                 {
                     *reply = reply_info;
                 } else {
@@ -57,9 +216,12 @@ pub async fn handle(
             }
         }
         RoomEventData::Image(image_event) => {
+            image_event.deleted = false;
+            image_event.expires_at = expires_at;
+
             if let Some(reply) = &mut image_event.reply_to {
                 if let Some(reply_info) =
-                    validate_and_enrich_reply(&reply.message_id, &data.room, &state)
+                    validate_and_enrich_reply(&reply.message_id, &data.room, state)
                 {
                     *reply = reply_info;
                 } else {
@@ -67,27 +229,83 @@ pub async fn handle(
                 }
             }
         }
+        RoomEventData::Audio(audio_event) => {
+            if let Err(e) = media::prepare_audio(state, from, audio_event) {
+                println!("Rejected audio from user {}: {}", from, e);
+                return Err(e);
+            }
+
+            audio_event.deleted = false;
+            audio_event.expires_at = expires_at;
+
+            if let Some(reply) = &mut audio_event.reply_to {
+                if let Some(reply_info) =
+                    validate_and_enrich_reply(&reply.message_id, &data.room, state)
+                {
+                    *reply = reply_info;
+                } else {
+                    audio_event.reply_to = None;
+                }
+            }
+        }
+        RoomEventData::Poll(poll) => {
+            if let Err(e) = polls::prepare(poll, timestamp) {
+                println!("Rejected poll from user {}: {}", from, e);
+                return Err(e.to_string());
+            }
+        }
         _ => {}
     }
 
     let event = RoomEvent {
         id,
-        from: s.id,
-        timestamp: Utc::now(),
+        from,
+        timestamp,
         data: event_data,
+        sender_key: state.user_keys.get(&from).map(|key| *key),
     };
 
     if let Some(mut room) = state.rooms.get_mut(&data.room) {
         room.events.push(event.clone());
     }
 
-    if let Err(e) = io
-        .to(data.room.to_string())
-        .emit("room.event", &event)
-        .await
-    {
-        println!("Failed to broadcast message to room {}: {}", data.room, e);
+    protocol::broadcast_room_event(io, state, data.room, &event).await;
+
+    if let RoomEventData::Message(message_event) = &event.data {
+        let urls = link_preview::extract_urls(&message_event.content);
+        if !urls.is_empty() {
+            tokio::spawn(link_preview::unfurl(
+                io.clone(),
+                state.clone(),
+                data.room,
+                event.id,
+                from,
+                urls,
+            ));
+        }
+
+        mentions::notify(
+            io,
+            state,
+            data.room,
+            &event,
+            &message_event.content,
+            &mentions,
+            &HashSet::new(),
+        );
+
+        moderation::flag(
+            io,
+            state,
+            data.room,
+            event.id,
+            from,
+            &message_event.content,
+            flags,
+        );
     }
+
+    Ok(event)
 }
 
 fn validate_and_enrich_reply(
@@ -109,15 +327,40 @@ fn validate_and_enrich_reply(
                     ReplyMessageType::Deleted,
                 )
             } else {
-                let preview = if msg.content.len() > 100 {
-                    format!("{}...", &msg.content[..100])
-                } else {
-                    msg.content.clone()
+                // Cut on a character boundary; slicing bytes would panic
+                // inside a multibyte character.
+                let preview = match msg.content.char_indices().nth(100) {
+                    Some((end, _)) => format!("{}...", &msg.content[..end]),
+                    None => msg.content.clone(),
                 };
                 (preview, ReplyMessageType::Text)
             }
         }
-        RoomEventData::Image(img) => (format!("📷 {}", img.filename), ReplyMessageType::Image),
+        RoomEventData::Image(img) => {
+            if img.deleted {
+                (
+                    "This message was deleted".to_string(),
+                    ReplyMessageType::Deleted,
+                )
+            } else {
+                (format!("📷 {}", img.filename), ReplyMessageType::Image)
+            }
+        }
+        RoomEventData::Audio(audio) => {
+            if audio.deleted {
+                (
+                    "This message was deleted".to_string(),
+                    ReplyMessageType::Deleted,
+                )
+            } else {
+                let seconds = audio.duration_ms / 1000;
+                (
+                    format!("🎤 Voice message ({}:{:02})", seconds / 60, seconds % 60),
+                    ReplyMessageType::Audio,
+                )
+            }
+        }
+        RoomEventData::Poll(poll) => (format!("📊 {}", poll.question), ReplyMessageType::Text),
         _ => return None,
     };
 
@@ -129,3 +372,180 @@ fn validate_and_enrich_reply(
         message_type,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            LinkPreview, LinkPreviewEvent, MessagePinEvent, MessageStarEvent, MessageUnstarEvent,
+            PinPermission, Room, SlowMode, TextMessageEvent, TopicChangeEvent,
+        },
+        rate_limit::{Limit, RateLimiter},
+        socket::test_io,
+    };
+    use std::{collections::HashMap, sync::Arc};
+
+    /// A room with one member, who has sent one message.
+    fn room_with_message(state: &AppState, member: Sid) -> (Uuid, Uuid) {
+        let mut room = Room::new(Uuid::new_v4(), "room".to_string());
+        room.members.insert(member);
+        let message_id = Uuid::new_v4();
+        room.events.push(RoomEvent {
+            id: message_id,
+            from: member,
+            timestamp: Utc::now(),
+            data: RoomEventData::Message(TextMessageEvent {
+                content: "hello".to_string(),
+                edited: false,
+                deleted: false,
+                reply_to: None,
+                expires_at: None,
+                mentions: Vec::new(),
+                mentions_room: false,
+                formatted: None,
+                link_previews: Vec::new(),
+                emote: false,
+            }),
+            sender_key: None,
+        });
+        let room_id = room.id;
+        state.rooms.insert(room_id, room);
+        (room_id, message_id)
+    }
+
+    async fn send(
+        state: &AppState,
+        from: Sid,
+        room: Uuid,
+        payload: RoomEventData,
+    ) -> Result<RoomEvent, String> {
+        deliver(
+            &test_io(),
+            state,
+            from,
+            SendEventPayload {
+                room,
+                payload,
+                ttl_secs: None,
+            },
+        )
+        .await
+    }
+
+    #[test]
+    fn reply_previews_cut_between_multibyte_characters() {
+        let state = AppState::from_env();
+        let (room_id, message_id) = room_with_message(&state, Sid::new());
+        // Byte 100 falls inside the first "é".
+        let content = format!("{}{}", "a".repeat(99), "é".repeat(5));
+        if let RoomEventData::Message(message) =
+            &mut state.rooms.get_mut(&room_id).unwrap().events[0].data
+        {
+            message.content = content;
+        }
+
+        let reply = validate_and_enrich_reply(&message_id, &room_id, &state).unwrap();
+
+        assert_eq!(reply.content_preview, format!("{}é...", "a".repeat(99)));
+    }
+
+    #[test]
+    fn slow_mode_refusals_keep_the_senders_tokens() {
+        let mut state = AppState::from_env();
+        state.rate_limits = Arc::new(RateLimiter::new(HashMap::from([(
+            Action::RoomSend,
+            Limit::new(2, 60),
+        )])));
+        let (room_id, _) = room_with_message(&state, Sid::new());
+        state.rooms.get_mut(&room_id).unwrap().slow_mode = Some(SlowMode {
+            interval_secs: 60,
+            until: None,
+        });
+        let user_key = Uuid::new_v4();
+
+        assert!(check_rate_limit(&state, room_id, user_key, None).is_ok());
+        for _ in 0..3 {
+            let limited = check_rate_limit(&state, room_id, user_key, None).unwrap_err();
+            assert!(limited.slow_mode);
+        }
+
+        assert!(
+            state
+                .rate_limits
+                .check(user_key, None, Action::RoomSend)
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_forged_pins() {
+        let state = AppState::from_env();
+        let member = Sid::new();
+        let (room_id, message_id) = room_with_message(&state, member);
+        state.rooms.get_mut(&room_id).unwrap().policy.pin_permission = PinPermission::Moderators;
+
+        let forged = RoomEventData::MessagePin(MessagePinEvent { message_id });
+        assert!(send(&state, member, room_id, forged).await.is_err());
+
+        let room = state.rooms.get(&room_id).unwrap();
+        assert_eq!(room.events.len(), 1);
+        assert!(room.pinned.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_stars_sent_to_the_room() {
+        let state = AppState::from_env();
+        let member = Sid::new();
+        let (room_id, message_id) = room_with_message(&state, member);
+
+        let star = RoomEventData::MessageStar(MessageStarEvent { message_id });
+        assert!(send(&state, member, room_id, star).await.is_err());
+        let unstar = RoomEventData::MessageUnstar(MessageUnstarEvent { message_id });
+        assert!(send(&state, member, room_id, unstar).await.is_err());
+
+        assert_eq!(state.rooms.get(&room_id).unwrap().events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_forged_link_previews() {
+        let state = AppState::from_env();
+        let member = Sid::new();
+        let (room_id, message_id) = room_with_message(&state, member);
+
+        let forged = RoomEventData::LinkPreview(LinkPreviewEvent {
+            message_id,
+            previews: vec![LinkPreview {
+                url: "https://bank.example/login".to_string(),
+                title: Some("Your bank".to_string()),
+                description: None,
+                image_url: Some("http://169.254.169.254/latest/meta-data".to_string()),
+                site_name: None,
+            }],
+        });
+        assert!(send(&state, member, room_id, forged).await.is_err());
+
+        let room = state.rooms.get(&room_id).unwrap();
+        assert_eq!(room.events.len(), 1);
+        assert!(matches!(
+            &room.events[0].data,
+            RoomEventData::Message(message) if message.link_previews.is_empty()
+        ));
+    }
+
+    #[tokio::test]
+    async fn refuses_topic_changes_sent_to_the_room() {
+        let state = AppState::from_env();
+        let member = Sid::new();
+        let (room_id, _) = room_with_message(&state, member);
+
+        let forged = RoomEventData::TopicChange(TopicChangeEvent {
+            topic: "Free crypto, DM me".to_string(),
+        });
+        assert!(send(&state, member, room_id, forged).await.is_err());
+
+        let room = state.rooms.get(&room_id).unwrap();
+        assert_eq!(room.events.len(), 1);
+        assert!(room.topic.is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use std::net::IpAddr;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::{
+    extract::{SocketRef, TryData},
+    socket::Sid,
+};
+use tracing::{info, warn};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    rate_limit,
+    socket::{
+        HandlerError, emit_error,
+        protocol::{Capability, ClientProtocol, MIN_PROTOCOL_VERSION},
+    },
+    state::AppState,
+};
+
+/// Auth payload clients may send with the socket.io handshake.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, TS, JsonSchema)]
+#[ts(export)]
+pub struct ConnectAuth {
+    /// Key returned in a previous `session.info`, used to restore per-user data.
+    #[serde(default)]
+    pub user_key: Option<Uuid>,
+    /// Protocol version the client was built against. Clients that leave it
+    /// out are treated as speaking version 1.
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    /// Capabilities the client understands. Defaults to everything its
+    /// protocol version includes; unknown names are ignored.
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
+    /// Connects as a bot instead of a user.
+    #[serde(default)]
+    pub bot_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct SessionInfo {
+    pub user_key: Uuid,
+    /// The protocol version negotiated for this connection.
+    pub protocol_version: u32,
+    pub server_version: String,
+    /// Every capability the server supports, whether or not the client
+    /// asked for it.
+    pub capabilities: Vec<Capability>,
+}
+
+pub fn start_session(s: &SocketRef, state: &AppState, TryData(auth): TryData<ConnectAuth>) {
+    let auth = auth.unwrap_or_else(|e| {
+        warn!("Ignoring malformed auth payload from {}: {}", s.id, e);
+        ConnectAuth::default()
+    });
+
+    let Some(protocol) =
+        ClientProtocol::negotiate(auth.protocol_version, auth.capabilities.as_deref())
+    else {
+        warn!(
+            "Rejecting {} with unsupported protocol version {:?}",
+            s.id, auth.protocol_version
+        );
+        emit_error(
+            s,
+            &format!(
+                "Protocol version {:?} is no longer supported, the oldest supported version is {}",
+                auth.protocol_version, MIN_PROTOCOL_VERSION
+            ),
+        );
+        s.clone().disconnect().ok();
+        return;
+    };
+
+    let bot = match auth.bot_token.as_deref() {
+        Some(token) => match state.bots.by_token(token) {
+            Some(bot) => Some(bot),
+            None => {
+                warn!("Rejecting {} with an unknown bot token", s.id);
+                emit_error(s, "Unknown bot token");
+                s.clone().disconnect().ok();
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // Bots keep their data under their own id rather than a client key.
+    let user_key = match &bot {
+        Some(bot) => bot.id,
+        None => {
+            let user_key = auth.user_key.unwrap_or_else(Uuid::new_v4);
+            state.issued_keys.insert(user_key);
+            user_key
+        }
+    };
+    state.user_keys.insert(s.id, user_key);
+
+    if let Some(bot) = bot {
+        info!("Bot {} ({}) connected as {}", bot.id, bot.name, s.id);
+        state.usernames.insert(s.id, bot.name);
+        state.bots.connect(s.id, bot.id);
+    }
+
+    info!(
+        "User {} connected with user key {} on protocol version {}",
+        s.id, user_key, protocol.version
+    );
+
+    let session_info = SessionInfo {
+        user_key,
+        protocol_version: protocol.version,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: Capability::ALL.to_vec(),
+    };
+    state.client_protocols.insert(s.id, protocol);
+
+    if let Err(e) = s.emit("session.info", &session_info) {
+        warn!("Failed to send session info to {}: {}", s.id, e);
+    }
+}
+
+pub fn end_session(s: &SocketRef, state: &AppState) {
+    state.user_keys.remove(&s.id);
+    state.client_protocols.remove(&s.id);
+    state.bots.disconnect(s.id);
+}
+
+/// Returns the stable key for a connected socket.
+pub fn user_key(state: &AppState, sid: Sid) -> Uuid {
+    *state.user_keys.entry(sid).or_insert_with(Uuid::new_v4)
+}
+
+/// Returns the address `s` connected from, if known.
+pub fn address(s: &SocketRef) -> Option<IpAddr> {
+    rate_limit::client_address(&s.req_parts().extensions)
+}
+
+/// Refuses `user` unless they moderate `room_id`, giving `denied` as the
+/// reason.
+pub fn ensure_moderator(
+    state: &AppState,
+    room_id: Uuid,
+    user: Sid,
+    denied: &'static str,
+) -> Result<(), HandlerError> {
+    let room = state
+        .rooms
+        .get(&room_id)
+        .ok_or(HandlerError::NotFound("Room does not exist"))?;
+
+    if !room.is_moderator(&user_key(state, user)) {
+        return Err(HandlerError::Forbidden(denied));
+    }
+
+    Ok(())
+}
+
+/// Returns every connected socket that belongs to the user with `user_key`.
+pub fn sockets_for(state: &AppState, user_key: Uuid) -> Vec<Sid> {
+    state
+        .user_keys
+        .iter()
+        .filter(|entry| *entry.value() == user_key)
+        .map(|entry| *entry.key())
+        .collect()
+}
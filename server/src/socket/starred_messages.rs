@@ -1,36 +1,67 @@
+use chrono::{DateTime, Utc};
 use socketioxide::extract::{Data, SocketRef, State};
-use std::collections::HashSet;
 use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::{
     models::{MessageStarEvent, MessageUnstarEvent, RoomEvent, RoomEventData},
+    socket::{emit_error, session},
     state::AppState,
 };
 
-#[derive(serde::Deserialize, Debug, ts_rs::TS)]
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(serde::Deserialize, Debug, ts_rs::TS, schemars::JsonSchema)]
 #[ts(export)]
 pub struct StarMessageRequest {
     pub room_id: Uuid,
     pub message_id: Uuid,
 }
 
-#[derive(serde::Deserialize, Debug, ts_rs::TS)]
+#[derive(serde::Deserialize, Debug, ts_rs::TS, schemars::JsonSchema)]
 #[ts(export)]
 pub struct UnstarMessageRequest {
     pub room_id: Uuid,
     pub message_id: Uuid,
 }
 
-#[derive(serde::Serialize, Debug, ts_rs::TS)]
+#[derive(serde::Deserialize, Debug, Default, ts_rs::TS, schemars::JsonSchema)]
 #[ts(export)]
-pub struct StarredMessagesResponse {
-    pub starred_message_ids: Vec<Uuid>,
+pub struct GetStarredMessagesRequest {
+    /// Restrict the results to a single room.
+    #[serde(default)]
+    pub room_id: Option<Uuid>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
-#[derive(serde::Serialize, Debug)]
-pub struct ErrorResponse {
-    pub message: String,
+/// A saved message, keyed by the stable user key rather than the socket id.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct StarredItem {
+    pub room_id: Uuid,
+    pub message_id: Uuid,
+    pub starred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, Debug, ts_rs::TS, schemars::JsonSchema)]
+#[ts(export)]
+pub struct StarredMessage {
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub starred_at: DateTime<Utc>,
+    /// The message as it currently is, including any edits.
+    pub message: RoomEvent,
+}
+
+#[derive(serde::Serialize, Debug, ts_rs::TS, schemars::JsonSchema)]
+#[ts(export)]
+pub struct StarredMessagesResponse {
+    pub items: Vec<StarredMessage>,
+    pub total: usize,
+    pub next_offset: Option<usize>,
 }
 
 pub async fn star_message(
@@ -45,12 +76,7 @@ pub async fn star_message(
             "User {} tried to star message in non-existent room {}",
             user_id, data.room_id
         );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "Room does not exist".to_string(),
-            },
-        );
+        emit_error(&socket, "Room does not exist");
         return;
     }
 
@@ -65,12 +91,7 @@ pub async fn star_message(
             "User {} tried to star message in room {} they're not a member of",
             user_id, data.room_id
         );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "You are not a member of this room".to_string(),
-            },
-        );
+        emit_error(&socket, "You are not a member of this room");
         return;
     }
 
@@ -85,35 +106,37 @@ pub async fn star_message(
             "User {} tried to star non-existent message {} in room {}",
             user_id, data.message_id, data.room_id
         );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "Message does not exist".to_string(),
-            },
-        );
+        emit_error(&socket, "Message does not exist");
         return;
     }
 
-    let key = (data.room_id, user_id);
-    state
-        .starred_messages
-        .entry(key)
-        .or_insert_with(HashSet::new)
-        .insert(data.message_id);
+    let user_key = session::user_key(&state, user_id);
+    {
+        let mut starred = state.starred_messages.entry(user_key).or_default();
+        if !starred
+            .iter()
+            .any(|item| item.message_id == data.message_id)
+        {
+            starred.push(StarredItem {
+                room_id: data.room_id,
+                message_id: data.message_id,
+                starred_at: Utc::now(),
+            });
+        }
+    }
 
+    // Stars are private, so the event only goes to the starrer and is not
+    // recorded in the room timeline.
     let star_event = RoomEvent {
         id: Uuid::new_v4(),
         from: user_id,
-        timestamp: chrono::Utc::now(),
+        timestamp: Utc::now(),
         data: RoomEventData::MessageStar(MessageStarEvent {
             message_id: data.message_id,
         }),
+        sender_key: None,
     };
 
-    if let Some(mut room) = state.rooms.get_mut(&data.room_id) {
-        room.events.push(star_event.clone());
-    }
-
     if let Err(e) = socket.emit("room.event", &star_event) {
         error!("Failed to emit star event: {}", e);
     }
@@ -130,44 +153,12 @@ pub async fn unstar_message(
     State(state): State<AppState>,
 ) {
     let user_id = socket.id;
+    let user_key = session::user_key(&state, user_id);
 
-    if !state.rooms.contains_key(&data.room_id) {
-        error!(
-            "User {} tried to unstar message in non-existent room {}",
-            user_id, data.room_id
-        );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "Room does not exist".to_string(),
-            },
-        );
-        return;
-    }
-
-    let is_member = state
-        .rooms
-        .get(&data.room_id)
-        .map(|room| room.members.contains(&user_id))
-        .unwrap_or(false);
-
-    if !is_member {
-        error!(
-            "User {} tried to unstar message in room {} they're not a member of",
-            user_id, data.room_id
-        );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "You are not a member of this room".to_string(),
-            },
-        );
-        return;
-    }
-
-    let key = (data.room_id, user_id);
-    let was_starred = if let Some(mut starred_set) = state.starred_messages.get_mut(&key) {
-        starred_set.remove(&data.message_id)
+    let was_starred = if let Some(mut starred) = state.starred_messages.get_mut(&user_key) {
+        let before = starred.len();
+        starred.retain(|item| item.message_id != data.message_id);
+        starred.len() != before
     } else {
         false
     };
@@ -177,28 +168,20 @@ pub async fn unstar_message(
             "User {} tried to unstar message {} that wasn't starred in room {}",
             user_id, data.message_id, data.room_id
         );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "Message was not starred".to_string(),
-            },
-        );
+        emit_error(&socket, "Message was not starred");
         return;
     }
 
     let unstar_event = RoomEvent {
         id: Uuid::new_v4(),
         from: user_id,
-        timestamp: chrono::Utc::now(),
+        timestamp: Utc::now(),
         data: RoomEventData::MessageUnstar(MessageUnstarEvent {
             message_id: data.message_id,
         }),
+        sender_key: None,
     };
 
-    if let Some(mut room) = state.rooms.get_mut(&data.room_id) {
-        room.events.push(unstar_event.clone());
-    }
-
     if let Err(e) = socket.emit("room.event", &unstar_event) {
         error!("Failed to emit unstar event: {}", e);
     }
@@ -211,54 +194,42 @@ pub async fn unstar_message(
 
 pub async fn get_starred_messages(
     socket: SocketRef,
-    Data(room_id): Data<Uuid>,
+    Data(data): Data<GetStarredMessagesRequest>,
     State(state): State<AppState>,
 ) {
     let user_id = socket.id;
+    let user_key = session::user_key(&state, user_id);
 
-    if !state.rooms.contains_key(&room_id) {
-        error!(
-            "User {} tried to get starred messages in non-existent room {}",
-            user_id, room_id
-        );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "Room does not exist".to_string(),
-            },
-        );
-        return;
-    }
-
-    let is_member = state
-        .rooms
-        .get(&room_id)
-        .map(|room| room.members.contains(&user_id))
-        .unwrap_or(false);
-
-    if !is_member {
-        error!(
-            "User {} tried to get starred messages in room {} they're not a member of",
-            user_id, room_id
-        );
-        let _ = socket.emit(
-            "error",
-            &ErrorResponse {
-                message: "You are not a member of this room".to_string(),
-            },
-        );
-        return;
-    }
-
-    let key = (room_id, user_id);
-    let starred_message_ids: Vec<Uuid> = state
+    let mut starred: Vec<StarredItem> = state
         .starred_messages
-        .get(&key)
-        .map(|starred_set| starred_set.iter().copied().collect())
+        .get(&user_key)
+        .map(|items| {
+            items
+                .iter()
+                .filter(|item| data.room_id.is_none_or(|room_id| item.room_id == room_id))
+                .cloned()
+                .collect()
+        })
         .unwrap_or_default();
+    starred.sort_by_key(|item| std::cmp::Reverse(item.starred_at));
+
+    let items: Vec<StarredMessage> = starred
+        .iter()
+        .filter_map(|item| snapshot(&state, item))
+        .collect();
+
+    let total = items.len();
+    let limit = data
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let items: Vec<StarredMessage> = items.into_iter().skip(data.offset).take(limit).collect();
+    let next_offset = Some(data.offset + items.len()).filter(|next| *next < total);
 
     let response = StarredMessagesResponse {
-        starred_message_ids,
+        items,
+        total,
+        next_offset,
     };
 
     if let Err(e) = socket.emit("starred_messages.list", &response) {
@@ -266,9 +237,25 @@ pub async fn get_starred_messages(
     }
 
     info!(
-        "User {} requested starred messages in room {}, found {} messages",
+        "User {} requested starred messages, returning {} of {}",
         user_id,
-        room_id,
-        response.starred_message_ids.len()
+        response.items.len(),
+        response.total
     );
 }
+
+fn snapshot(state: &AppState, item: &StarredItem) -> Option<StarredMessage> {
+    let room = state.rooms.get(&item.room_id)?;
+    let message = room
+        .events
+        .iter()
+        .find(|event| event.id == item.message_id)?
+        .clone();
+
+    Some(StarredMessage {
+        room_id: item.room_id,
+        room_name: room.name.clone(),
+        starred_at: item.starred_at,
+        message,
+    })
+}
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::{
     SocketIo,
@@ -7,21 +8,25 @@ use socketioxide::{
 use ts_rs::TS;
 use uuid::Uuid;
 
-use crate::state::AppState;
+use crate::{
+    rate_limit::Action,
+    socket::{blocking, rate_limits, session},
+    state::AppState,
+};
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct StartTypingPayload {
     pub room_id: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct StopTypingPayload {
     pub room_id: Uuid,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct TypingIndicator {
     pub user_id: String,
@@ -39,6 +44,15 @@ pub async fn start_typing(
         return;
     }
 
+    if let Err(limited) = state.rate_limits.check(
+        session::user_key(&state, s.id),
+        session::address(&s),
+        Action::TypingStart,
+    ) {
+        rate_limits::emit_rate_limited(&s, Some(data.room_id), &limited);
+        return;
+    }
+
     let username = state.usernames.get(&s.id).map(|u| u.clone());
 
     let typing_indicator = TypingIndicator {
@@ -47,14 +61,15 @@ pub async fn start_typing(
         room_id: data.room_id.to_string(),
     };
 
-    if let Err(e) = io
-        .to(data.room_id.to_string())
-        .except(s.id.to_string())
-        .emit("typing.start", &typing_indicator)
-        .await
-    {
-        println!("Failed to broadcast typing start: {}", e);
-    }
+    broadcast(
+        &io,
+        &state,
+        &s,
+        data.room_id,
+        "typing.start",
+        &typing_indicator,
+    )
+    .await;
 }
 
 pub async fn stop_typing(
@@ -75,13 +90,43 @@ pub async fn stop_typing(
         room_id: data.room_id.to_string(),
     };
 
-    if let Err(e) = io
-        .to(data.room_id.to_string())
-        .except(s.id.to_string())
-        .emit("typing.stop", &typing_indicator)
-        .await
-    {
-        println!("Failed to broadcast typing stop: {}", e);
+    broadcast(
+        &io,
+        &state,
+        &s,
+        data.room_id,
+        "typing.stop",
+        &typing_indicator,
+    )
+    .await;
+}
+
+/// Sends a typing indicator to the rest of the room, leaving out users who
+/// have blocked the typist.
+async fn broadcast(
+    io: &SocketIo,
+    state: &AppState,
+    s: &SocketRef,
+    room_id: Uuid,
+    event: &str,
+    indicator: &TypingIndicator,
+) {
+    let blockers = blocking::blockers_of(state, s.id);
+
+    if blockers.is_empty() {
+        if let Err(e) = s.to(room_id.to_string()).emit(event, indicator).await {
+            println!("Failed to broadcast {}: {}", event, e);
+        }
+        return;
+    }
+
+    for socket in io.to(room_id.to_string()).sockets() {
+        if socket.id == s.id || blockers.contains(&socket.id) {
+            continue;
+        }
+        if let Err(e) = socket.emit(event, indicator) {
+            println!("Failed to send {} to {}: {}", event, socket.id, e);
+        }
     }
 }
 
@@ -1,43 +1,172 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use socketioxide::{
     SocketIo,
     extract::{Data, SocketRef, State},
+    socket::Sid,
 };
 use ts_rs::TS;
 use uuid::Uuid;
 
 use crate::{
+    audit::AuditAction,
     models::{
-        RoomEvent, RoomEventData, RoomMember, RoomMembersResponse, UserJoinEvent, UserLeaveEvent,
+        Room, RoomEvent, RoomEventData, RoomMember, RoomMembersResponse, UserJoinEvent,
+        UserLeaveEvent,
     },
+    rate_limit::{Action, RateLimited},
+    socket::{ErrorResponse, HandlerError, audit, protocol, rate_limits, session},
     state::AppState,
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct SetUsernamePayload {
     pub username: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
 #[ts(export)]
 pub struct GetMembersPayload {
     pub room_id: Uuid,
 }
 
+/// Sent to a room when one of its members changes their username.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct UserRenamedEvent {
+    pub room_id: Uuid,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+    pub old_username: Option<String>,
+    pub username: String,
+}
+
+pub enum RenameError {
+    Rejected(HandlerError),
+    RateLimited(RateLimited),
+}
+
+impl std::fmt::Display for RenameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameError::Rejected(e) => write!(f, "{e}"),
+            RenameError::RateLimited(limited) => write!(f, "{limited}"),
+        }
+    }
+}
+
+impl From<HandlerError> for RenameError {
+    fn from(error: HandlerError) -> Self {
+        RenameError::Rejected(error)
+    }
+}
+
 pub async fn set_username(
     s: SocketRef,
+    io: SocketIo,
     Data(data): Data<SetUsernamePayload>,
     State(state): State<AppState>,
 ) {
-    println!("User {} setting username to: {}", s.id, data.username);
+    match change_username(&s, &io, &state, &data.username).await {
+        Ok(_) => {}
+        Err(RenameError::Rejected(e)) => {
+            let _ = s.emit("error", &ErrorResponse::from(e));
+        }
+        Err(RenameError::RateLimited(limited)) => {
+            rate_limits::emit_rate_limited(&s, None, &limited);
+        }
+    }
+}
 
-    // Store username in state
-    state.usernames.insert(s.id, data.username.clone());
+/// Gives the user behind `s` a new username on all of their connections and
+/// tells the rooms they are in.
+pub async fn change_username(
+    s: &SocketRef,
+    io: &SocketIo,
+    state: &AppState,
+    username: &str,
+) -> Result<String, RenameError> {
+    let username = state.limits.username(username)?;
+    let user_key = session::user_key(state, s.id);
 
-    // Emit success response
-    if let Err(e) = s.emit("username.set", &data.username) {
-        eprintln!("Failed to confirm username set: {}", e);
+    let changed = state
+        .username_registry
+        .check(user_key, &username)
+        .map_err(|e| HandlerError::Invalid(e.to_string()))?;
+    // Taking back your own name after reconnecting is free.
+    if changed {
+        state
+            .rate_limits
+            .check(user_key, session::address(s), Action::UsernameChange)
+            .map_err(RenameError::RateLimited)?;
+    }
+    state
+        .username_registry
+        .claim(user_key, &username)
+        .map_err(|e| HandlerError::Invalid(e.to_string()))?;
+
+    println!("User {} setting username to: {}", s.id, username);
+
+    // Every socket of the user shares the name, so the change is recorded once.
+    let mut renamed_from = None;
+    for sid in session::sockets_for(state, user_key) {
+        let old_username = state.usernames.insert(sid, username.clone());
+        if old_username.as_ref() != Some(&username) {
+            renamed_from.get_or_insert_with(|| old_username.clone());
+            announce_rename(io, state, sid, old_username, &username).await;
+        }
+
+        if let Some(socket) = io.get_socket(sid)
+            && let Err(e) = socket.emit("username.set", &username)
+        {
+            eprintln!("Failed to confirm username set: {}", e);
+        }
+    }
+
+    if let Some(old_username) = renamed_from {
+        state.audit.record(
+            audit::entry(state, AuditAction::UsernameChanged, s.id)
+                .target(s.id)
+                .change(old_username, Some(username.clone())),
+        );
+    }
+
+    Ok(username)
+}
+
+async fn announce_rename(
+    io: &SocketIo,
+    state: &AppState,
+    user_id: Sid,
+    old_username: Option<String>,
+    username: &str,
+) {
+    let rooms: Vec<Uuid> = state
+        .rooms
+        .iter()
+        .filter(|room| room.members.contains(&user_id))
+        .map(|room| room.id)
+        .collect();
+
+    for room_id in rooms {
+        let event = UserRenamedEvent {
+            room_id,
+            user_id,
+            old_username: old_username.clone(),
+            username: username.to_string(),
+        };
+
+        if let Err(e) = io
+            .to(room_id.to_string())
+            .emit("user.renamed", &event)
+            .await
+        {
+            println!("Failed to broadcast rename: {}", e);
+        }
+
+        send_updated_members_to_room(io, state, room_id).await;
     }
 }
 
@@ -46,21 +175,17 @@ pub async fn get_room_members(
     Data(data): Data<GetMembersPayload>,
     State(state): State<AppState>,
 ) {
-    if let Some(room) = state.rooms.get(&data.room_id) {
-        let members: Vec<RoomMember> = room
-            .members
-            .iter()
-            .map(|member_id| {
-                let username = state.usernames.get(member_id).map(|u| u.clone());
-                RoomMember {
-                    user_id: *member_id,
-                    username,
-                }
-            })
-            .collect();
-
-        let response = RoomMembersResponse { members };
+    let user_key = session::user_key(&state, s.id);
+    if let Err(e) = ensure_can_join(&state, data.room_id, user_key) {
+        println!(
+            "User {} may not list members of room {}: {}",
+            s.id, data.room_id, e
+        );
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
 
+    if let Some(response) = room_members(&state, data.room_id) {
         if let Err(e) = s.emit("room.members", &response) {
             eprintln!("Failed to send room members: {}", e);
         }
@@ -75,102 +200,155 @@ pub async fn get_room_members(
     }
 }
 
-pub async fn handle_user_join_room(s: SocketRef, io: SocketIo, room_id: Uuid, state: AppState) {
-    let username = state.usernames.get(&s.id).map(|u| u.clone());
+/// Direct conversations are only visible to their participants, and banned
+/// users cannot rejoin.
+pub fn ensure_can_join(
+    state: &AppState,
+    room_id: Uuid,
+    user_key: Uuid,
+) -> Result<(), HandlerError> {
+    let room = state
+        .rooms
+        .get(&room_id)
+        .ok_or(HandlerError::NotFound("Room does not exist"))?;
+
+    if !room.can_join(&user_key) {
+        return Err(HandlerError::Forbidden(
+            "You are not a participant of this conversation",
+        ));
+    }
+    if room.is_banned(&user_key) {
+        return Err(HandlerError::Forbidden("You are banned from this room"));
+    }
+
+    Ok(())
+}
+
+pub fn room_members(state: &AppState, room_id: Uuid) -> Option<RoomMembersResponse> {
+    let room = state.rooms.get(&room_id)?;
+
+    let members = room
+        .members
+        .iter()
+        .map(|member_id| {
+            let username = state.usernames.get(member_id).map(|u| u.clone());
+            let profile = state
+                .user_keys
+                .get(member_id)
+                .and_then(|user_key| state.profiles.get(&*user_key))
+                .and_then(|profile| profile.summary());
+            RoomMember {
+                user_id: *member_id,
+                username,
+                is_bot: state.bots.is_bot(*member_id),
+                profile,
+            }
+        })
+        .collect();
+
+    Some(RoomMembersResponse { members })
+}
+
+pub async fn handle_user_join_room(user_id: Sid, io: SocketIo, room_id: Uuid, state: AppState) {
+    let username = state.usernames.get(&user_id).map(|u| u.clone());
 
     let join_event = RoomEvent {
         id: Uuid::new_v4(),
-        from: s.id,
+        from: user_id,
         timestamp: chrono::Utc::now(),
         data: RoomEventData::UserJoin(UserJoinEvent {
-            user_id: s.id,
+            user_id,
             username: username.clone(),
         }),
+        sender_key: None,
     };
 
     if let Some(mut room) = state.rooms.get_mut(&room_id) {
         room.events.push(join_event.clone());
     }
 
-    if let Err(e) = io
-        .to(room_id.to_string())
-        .emit("room.event", &join_event)
-        .await
-    {
-        println!("Failed to broadcast user join event: {}", e);
-    }
+    protocol::broadcast_room_event(&io, &state, room_id, &join_event).await;
 
     send_updated_members_to_room(&io, &state, room_id).await;
 
-    println!("User {} ({:?}) joined room {}", s.id, username, room_id);
+    println!("User {} ({:?}) joined room {}", user_id, username, room_id);
 }
 
-pub async fn handle_user_leave_room(s: SocketRef, io: SocketIo, room_id: Uuid, state: AppState) {
-    let username = state.usernames.get(&s.id).map(|u| u.clone());
+pub async fn handle_user_leave_room(user_id: Sid, io: SocketIo, room_id: Uuid, state: AppState) {
+    let username = state.usernames.get(&user_id).map(|u| u.clone());
 
     // Create user leave event
     let leave_event = RoomEvent {
         id: Uuid::new_v4(),
-        from: s.id,
+        from: user_id,
         timestamp: chrono::Utc::now(),
         data: RoomEventData::UserLeave(UserLeaveEvent {
-            user_id: s.id,
+            user_id,
             username: username.clone(),
         }),
+        sender_key: None,
     };
 
     if let Some(mut room) = state.rooms.get_mut(&room_id) {
         room.events.push(leave_event.clone());
     }
 
-    if let Err(e) = io
-        .to(room_id.to_string())
-        .emit("room.event", &leave_event)
-        .await
-    {
-        println!("Failed to broadcast user leave event: {}", e);
-    }
+    protocol::broadcast_room_event(&io, &state, room_id, &leave_event).await;
 
     send_updated_members_to_room(&io, &state, room_id).await;
 
-    println!("User {} ({:?}) left room {}", s.id, username, room_id);
+    println!("User {} ({:?}) left room {}", user_id, username, room_id);
 }
 
-async fn send_updated_members_to_room(io: &SocketIo, state: &AppState, room_id: Uuid) {
-    if let Some(room) = state.rooms.get(&room_id) {
-        let members: Vec<RoomMember> = room
-            .members
-            .iter()
-            .map(|member_id| {
-                let username = state.usernames.get(member_id).map(|u| u.clone());
-                RoomMember {
-                    user_id: *member_id,
-                    username,
-                }
-            })
-            .collect();
-
-        let response = RoomMembersResponse { members };
-
-        if let Err(e) = io
+pub async fn send_updated_members_to_room(io: &SocketIo, state: &AppState, room_id: Uuid) {
+    if let Some(response) = room_members(state, room_id)
+        && let Err(e) = io
             .to(room_id.to_string())
             .emit("room.members", &response)
             .await
-        {
-            println!("Failed to broadcast updated room members: {}", e);
-        }
+    {
+        println!("Failed to broadcast updated room members: {}", e);
     }
 }
 
 pub async fn handle_disconnect(s: SocketRef, io: SocketIo, state: AppState) {
     println!("User {} disconnecting, cleaning up from all rooms", s.id);
 
+    leave_all_rooms(&io, &state, s.id).await;
+
+    state.usernames.remove(&s.id);
+    session::end_session(&s, &state);
+
+    println!("Cleanup completed for disconnected user {}", s.id);
+}
+
+/// Takes the socket out of the room for good. The user stops being a member
+/// once none of their sockets are left in it. Returns whether the socket was
+/// in the room.
+pub fn remove_member(state: &AppState, room: &mut Room, user_id: Sid) -> bool {
+    let removed = room.members.remove(&user_id);
+
+    let user_key = session::user_key(state, user_id);
+    if !session::sockets_for(state, user_key)
+        .iter()
+        .any(|sid| room.members.contains(sid))
+    {
+        room.member_keys.remove(&user_key);
+    }
+
+    removed
+}
+
+/// Takes `user_id` out of every room it is in, announcing each departure.
+/// Disconnecting does not end room membership, so the user's key stays
+/// listed.
+pub async fn leave_all_rooms(io: &SocketIo, state: &AppState, user_id: Sid) {
     let rooms_to_leave: Vec<Uuid> = state
         .rooms
         .iter()
         .filter_map(|entry| {
             let (room_id, room) = entry.pair();
-            if room.members.contains(&s.id) {
+            if room.members.contains(&user_id) {
                 Some(*room_id)
             } else {
                 None
@@ -180,13 +358,33 @@ pub async fn handle_disconnect(s: SocketRef, io: SocketIo, state: AppState) {
 
     for room_id in rooms_to_leave {
         if let Some(mut room) = state.rooms.get_mut(&room_id) {
-            room.members.remove(&s.id);
+            room.members.remove(&user_id);
         }
 
-        handle_user_leave_room(s.clone(), io.clone(), room_id, state.clone()).await;
+        handle_user_leave_room(user_id, io.clone(), room_id, state.clone()).await;
     }
+}
 
-    state.usernames.remove(&s.id);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Cleanup completed for disconnected user {}", s.id);
+    #[test]
+    fn only_participants_may_see_a_direct_conversation() {
+        let state = AppState::from_env();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let room = Room::new_direct(Uuid::new_v4(), "dm".to_string(), vec![alice, bob]);
+        let room_id = room.id;
+        state.rooms.insert(room_id, room);
+
+        assert!(ensure_can_join(&state, room_id, alice).is_ok());
+        assert!(matches!(
+            ensure_can_join(&state, room_id, Uuid::new_v4()),
+            Err(HandlerError::Forbidden(_))
+        ));
+        assert!(matches!(
+            ensure_can_join(&state, Uuid::new_v4(), alice),
+            Err(HandlerError::NotFound(_))
+        ));
+    }
 }
@@ -0,0 +1,264 @@
+//! Limits and clean-up applied to client input before any handler stores it.
+//!
+//! Text is normalized to NFC and stripped of control characters. Fields that
+//! may span several lines keep their line breaks and tabs; single-line fields
+//! such as names have them replaced by spaces and are trimmed. Usernames also
+//! lose invisible formatting characters, which could otherwise make one name
+//! pass for another. The limits can
+//! be changed with `MAX_MESSAGE_CHARS`, `MAX_ROOM_NAME_CHARS`,
+//! `MAX_USERNAME_CHARS` and `MAX_IMAGE_BYTES`.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use tracing::warn;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    media::MAX_BLOB_BYTES,
+    models::{FormattedBody, ImageMessageEvent, RoomEventData},
+    socket::HandlerError,
+};
+
+const MAX_FILENAME_CHARS: usize = 255;
+const MAX_MIME_TYPE_CHARS: usize = 100;
+const MAX_REACTION_CHARS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub message_chars: usize,
+    pub room_name_chars: usize,
+    pub username_chars: usize,
+    /// Largest decoded image that may be sent inline in a message.
+    pub image_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            message_chars: 4_000,
+            room_name_chars: 64,
+            username_chars: 32,
+            image_bytes: MAX_BLOB_BYTES,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            message_chars: env_limit("MAX_MESSAGE_CHARS", defaults.message_chars),
+            room_name_chars: env_limit("MAX_ROOM_NAME_CHARS", defaults.room_name_chars),
+            username_chars: env_limit("MAX_USERNAME_CHARS", defaults.username_chars),
+            image_bytes: env_limit("MAX_IMAGE_BYTES", defaults.image_bytes),
+        }
+    }
+
+    /// Cleans the text of an event sent by a client and checks it against the
+    /// limits. Image sizes are measured from the decoded data.
+    pub fn event(&self, event: &mut RoomEventData) -> Result<(), HandlerError> {
+        match event {
+            RoomEventData::Message(message) => {
+                self.message(&mut message.content, message.formatted.as_mut())
+            }
+            RoomEventData::MessageEdit(edit) => {
+                self.message(&mut edit.new_content, edit.formatted.as_mut())
+            }
+            RoomEventData::Image(image) => self.image(image),
+            RoomEventData::Poll(poll) => {
+                poll.question = clean_text(&poll.question);
+                for option in &mut poll.options {
+                    option.text = clean_line(&option.text);
+                }
+                Ok(())
+            }
+            RoomEventData::Reaction(reaction) => reaction_text(&mut reaction.reaction),
+            RoomEventData::ReactionRemove(reaction) => reaction_text(&mut reaction.reaction),
+            _ => Ok(()),
+        }
+    }
+
+    /// Cleans a message body and its formatted version, if any.
+    pub fn message(
+        &self,
+        content: &mut String,
+        formatted: Option<&mut FormattedBody>,
+    ) -> Result<(), HandlerError> {
+        *content = clean_text(content);
+        check_length("Messages", content, self.message_chars)?;
+
+        match formatted {
+            Some(formatted) => {
+                formatted.body = clean_text(&formatted.body);
+                check_length("Messages", &formatted.body, self.message_chars)?;
+                if content.trim().is_empty() && formatted.body.trim().is_empty() {
+                    return Err(HandlerError::Invalid("Message cannot be empty".to_string()));
+                }
+            }
+            None if content.trim().is_empty() => {
+                return Err(HandlerError::Invalid("Message cannot be empty".to_string()));
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn room_name(&self, name: &str) -> Result<String, HandlerError> {
+        let name = clean_line(name);
+        if name.is_empty() {
+            return Err(HandlerError::Invalid(
+                "Room name cannot be empty".to_string(),
+            ));
+        }
+        check_length("Room names", &name, self.room_name_chars)?;
+        Ok(name)
+    }
+
+    pub fn username(&self, username: &str) -> Result<String, HandlerError> {
+        let username = clean_name(username);
+        if username.is_empty() {
+            return Err(HandlerError::Invalid(
+                "Username cannot be empty".to_string(),
+            ));
+        }
+        check_length("Usernames", &username, self.username_chars)?;
+        Ok(username)
+    }
+
+    fn image(&self, image: &mut ImageMessageEvent) -> Result<(), HandlerError> {
+        image.filename = clean_line(&image.filename);
+        check_length("File names", &image.filename, MAX_FILENAME_CHARS)?;
+
+        image.mime_type = clean_line(&image.mime_type).to_ascii_lowercase();
+        if !image.mime_type.starts_with("image/")
+            || image.mime_type.chars().count() > MAX_MIME_TYPE_CHARS
+        {
+            return Err(HandlerError::Invalid(format!(
+                "Unsupported image type {}",
+                image.mime_type
+            )));
+        }
+
+        // Refuse oversized payloads before spending time decoding them.
+        if image.image_data.len() / 4 * 3 > self.image_bytes + 2 {
+            return Err(self.image_too_large());
+        }
+
+        let size = STANDARD
+            .decode(image.image_data.as_bytes())
+            .map_err(|_| HandlerError::Invalid("Image is not valid base64".to_string()))?
+            .len();
+        if size == 0 {
+            return Err(HandlerError::Invalid("Image is empty".to_string()));
+        }
+        if size > self.image_bytes {
+            return Err(self.image_too_large());
+        }
+
+        image.size = u32::try_from(size).map_err(|_| self.image_too_large())?;
+        Ok(())
+    }
+
+    fn image_too_large(&self) -> HandlerError {
+        HandlerError::Invalid(format!("Images are limited to {} bytes", self.image_bytes))
+    }
+}
+
+/// Normalizes text that may span several lines and drops control
+/// characters other than line breaks and tabs.
+pub fn clean_text(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .nfc()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect()
+}
+
+/// Normalizes a single-line value such as a name, turning line breaks and
+/// tabs into spaces.
+pub fn clean_line(text: &str) -> String {
+    text.nfc()
+        .filter_map(|c| match c {
+            '\n' | '\r' | '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Cleans a name like [`clean_line`] and also drops invisible characters such
+/// as zero-width spaces and bidi overrides.
+pub fn clean_name(text: &str) -> String {
+    let visible: String = text.chars().filter(|c| !is_invisible(*c)).collect();
+    clean_line(&visible)
+}
+
+/// Whether `c` is a format character (general category Cf) or a default
+/// ignorable code point. Both render as nothing, or reorder the text around
+/// them, instead of showing up as a character.
+pub fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{0600}'..='\u{0605}'
+            | '\u{061C}'
+            | '\u{06DD}'
+            | '\u{070F}'
+            | '\u{0890}'..='\u{0891}'
+            | '\u{08E2}'
+            | '\u{115F}'..='\u{1160}'
+            | '\u{17B4}'..='\u{17B5}'
+            | '\u{180B}'..='\u{180F}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{206F}'
+            | '\u{3164}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+            | '\u{FFA0}'
+            | '\u{FFF0}'..='\u{FFFB}'
+            | '\u{110BD}'
+            | '\u{110CD}'
+            | '\u{13430}'..='\u{1343F}'
+            | '\u{1BCA0}'..='\u{1BCA3}'
+            | '\u{1D173}'..='\u{1D17A}'
+            | '\u{E0000}'..='\u{E0FFF}'
+    )
+}
+
+fn reaction_text(reaction: &mut String) -> Result<(), HandlerError> {
+    *reaction = clean_line(reaction);
+    if reaction.is_empty() {
+        return Err(HandlerError::Invalid(
+            "Reaction cannot be empty".to_string(),
+        ));
+    }
+    check_length("Reactions", reaction, MAX_REACTION_CHARS)
+}
+
+fn check_length(what: &str, text: &str, max_chars: usize) -> Result<(), HandlerError> {
+    if text.chars().count() > max_chars {
+        return Err(HandlerError::Invalid(format!(
+            "{what} are limited to {max_chars} characters"
+        )));
+    }
+    Ok(())
+}
+
+fn env_limit(name: &str, default: usize) -> usize {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or_else(|| {
+                warn!("invalid {}={:?}, using {}", name, value, default);
+                default
+            }),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,394 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::extract::{Data, SocketRef, State};
+use tracing::{error, info};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    audit::AuditAction,
+    socket::{ErrorResponse, audit, emit_error, session},
+    state::AppState,
+    webhooks::{DeadLetter, DeliveryAttempt, IncomingWebhook, IncomingWebhookInfo, WebhookInfo},
+};
+
+/// Webhooks expose every event of a room, so only moderators manage them.
+const NOT_MODERATOR: &str = "Only moderators can manage webhooks";
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CreateWebhookPayload {
+    pub room_id: Uuid,
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DeleteWebhookPayload {
+    pub room_id: Uuid,
+    pub webhook_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct ListWebhooksPayload {
+    pub room_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct WebhookListResponse {
+    pub room_id: Uuid,
+    pub webhooks: Vec<WebhookInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct GetWebhookDeliveriesPayload {
+    pub room_id: Uuid,
+    /// Limits the log to one webhook.
+    #[serde(default)]
+    pub webhook_id: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct WebhookDeliveriesResponse {
+    pub room_id: Uuid,
+    /// Newest first.
+    pub deliveries: Vec<DeliveryAttempt>,
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct RedeliverWebhookPayload {
+    pub room_id: Uuid,
+    /// A delivery from the dead-letter list.
+    pub delivery_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct CreateIncomingWebhookPayload {
+    pub room_id: Uuid,
+    /// Name the integration's messages are shown under.
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct IncomingWebhookListResponse {
+    pub room_id: Uuid,
+    pub webhooks: Vec<IncomingWebhookInfo>,
+}
+
+pub async fn create_webhook(
+    s: SocketRef,
+    Data(data): Data<CreateWebhookPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let webhook = match state.webhooks.register(data.room_id, &data.url) {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            emit_error(&s, &e.to_string());
+            return;
+        }
+    };
+
+    // The URL is left out, since it often carries a credential of its own.
+    state.audit.record(
+        audit::entry(&state, AuditAction::WebhookCreated, s.id)
+            .in_room(data.room_id)
+            .target(webhook.id),
+    );
+
+    info!(
+        "User {} added webhook {} to room {}",
+        s.id, webhook.id, data.room_id
+    );
+
+    // The secret is only ever shown here.
+    let response = WebhookInfo {
+        secret: Some(webhook.secret.clone()),
+        ..webhook.info()
+    };
+    if let Err(e) = s.emit("webhook.created", &response) {
+        error!("Failed to confirm webhook to {}: {}", s.id, e);
+    }
+}
+
+pub async fn delete_webhook(
+    s: SocketRef,
+    Data(data): Data<DeleteWebhookPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    if let Err(e) = state.webhooks.remove(data.room_id, data.webhook_id) {
+        emit_error(&s, &e.to_string());
+        return;
+    }
+    state.audit.record(
+        audit::entry(&state, AuditAction::WebhookDeleted, s.id)
+            .in_room(data.room_id)
+            .target(data.webhook_id),
+    );
+
+    info!(
+        "User {} removed webhook {} from room {}",
+        s.id, data.webhook_id, data.room_id
+    );
+    emit_list(&s, &state, data.room_id);
+}
+
+pub async fn list_webhooks(
+    s: SocketRef,
+    Data(data): Data<ListWebhooksPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    emit_list(&s, &state, data.room_id);
+}
+
+pub async fn get_deliveries(
+    s: SocketRef,
+    Data(data): Data<GetWebhookDeliveriesPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let response = WebhookDeliveriesResponse {
+        room_id: data.room_id,
+        deliveries: state.webhooks.deliveries(data.room_id, data.webhook_id),
+        dead_letters: state.webhooks.dead_letters(data.room_id),
+    };
+
+    if let Err(e) = s.emit("webhook.deliveries", &response) {
+        error!("Failed to send webhook deliveries to {}: {}", s.id, e);
+    }
+}
+
+pub async fn redeliver(
+    s: SocketRef,
+    Data(data): Data<RedeliverWebhookPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    if state
+        .webhooks
+        .redeliver(data.room_id, data.delivery_id)
+        .is_none()
+    {
+        emit_error(&s, "Delivery is not in the dead-letter list");
+        return;
+    }
+
+    info!(
+        "User {} requeued webhook delivery {} in room {}",
+        s.id, data.delivery_id, data.room_id
+    );
+}
+
+pub async fn create_incoming_webhook(
+    s: SocketRef,
+    Data(data): Data<CreateIncomingWebhookPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let webhook = match add_incoming_webhook(&state, data.room_id, &data.name) {
+        Ok(webhook) => webhook,
+        Err(message) => {
+            emit_error(&s, &message);
+            return;
+        }
+    };
+    state.audit.record(
+        audit::entry(&state, AuditAction::IncomingWebhookCreated, s.id)
+            .in_room(data.room_id)
+            .target(webhook.id)
+            .change(None, Some(webhook.name.clone())),
+    );
+
+    info!(
+        "User {} added incoming webhook {} ({}) to room {}",
+        s.id, webhook.id, webhook.name, data.room_id
+    );
+
+    // The token is only ever shown here.
+    let response = IncomingWebhookInfo {
+        token: Some(webhook.token.clone()),
+        ..webhook.info()
+    };
+    if let Err(e) = s.emit("incoming_webhook.created", &response) {
+        error!("Failed to confirm incoming webhook to {}: {}", s.id, e);
+    }
+}
+
+pub async fn delete_incoming_webhook(
+    s: SocketRef,
+    Data(data): Data<DeleteWebhookPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    let name = match remove_incoming_webhook(&state, data.room_id, data.webhook_id) {
+        Ok(name) => name,
+        Err(message) => {
+            emit_error(&s, &message);
+            return;
+        }
+    };
+    state.audit.record(
+        audit::entry(&state, AuditAction::IncomingWebhookDeleted, s.id)
+            .in_room(data.room_id)
+            .target(data.webhook_id)
+            .change(Some(name), None),
+    );
+
+    info!(
+        "User {} removed incoming webhook {} from room {}",
+        s.id, data.webhook_id, data.room_id
+    );
+    emit_incoming_list(&s, &state, data.room_id);
+}
+
+pub async fn list_incoming_webhooks(
+    s: SocketRef,
+    Data(data): Data<ListWebhooksPayload>,
+    State(state): State<AppState>,
+) {
+    if let Err(e) = session::ensure_moderator(&state, data.room_id, s.id, NOT_MODERATOR) {
+        let _ = s.emit("error", &ErrorResponse::from(e));
+        return;
+    }
+
+    emit_incoming_list(&s, &state, data.room_id);
+}
+
+/// Creates an incoming webhook whose name is claimed like a user's, so it
+/// cannot pose as a reserved name or someone else.
+fn add_incoming_webhook(
+    state: &AppState,
+    room_id: Uuid,
+    name: &str,
+) -> Result<IncomingWebhook, String> {
+    let webhook = state
+        .incoming_webhooks
+        .create(room_id, name)
+        .map_err(|e| e.to_string())?;
+
+    if let Err(e) = state.username_registry.claim(webhook.id, &webhook.name) {
+        let _ = state.incoming_webhooks.remove(room_id, webhook.id);
+        return Err(e.to_string());
+    }
+
+    // Messages are attributed to the integration like to any other user.
+    state.usernames.insert(webhook.sid, webhook.name.clone());
+
+    Ok(webhook)
+}
+
+/// Deletes an incoming webhook and frees its name, which is returned. The
+/// integration keeps its name in `usernames` so its earlier messages stay
+/// attributed.
+fn remove_incoming_webhook(
+    state: &AppState,
+    room_id: Uuid,
+    webhook_id: Uuid,
+) -> Result<String, String> {
+    let webhook = state
+        .incoming_webhooks
+        .remove(room_id, webhook_id)
+        .map_err(|e| e.to_string())?;
+    state.username_registry.release(webhook_id);
+    Ok(webhook.name)
+}
+
+fn emit_list(s: &SocketRef, state: &AppState, room_id: Uuid) {
+    let response = WebhookListResponse {
+        room_id,
+        webhooks: state
+            .webhooks
+            .for_room(room_id)
+            .iter()
+            .map(|webhook| webhook.info())
+            .collect(),
+    };
+
+    if let Err(e) = s.emit("webhook.list", &response) {
+        error!("Failed to send webhook list to {}: {}", s.id, e);
+    }
+}
+
+fn emit_incoming_list(s: &SocketRef, state: &AppState, room_id: Uuid) {
+    let response = IncomingWebhookListResponse {
+        room_id,
+        webhooks: state
+            .incoming_webhooks
+            .for_room(room_id)
+            .iter()
+            .map(|webhook| webhook.info())
+            .collect(),
+    };
+
+    if let Err(e) = s.emit("incoming_webhook.list", &response) {
+        error!("Failed to send incoming webhook list to {}: {}", s.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_reserved_names() {
+        let state = AppState::from_env();
+        let room_id = Uuid::new_v4();
+
+        assert!(add_incoming_webhook(&state, room_id, "Admin").is_err());
+        assert!(state.incoming_webhooks.for_room(room_id).is_empty());
+    }
+
+    #[test]
+    fn frees_the_name_when_deleted() {
+        let state = AppState::from_env();
+        let room_id = Uuid::new_v4();
+
+        let webhook = add_incoming_webhook(&state, room_id, "Deploys").unwrap();
+        assert_eq!(
+            state.username_registry.owner_of("deploys"),
+            Some(webhook.id)
+        );
+        assert!(add_incoming_webhook(&state, room_id, "Deploys").is_err());
+
+        remove_incoming_webhook(&state, room_id, webhook.id).unwrap();
+        assert!(add_incoming_webhook(&state, room_id, "Deploys").is_ok());
+    }
+}
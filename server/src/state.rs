@@ -1,13 +1,89 @@
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use socketioxide::socket::Sid;
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use uuid::Uuid;
 
-use crate::models::Room;
+use crate::{
+    audit::AuditLog,
+    bots::BotRegistry,
+    link_preview::LinkPreviewService,
+    media::MediaStore,
+    models::{Room, UserProfile},
+    moderation::{ModerationService, ReportQueue},
+    rate_limit::RateLimiter,
+    rest::RestSession,
+    socket::{
+        protocol::ClientProtocol, scheduled_messages::ScheduledMessage,
+        starred_messages::StarredItem, validation::Limits,
+    },
+    usernames::UsernameRegistry,
+    webhooks::{IncomingWebhookStore, WebhookService},
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub rooms: Arc<DashMap<Uuid, Room>>,
     pub usernames: Arc<DashMap<Sid, String>>,
-    pub starred_messages: Arc<DashMap<(Uuid, Sid), HashSet<Uuid>>>,
+    pub user_keys: Arc<DashMap<Sid, Uuid>>,
+    /// The protocol negotiated with each connected socket.
+    pub client_protocols: Arc<DashMap<Sid, ClientProtocol>>,
+    pub starred_messages: Arc<DashMap<Uuid, Vec<StarredItem>>>,
+    pub mention_counts: Arc<DashMap<Uuid, HashMap<Uuid, u32>>>,
+    pub scheduled_messages: Arc<DashMap<Uuid, ScheduledMessage>>,
+    pub link_previews: Arc<LinkPreviewService>,
+    pub media: Arc<MediaStore>,
+    /// Direct conversation room ids keyed by their sorted participant keys.
+    pub direct_rooms: Arc<DashMap<Vec<Uuid>, Uuid>>,
+    /// Every user key handed out in `session.info`. The REST API accepts no
+    /// others.
+    pub issued_keys: Arc<DashSet<Uuid>>,
+    /// The REST API users active recently, keyed by user key.
+    pub rest_sessions: Arc<DashMap<Uuid, RestSession>>,
+    pub webhooks: Arc<WebhookService>,
+    pub incoming_webhooks: Arc<IncomingWebhookStore>,
+    pub bots: Arc<BotRegistry>,
+    pub rate_limits: Arc<RateLimiter>,
+    pub limits: Arc<Limits>,
+    pub username_registry: Arc<UsernameRegistry>,
+    /// Profiles keyed by user key.
+    pub profiles: Arc<DashMap<Uuid, UserProfile>>,
+    pub moderation: Arc<ModerationService>,
+    pub reports: Arc<ReportQueue>,
+    pub audit: Arc<AuditLog>,
+    /// The users each user has blocked, all by user key.
+    pub blocks: Arc<DashMap<Uuid, HashSet<Uuid>>>,
+}
+
+impl AppState {
+    /// Builds an empty state, configured from the environment.
+    pub fn from_env() -> Self {
+        Self {
+            rooms: Arc::new(Default::default()),
+            usernames: Arc::new(Default::default()),
+            user_keys: Arc::new(Default::default()),
+            client_protocols: Arc::new(Default::default()),
+            starred_messages: Arc::new(Default::default()),
+            mention_counts: Arc::new(Default::default()),
+            scheduled_messages: Arc::new(Default::default()),
+            link_previews: Arc::new(LinkPreviewService::from_env()),
+            media: Arc::new(Default::default()),
+            direct_rooms: Arc::new(Default::default()),
+            issued_keys: Arc::new(Default::default()),
+            rest_sessions: Arc::new(Default::default()),
+            webhooks: Arc::new(WebhookService::from_env()),
+            incoming_webhooks: Arc::new(Default::default()),
+            bots: Arc::new(Default::default()),
+            rate_limits: Arc::new(RateLimiter::from_env()),
+            limits: Arc::new(Limits::from_env()),
+            username_registry: Arc::new(UsernameRegistry::from_env()),
+            profiles: Arc::new(Default::default()),
+            moderation: Arc::new(Default::default()),
+            reports: Arc::new(Default::default()),
+            audit: Arc::new(AuditLog::from_env()),
+            blocks: Arc::new(Default::default()),
+        }
+    }
 }
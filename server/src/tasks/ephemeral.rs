@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use socketioxide::{SocketIo, socket::Sid};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    models::{MessageDeleteEvent, RoomEvent, RoomEventData},
+    socket::protocol,
+    state::AppState,
+};
+
+const TICK: Duration = Duration::from_secs(1);
+
+struct Expired {
+    room_id: Uuid,
+    message_id: Uuid,
+    from: Sid,
+}
+
+pub async fn run(io: SocketIo, state: AppState) {
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+
+        for expired in expire_messages(&state) {
+            let delete_event = RoomEvent {
+                id: Uuid::new_v4(),
+                from: expired.from,
+                timestamp: Utc::now(),
+                data: RoomEventData::MessageDelete(MessageDeleteEvent {
+                    message_id: expired.message_id,
+                }),
+                sender_key: None,
+            };
+
+            protocol::broadcast_room_event(&io, &state, expired.room_id, &delete_event).await;
+        }
+    }
+}
+
+/// Redacts every message whose TTL has elapsed and returns what was removed.
+fn expire_messages(state: &AppState) -> Vec<Expired> {
+    let now = Utc::now();
+    let mut expired = Vec::new();
+
+    for mut entry in state.rooms.iter_mut() {
+        let room = entry.value_mut();
+        let first_expired = expired.len();
+
+        for event in &mut room.events {
+            let Some(expires_at) = event.data.expires_at() else {
+                continue;
+            };
+
+            if expires_at <= now && event.data.redact() {
+                state.media.release(&event.data);
+                expired.push(Expired {
+                    room_id: room.id,
+                    message_id: event.id,
+                    from: event.from,
+                });
+            }
+        }
+
+        for expired in &expired[first_expired..] {
+            room.forget_redacted(expired.message_id);
+        }
+    }
+
+    if !expired.is_empty() {
+        info!("Expired {} disappearing messages", expired.len());
+    }
+
+    expired
+}
@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::state::AppState;
+
+const TICK: Duration = Duration::from_secs(60);
+
+pub async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+        state.media.prune_unclaimed(Utc::now());
+    }
+}
@@ -0,0 +1,23 @@
+mod ephemeral;
+mod media;
+mod polls;
+mod rate_limits;
+mod rest_sessions;
+mod retention;
+mod scheduler;
+
+use socketioxide::SocketIo;
+
+use crate::{socket::scheduled_messages, state::AppState};
+
+pub fn spawn_all(io: SocketIo, state: AppState) {
+    scheduled_messages::load(&state);
+
+    tokio::spawn(retention::run(state.clone()));
+    tokio::spawn(media::run(state.clone()));
+    tokio::spawn(ephemeral::run(io.clone(), state.clone()));
+    tokio::spawn(polls::run(io.clone(), state.clone()));
+    tokio::spawn(rate_limits::run(io.clone(), state.clone()));
+    tokio::spawn(rest_sessions::run(io.clone(), state.clone()));
+    tokio::spawn(scheduler::run(io, state));
+}
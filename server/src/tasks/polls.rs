@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+use socketioxide::SocketIo;
+
+use crate::{socket::polls, state::AppState};
+
+const TICK: Duration = Duration::from_secs(1);
+
+pub async fn run(io: SocketIo, state: AppState) {
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+        polls::close_expired(&io, &state).await;
+    }
+}
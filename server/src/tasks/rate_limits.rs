@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use socketioxide::SocketIo;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{socket::rate_limits, state::AppState};
+
+const TICK: Duration = Duration::from_secs(10);
+
+pub async fn run(io: SocketIo, state: AppState) {
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+        state.rate_limits.prune();
+
+        for room_id in expire_slow_modes(&state) {
+            info!("Slow mode in room {} ended", room_id);
+            rate_limits::broadcast(&io, room_id, None).await;
+        }
+    }
+}
+
+fn expire_slow_modes(state: &AppState) -> Vec<Uuid> {
+    let now = Utc::now();
+    let mut expired = Vec::new();
+
+    for mut room in state.rooms.iter_mut() {
+        if room
+            .slow_mode
+            .as_ref()
+            .is_some_and(|slow_mode| !slow_mode.is_active(now))
+        {
+            room.slow_mode = None;
+            expired.push(room.id);
+        }
+    }
+
+    expired
+}
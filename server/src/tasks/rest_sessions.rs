@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+use socketioxide::SocketIo;
+
+use crate::{rest, state::AppState};
+
+const TICK: Duration = Duration::from_secs(60);
+
+pub async fn run(io: SocketIo, state: AppState) {
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+        rest::expire_sessions(&io, &state).await;
+    }
+}
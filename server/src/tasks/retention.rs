@@ -0,0 +1,83 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::state::AppState;
+
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+pub async fn run(state: AppState) {
+    let interval_secs = match std::env::var("RETENTION_INTERVAL_SECS") {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .unwrap_or_else(|| {
+                warn!(
+                    "invalid RETENTION_INTERVAL_SECS={:?}, using {}",
+                    value, DEFAULT_INTERVAL_SECS
+                );
+                DEFAULT_INTERVAL_SECS
+            }),
+        Err(_) => DEFAULT_INTERVAL_SECS,
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        prune(&state);
+    }
+}
+
+fn prune(state: &AppState) {
+    let now = Utc::now();
+
+    for mut entry in state.rooms.iter_mut() {
+        let room = entry.value_mut();
+        let mut pruned: HashSet<Uuid> = HashSet::new();
+
+        if let Some(cutoff) = room.policy.retention_cutoff(now) {
+            room.events.retain(|event| {
+                if event.timestamp < cutoff {
+                    state.media.release(&event.data);
+                    pruned.insert(event.id);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        if let Some(max_events) = room.policy.max_events {
+            let max_events = max_events as usize;
+            if room.events.len() > max_events {
+                let excess = room.events.len() - max_events;
+                for event in room.events.drain(..excess) {
+                    state.media.release(&event.data);
+                    pruned.insert(event.id);
+                }
+            }
+        }
+
+        if pruned.is_empty() {
+            continue;
+        }
+
+        room.pinned.retain(|id| !pruned.contains(id));
+
+        let room_id = room.id;
+        for mut starred in state.starred_messages.iter_mut() {
+            starred.retain(|item| item.room_id != room_id || !pruned.contains(&item.message_id));
+        }
+
+        info!(
+            "Pruned {} events from room {} by retention policy",
+            pruned.len(),
+            room_id
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use socketioxide::{SocketIo, socket::Sid};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    socket::{
+        scheduled_messages::{self, ScheduledMessage},
+        send_event,
+    },
+    state::AppState,
+};
+
+const TICK: Duration = Duration::from_secs(1);
+
+/// Why a due message was not sent.
+enum DeliveryError {
+    /// The author is over their rate limit; the message is tried again on the
+    /// next tick.
+    RateLimited,
+    /// The message can no longer be sent as it is.
+    Rejected(String),
+}
+
+pub async fn run(io: SocketIo, state: AppState) {
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+        let due: Vec<Uuid> = state
+            .scheduled_messages
+            .iter()
+            .filter(|entry| entry.send_at <= now && entry.failure.is_none())
+            .map(|entry| *entry.key())
+            .collect();
+
+        if due.is_empty() {
+            continue;
+        }
+
+        for id in due {
+            // Taken out first so an edit or cancel cannot race the delivery.
+            let Some((_, mut scheduled)) = state.scheduled_messages.remove(&id) else {
+                continue;
+            };
+
+            info!(
+                "Delivering scheduled message {} to room {}",
+                scheduled.id, scheduled.message.room
+            );
+
+            match deliver(&io, &state, &scheduled).await {
+                Ok(()) => {}
+                Err(DeliveryError::RateLimited) => {
+                    state.scheduled_messages.insert(id, scheduled);
+                }
+                Err(DeliveryError::Rejected(reason)) => {
+                    warn!("Scheduled message {} was rejected: {}", id, reason);
+                    let author_key = scheduled.author_key;
+                    scheduled.failure = Some(reason);
+                    state.scheduled_messages.insert(id, scheduled);
+                    scheduled_messages::notify_author(&io, &state, author_key);
+                }
+            }
+        }
+
+        scheduled_messages::persist(&state).await;
+    }
+}
+
+/// Sends a due message with the same checks as if the author sent it now.
+async fn deliver(
+    io: &SocketIo,
+    state: &AppState,
+    scheduled: &ScheduledMessage,
+) -> Result<(), DeliveryError> {
+    let room_id = scheduled.message.room;
+    let online = scheduled_messages::sender(state, scheduled)
+        .map_err(|e| DeliveryError::Rejected(e.to_string()))?;
+
+    send_event::check_rate_limit(state, room_id, scheduled.author_key, None)
+        .map_err(|_| DeliveryError::RateLimited)?;
+
+    // An author who is not in the room right now gets a connection id for the
+    // delivery, as REST users do.
+    let from = online.unwrap_or_else(|| {
+        let sid = Sid::new();
+        state.user_keys.insert(sid, scheduled.author_key);
+        sid
+    });
+
+    let result = send_event::deliver(io, state, from, scheduled.message.clone()).await;
+
+    if online.is_none() {
+        state.user_keys.remove(&from);
+    }
+
+    result.map(|_| ()).map_err(DeliveryError::Rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{Room, RoomEventData, TextMessageEvent},
+        socket::{send_event::SendEventPayload, test_io},
+    };
+
+    fn scheduled(room: Uuid, author_key: Uuid) -> ScheduledMessage {
+        ScheduledMessage {
+            id: Uuid::new_v4(),
+            author_key,
+            send_at: Utc::now(),
+            created_at: Utc::now(),
+            message: SendEventPayload {
+                room,
+                payload: RoomEventData::Message(TextMessageEvent {
+                    content: "good morning".to_string(),
+                    edited: false,
+                    deleted: false,
+                    reply_to: None,
+                    expires_at: None,
+                    mentions: Vec::new(),
+                    mentions_room: false,
+                    formatted: None,
+                    link_previews: Vec::new(),
+                    emote: false,
+                }),
+                ttl_secs: None,
+            },
+            failure: None,
+        }
+    }
+
+    fn room(state: &AppState, member_key: Option<Uuid>) -> Uuid {
+        let mut room = Room::new(Uuid::new_v4(), "room".to_string());
+        room.member_keys.extend(member_key);
+        let id = room.id;
+        state.rooms.insert(id, room);
+        id
+    }
+
+    #[tokio::test]
+    async fn sends_on_behalf_of_offline_members() {
+        let state = AppState::from_env();
+        let author_key = Uuid::new_v4();
+        let room_id = room(&state, Some(author_key));
+
+        assert!(
+            deliver(&test_io(), &state, &scheduled(room_id, author_key))
+                .await
+                .is_ok()
+        );
+
+        let room = state.rooms.get(&room_id).unwrap();
+        let [event] = &room.events[..] else {
+            panic!("expected one event, got {:?}", room.events);
+        };
+        assert!(matches!(
+            &event.data,
+            RoomEventData::Message(message) if message.content == "good morning"
+        ));
+        assert!(state.user_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_authors_who_left_the_room() {
+        let state = AppState::from_env();
+        let room_id = room(&state, None);
+
+        assert!(matches!(
+            deliver(&test_io(), &state, &scheduled(room_id, Uuid::new_v4())).await,
+            Err(DeliveryError::Rejected(_))
+        ));
+        assert!(state.rooms.get(&room_id).unwrap().events.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! Ownership of usernames.
+//!
+//! A name belongs to the user key that claimed it and stays theirs across
+//! reconnects until they pick another one. Names are compared after folding
+//! away case, compatibility forms such as fullwidth letters, and invisible
+//! characters, so look-alikes count as the same name. Reserved names can never
+//! be claimed; extra ones can be listed in `RESERVED_USERNAMES`, separated by
+//! commas.
+
+use std::collections::HashSet;
+
+use dashmap::{DashMap, mapref::entry::Entry};
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+
+use crate::socket::validation;
+
+const DEFAULT_RESERVED: [&str; 13] = [
+    "admin",
+    "administrator",
+    "bot",
+    "everyone",
+    "here",
+    "mod",
+    "moderator",
+    "root",
+    "room",
+    "server",
+    "staff",
+    "support",
+    "system",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UsernameError {
+    Reserved,
+    Taken,
+}
+
+impl std::fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsernameError::Reserved => write!(f, "That username is reserved"),
+            UsernameError::Taken => write!(f, "That username is already taken"),
+        }
+    }
+}
+
+pub struct UsernameRegistry {
+    reserved: HashSet<String>,
+    /// The user key holding each name, keyed by the folded name.
+    owners: DashMap<String, Uuid>,
+    /// The name each user key holds, as they last typed it.
+    names: DashMap<Uuid, String>,
+}
+
+impl UsernameRegistry {
+    pub fn new(reserved: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            reserved: reserved.into_iter().map(|name| fold(&name)).collect(),
+            owners: DashMap::new(),
+            names: DashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let extra = std::env::var("RESERVED_USERNAMES").unwrap_or_default();
+
+        Self::new(
+            DEFAULT_RESERVED.into_iter().map(str::to_string).chain(
+                extra
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+            ),
+        )
+    }
+
+    /// Checks whether `user` may take `name`, and returns whether it differs
+    /// from the name they hold now.
+    pub fn check(&self, user: Uuid, name: &str) -> Result<bool, UsernameError> {
+        let folded = fold(name);
+        if self.reserved.contains(&folded) {
+            return Err(UsernameError::Reserved);
+        }
+
+        match self.owners.get(&folded) {
+            Some(owner) if *owner != user => Err(UsernameError::Taken),
+            Some(_) => Ok(false),
+            None => Ok(true),
+        }
+    }
+
+    /// Gives `name` to `user`, releasing the name they held before.
+    pub fn claim(&self, user: Uuid, name: &str) -> Result<(), UsernameError> {
+        let folded = fold(name);
+        if self.reserved.contains(&folded) {
+            return Err(UsernameError::Reserved);
+        }
+
+        match self.owners.entry(folded.clone()) {
+            Entry::Occupied(owner) if *owner.get() != user => return Err(UsernameError::Taken),
+            Entry::Occupied(_) => {
+                self.names.insert(user, name.to_string());
+                return Ok(());
+            }
+            Entry::Vacant(owner) => {
+                owner.insert(user);
+            }
+        }
+
+        if let Some(previous) = self.names.insert(user, name.to_string()) {
+            self.owners
+                .remove_if(&fold(&previous), |_, owner| *owner == user);
+        }
+
+        Ok(())
+    }
+
+    /// Frees the name `user` holds, e.g. when a bot is deleted.
+    pub fn release(&self, user: Uuid) {
+        if let Some((_, name)) = self.names.remove(&user) {
+            self.owners
+                .remove_if(&fold(&name), |_, owner| *owner == user);
+        }
+    }
+
+    /// The name `user` holds, even while they are offline.
+    pub fn name_of(&self, user: Uuid) -> Option<String> {
+        self.names.get(&user).map(|name| name.clone())
+    }
+
+    /// The user key holding `name` or a look-alike of it.
+    pub fn owner_of(&self, name: &str) -> Option<Uuid> {
+        self.owners.get(&fold(name)).map(|owner| *owner)
+    }
+}
+
+/// NFKC with case folding, ignoring invisible characters and whitespace
+/// differences. Upper-casing first folds characters like `ß` to `ss`.
+fn fold(name: &str) -> String {
+    let visible: String = name
+        .nfkc()
+        .filter(|c| !validation::is_invisible(*c))
+        .collect();
+
+    visible
+        .to_uppercase()
+        .to_lowercase()
+        .nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> UsernameRegistry {
+        UsernameRegistry::new(DEFAULT_RESERVED.into_iter().map(str::to_string))
+    }
+
+    #[test]
+    fn reserves_look_alikes_of_reserved_names() {
+        let registry = registry();
+        let user = Uuid::new_v4();
+
+        for name in [
+            "admin",
+            "ADMIN",
+            "admin\u{200B}",
+            "ad\u{00AD}min",
+            "\u{2060}admin\u{FEFF}",
+            "\u{202E}admin",
+            "ａｄｍｉｎ",
+            "𝐚𝐝𝐦𝐢𝐧",
+            "ＡＤＭＩＮ\u{200D}",
+        ] {
+            assert_eq!(
+                registry.claim(user, name),
+                Err(UsernameError::Reserved),
+                "{name:?}"
+            );
+        }
+        assert_eq!(registry.claim(user, "admins"), Ok(()));
+    }
+
+    #[test]
+    fn treats_look_alikes_as_the_same_name() {
+        let registry = registry();
+        let alice = Uuid::new_v4();
+        let mallory = Uuid::new_v4();
+        registry.claim(alice, "Alice").unwrap();
+        registry.claim(Uuid::new_v4(), "Straße").unwrap();
+
+        for name in [
+            "alice",
+            "ＡＬＩＣＥ",
+            "Ali\u{200B}ce",
+            "\u{202E}Alice\u{202C}",
+            "STRASSE",
+        ] {
+            assert_eq!(registry.check(mallory, name), Err(UsernameError::Taken));
+            assert_eq!(
+                registry.claim(mallory, name),
+                Err(UsernameError::Taken),
+                "{name:?}"
+            );
+        }
+
+        assert_eq!(registry.check(alice, "ａｌｉｃｅ"), Ok(false));
+        assert_eq!(registry.owner_of("ALI\u{200B}CE"), Some(alice));
+        assert_eq!(registry.name_of(alice).as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn releases_names() {
+        let registry = registry();
+        let bot = Uuid::new_v4();
+        let user = Uuid::new_v4();
+        registry.claim(bot, "Helper").unwrap();
+
+        assert_eq!(registry.claim(user, "helper"), Err(UsernameError::Taken));
+        registry.release(bot);
+        assert_eq!(registry.claim(user, "helper"), Ok(()));
+    }
+
+    #[test]
+    fn renaming_frees_the_old_name() {
+        let registry = registry();
+        let user = Uuid::new_v4();
+        registry.claim(user, "first").unwrap();
+        registry.claim(user, "second").unwrap();
+
+        assert_eq!(registry.claim(Uuid::new_v4(), "FIRST"), Ok(()));
+    }
+
+    #[test]
+    fn displayed_names_lose_invisible_characters() {
+        assert_eq!(validation::clean_name("\u{202E}nimda"), "nimda");
+        assert_eq!(validation::clean_name(" Al\u{200B}ice\u{FEFF}\n"), "Alice");
+        assert_eq!(validation::clean_name("ｆｕｌｌ"), "ｆｕｌｌ");
+    }
+}
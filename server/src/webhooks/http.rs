@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{header, redirect};
+
+use super::{DELIVERY_HEADER, SIGNATURE_HEADER, SignedRequest, TIMESTAMP_HEADER, WebhookSender};
+use crate::link_preview::ssrf;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const USER_AGENT: &str = concat!("simple-chat-webhooks/", env!("CARGO_PKG_VERSION"));
+
+/// Posts webhook deliveries over HTTP, refusing to connect to private
+/// addresses unless told otherwise. Redirects are not followed, and proxies
+/// from the environment are ignored so the checked address is the one used.
+pub struct HttpSender {
+    allow_private: bool,
+}
+
+impl HttpSender {
+    pub fn new(allow_private: bool) -> Self {
+        Self { allow_private }
+    }
+}
+
+#[async_trait]
+impl WebhookSender for HttpSender {
+    async fn send(&self, request: &SignedRequest) -> Result<u16, String> {
+        let addr = ssrf::resolve_public(&request.url, self.allow_private)
+            .await
+            .map_err(|e| e.to_string())?;
+        let host = request.url.host_str().unwrap_or_default().to_string();
+
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .no_proxy()
+            .timeout(TIMEOUT)
+            .user_agent(USER_AGENT)
+            .resolve(&host, addr)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let response = client
+            .post(request.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &request.signature)
+            .header(TIMESTAMP_HEADER, request.timestamp.to_string())
+            .header(DELIVERY_HEADER, request.delivery_id.to_string())
+            .body(request.body.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(response.status().as_u16())
+    }
+}
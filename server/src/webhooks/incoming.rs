@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socketioxide::socket::Sid;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::{MAX_WEBHOOKS_PER_ROOM, WebhookError, random_token};
+use crate::socket::validation;
+
+const MAX_NAME_CHARS: usize = 32;
+
+/// A token that lets an integration post into one room.
+#[derive(Debug, Clone)]
+pub struct IncomingWebhook {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    /// Shown as the author of the integration's messages.
+    pub name: String,
+    pub token: String,
+    /// The id the integration posts as.
+    pub sid: Sid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An incoming webhook as shown to room moderators. The token is only
+/// returned when the webhook is created.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct IncomingWebhookInfo {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub name: String,
+    #[ts(type = "String")]
+    #[schemars(with = "String")]
+    pub user_id: Sid,
+    pub created_at: DateTime<Utc>,
+    /// Post to `/api/hooks/{token}` to send a message.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl IncomingWebhook {
+    pub fn info(&self) -> IncomingWebhookInfo {
+        IncomingWebhookInfo {
+            id: self.id,
+            room_id: self.room_id,
+            name: self.name.clone(),
+            user_id: self.sid,
+            created_at: self.created_at,
+            token: None,
+        }
+    }
+}
+
+/// Incoming webhooks keyed by token.
+#[derive(Default)]
+pub struct IncomingWebhookStore {
+    webhooks: DashMap<String, IncomingWebhook>,
+}
+
+impl IncomingWebhookStore {
+    pub fn create(&self, room_id: Uuid, name: &str) -> Result<IncomingWebhook, WebhookError> {
+        let name = validation::clean_line(name);
+        if name.is_empty() {
+            return Err(WebhookError::InvalidName("name is empty"));
+        }
+        if name.chars().count() > MAX_NAME_CHARS {
+            return Err(WebhookError::InvalidName("name is too long"));
+        }
+
+        if self.for_room(room_id).len() >= MAX_WEBHOOKS_PER_ROOM {
+            return Err(WebhookError::TooMany);
+        }
+
+        let webhook = IncomingWebhook {
+            id: Uuid::new_v4(),
+            room_id,
+            name,
+            token: random_token("whin_"),
+            sid: Sid::new(),
+            created_at: Utc::now(),
+        };
+        self.webhooks.insert(webhook.token.clone(), webhook.clone());
+
+        Ok(webhook)
+    }
+
+    pub fn remove(&self, room_id: Uuid, webhook_id: Uuid) -> Result<IncomingWebhook, WebhookError> {
+        let token = self
+            .webhooks
+            .iter()
+            .find(|webhook| webhook.id == webhook_id && webhook.room_id == room_id)
+            .map(|webhook| webhook.token.clone())
+            .ok_or(WebhookError::NotFound)?;
+
+        self.webhooks
+            .remove(&token)
+            .map(|(_, webhook)| webhook)
+            .ok_or(WebhookError::NotFound)
+    }
+
+    pub fn for_room(&self, room_id: Uuid) -> Vec<IncomingWebhook> {
+        let mut webhooks: Vec<IncomingWebhook> = self
+            .webhooks
+            .iter()
+            .filter(|webhook| webhook.room_id == room_id)
+            .map(|webhook| webhook.clone())
+            .collect();
+        webhooks.sort_by_key(|webhook| webhook.created_at);
+        webhooks
+    }
+
+    pub fn by_token(&self, token: &str) -> Option<IncomingWebhook> {
+        self.webhooks.get(token).map(|webhook| webhook.clone())
+    }
+}
@@ -0,0 +1,668 @@
+//! Outgoing webhooks that receive every new event of a room, and incoming
+//! webhooks that let integrations post into one.
+//!
+//! Each outgoing delivery is a JSON POST signed with the webhook's secret. Failed
+//! deliveries are retried with exponential backoff and end up in the room's
+//! dead-letter list once [`MAX_ATTEMPTS`] is reached. Requests go through
+//! [`WebhookSender`] so the network side can be swapped out.
+
+mod http;
+mod incoming;
+
+use std::{collections::VecDeque, fmt::Write, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{info, warn};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::models::RoomEvent;
+
+pub use http::HttpSender;
+pub use incoming::{IncomingWebhook, IncomingWebhookInfo, IncomingWebhookStore};
+
+pub const MAX_WEBHOOKS_PER_ROOM: usize = 10;
+pub const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Delivery log entries kept per webhook.
+const MAX_LOG_ENTRIES: usize = 100;
+/// Dead letters kept per room.
+const MAX_DEAD_LETTERS: usize = 100;
+
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub url: Url,
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A webhook as shown to room moderators. The secret is only returned when
+/// the webhook is created.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct WebhookInfo {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    pub fn info(&self) -> WebhookInfo {
+        WebhookInfo {
+            id: self.id,
+            room_id: self.room_id,
+            url: self.url.to_string(),
+            created_at: self.created_at,
+            secret: None,
+        }
+    }
+}
+
+/// The JSON body posted to a webhook.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct WebhookPayload {
+    pub webhook_id: Uuid,
+    pub room_id: Uuid,
+    pub event: RoomEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, JsonSchema)]
+#[ts(export)]
+pub enum DeliveryOutcome {
+    Delivered,
+    /// Failed, another attempt is scheduled.
+    Retrying,
+    /// Failed for the last time and moved to the dead-letter list.
+    DeadLettered,
+}
+
+/// One attempt at delivering an event to a webhook.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DeliveryAttempt {
+    pub delivery_id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_id: Uuid,
+    pub attempt: u32,
+    pub outcome: DeliveryOutcome,
+    #[serde(default)]
+    pub status_code: Option<u16>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A delivery that ran out of attempts.
+#[derive(Serialize, Deserialize, Debug, Clone, TS, JsonSchema)]
+#[ts(export)]
+pub struct DeadLetter {
+    pub delivery_id: Uuid,
+    pub webhook_id: Uuid,
+    pub payload: WebhookPayload,
+    pub attempts: u32,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    InvalidUrl(String),
+    InvalidName(&'static str),
+    TooMany,
+    NotFound,
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookError::InvalidUrl(reason) => write!(f, "Invalid webhook URL: {reason}"),
+            WebhookError::InvalidName(reason) => write!(f, "Invalid integration name: {reason}"),
+            WebhookError::TooMany => write!(
+                f,
+                "A room can have at most {MAX_WEBHOOKS_PER_ROOM} webhooks"
+            ),
+            WebhookError::NotFound => write!(f, "Webhook does not exist"),
+        }
+    }
+}
+
+/// A signed request ready to be sent.
+#[derive(Debug, Clone)]
+pub struct SignedRequest {
+    pub url: Url,
+    pub delivery_id: Uuid,
+    pub timestamp: i64,
+    pub signature: String,
+    pub body: Vec<u8>,
+}
+
+#[async_trait]
+pub trait WebhookSender: Send + Sync {
+    /// Sends the request and returns the response status. Anything outside
+    /// 2xx counts as a failure.
+    async fn send(&self, request: &SignedRequest) -> Result<u16, String>;
+}
+
+pub struct WebhookService {
+    sender: Arc<dyn WebhookSender>,
+    webhooks: DashMap<Uuid, Webhook>,
+    logs: DashMap<Uuid, VecDeque<DeliveryAttempt>>,
+    dead_letters: DashMap<Uuid, VecDeque<DeadLetter>>,
+    initial_backoff: Duration,
+}
+
+impl WebhookService {
+    pub fn new(sender: Arc<dyn WebhookSender>) -> Self {
+        Self {
+            sender,
+            webhooks: DashMap::new(),
+            logs: DashMap::new(),
+            dead_letters: DashMap::new(),
+            initial_backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Set `WEBHOOKS_ALLOW_PRIVATE=true` to deliver to private addresses, for
+    /// example a receiver on localhost during development.
+    pub fn from_env() -> Self {
+        let allow_private = std::env::var("WEBHOOKS_ALLOW_PRIVATE")
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false);
+
+        Self::new(Arc::new(HttpSender::new(allow_private)))
+    }
+
+    pub fn register(&self, room_id: Uuid, url: &str) -> Result<Webhook, WebhookError> {
+        let url = Url::parse(url).map_err(|e| WebhookError::InvalidUrl(e.to_string()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(WebhookError::InvalidUrl(format!(
+                "unsupported scheme {}",
+                url.scheme()
+            )));
+        }
+
+        if self.for_room(room_id).len() >= MAX_WEBHOOKS_PER_ROOM {
+            return Err(WebhookError::TooMany);
+        }
+
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            room_id,
+            url,
+            secret: generate_secret(),
+            created_at: Utc::now(),
+        };
+        self.webhooks.insert(webhook.id, webhook.clone());
+
+        Ok(webhook)
+    }
+
+    pub fn remove(&self, room_id: Uuid, webhook_id: Uuid) -> Result<Webhook, WebhookError> {
+        let (_, webhook) = self
+            .webhooks
+            .remove_if(&webhook_id, |_, webhook| webhook.room_id == room_id)
+            .ok_or(WebhookError::NotFound)?;
+        self.logs.remove(&webhook_id);
+
+        Ok(webhook)
+    }
+
+    pub fn for_room(&self, room_id: Uuid) -> Vec<Webhook> {
+        let mut webhooks: Vec<Webhook> = self
+            .webhooks
+            .iter()
+            .filter(|webhook| webhook.room_id == room_id)
+            .map(|webhook| webhook.clone())
+            .collect();
+        webhooks.sort_by_key(|webhook| webhook.created_at);
+        webhooks
+    }
+
+    /// Recent delivery attempts of a room's webhooks, newest first.
+    pub fn deliveries(&self, room_id: Uuid, webhook_id: Option<Uuid>) -> Vec<DeliveryAttempt> {
+        let mut attempts: Vec<DeliveryAttempt> = self
+            .for_room(room_id)
+            .iter()
+            .filter(|webhook| webhook_id.is_none_or(|id| id == webhook.id))
+            .filter_map(|webhook| self.logs.get(&webhook.id))
+            .flat_map(|log| log.iter().cloned().collect::<Vec<_>>())
+            .collect();
+        attempts.sort_by_key(|attempt| std::cmp::Reverse(attempt.timestamp));
+        attempts
+    }
+
+    pub fn dead_letters(&self, room_id: Uuid) -> Vec<DeadLetter> {
+        self.dead_letters
+            .get(&room_id)
+            .map(|letters| letters.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Queues a new room event for every webhook of the room.
+    pub fn dispatch(self: &Arc<Self>, room_id: Uuid, event: &RoomEvent) {
+        for webhook in self.for_room(room_id) {
+            let payload = WebhookPayload {
+                webhook_id: webhook.id,
+                room_id,
+                event: event.clone(),
+            };
+            tokio::spawn(Arc::clone(self).deliver(webhook.id, Uuid::new_v4(), payload));
+        }
+    }
+
+    /// Takes a dead letter off the list and tries to deliver it again.
+    pub fn redeliver(self: &Arc<Self>, room_id: Uuid, delivery_id: Uuid) -> Option<()> {
+        let letter = {
+            let mut letters = self.dead_letters.get_mut(&room_id)?;
+            let index = letters
+                .iter()
+                .position(|letter| letter.delivery_id == delivery_id)?;
+            letters.remove(index)?
+        };
+
+        if !self.webhooks.contains_key(&letter.webhook_id) {
+            return None;
+        }
+
+        tokio::spawn(Arc::clone(self).deliver(letter.webhook_id, delivery_id, letter.payload));
+        Some(())
+    }
+
+    async fn deliver(
+        self: Arc<Self>,
+        webhook_id: Uuid,
+        delivery_id: Uuid,
+        payload: WebhookPayload,
+    ) {
+        let Ok(body) = serde_json::to_vec(&payload) else {
+            return;
+        };
+        let mut backoff = self.initial_backoff;
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            // The webhook may have been removed while we were backing off.
+            let Some(webhook) = self.webhooks.get(&webhook_id).map(|w| w.clone()) else {
+                return;
+            };
+
+            let timestamp = Utc::now();
+            let request = SignedRequest {
+                url: webhook.url.clone(),
+                delivery_id,
+                timestamp: timestamp.timestamp(),
+                signature: sign(&webhook.secret, timestamp.timestamp(), &body),
+                body: body.clone(),
+            };
+
+            let started = std::time::Instant::now();
+            let result = match self.sender.send(&request).await {
+                Ok(status) if (200..300).contains(&status) => Ok(status),
+                Ok(status) => Err((Some(status), format!("status {status}"))),
+                Err(e) => Err((None, e)),
+            };
+            let duration_ms = started.elapsed().as_millis() as u64;
+
+            let (outcome, status_code, error) = match &result {
+                Ok(status) => (DeliveryOutcome::Delivered, Some(*status), None),
+                Err((status, e)) if attempt < MAX_ATTEMPTS => {
+                    (DeliveryOutcome::Retrying, *status, Some(e.clone()))
+                }
+                Err((status, e)) => (DeliveryOutcome::DeadLettered, *status, Some(e.clone())),
+            };
+
+            self.log(DeliveryAttempt {
+                delivery_id,
+                webhook_id,
+                event_id: payload.event.id,
+                attempt,
+                outcome,
+                status_code,
+                error,
+                duration_ms,
+                timestamp,
+            });
+
+            let Err((_, e)) = result else {
+                return;
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                warn!(
+                    "Giving up on delivery {} to webhook {} after {} attempts: {}",
+                    delivery_id, webhook_id, attempt, e
+                );
+                self.dead_letter(DeadLetter {
+                    delivery_id,
+                    webhook_id,
+                    payload,
+                    attempts: attempt,
+                    last_error: e,
+                    failed_at: Utc::now(),
+                });
+                return;
+            }
+
+            info!(
+                "Delivery {} to webhook {} failed ({}), retrying in {:?}",
+                delivery_id, webhook_id, e, backoff
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    fn log(&self, attempt: DeliveryAttempt) {
+        let mut log = self.logs.entry(attempt.webhook_id).or_default();
+        if log.len() == MAX_LOG_ENTRIES {
+            log.pop_front();
+        }
+        log.push_back(attempt);
+    }
+
+    fn dead_letter(&self, letter: DeadLetter) {
+        let mut letters = self.dead_letters.entry(letter.payload.room_id).or_default();
+        if letters.len() == MAX_DEAD_LETTERS {
+            letters.pop_front();
+        }
+        letters.push_back(letter);
+    }
+}
+
+/// Signs `"{timestamp}.{body}"` with HMAC-SHA256 and returns it as
+/// `sha256=<hex>`. Receivers recompute it to check the request came from us,
+/// and reject old timestamps to stop replays.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    let mut signature = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        let _ = write!(signature, "{byte:02x}");
+    }
+    signature
+}
+
+fn generate_secret() -> String {
+    random_token("whsec_")
+}
+
+fn random_token(prefix: &str) -> String {
+    format!(
+        "{prefix}{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        Router,
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+    };
+    use socketioxide::socket::Sid;
+
+    use super::*;
+    use crate::models::{RoomEventData, TextMessageEvent};
+
+    /// A local receiver that records every request and answers with the
+    /// queued statuses, then with 200.
+    #[derive(Clone, Default)]
+    struct Receiver {
+        requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+        statuses: Arc<Mutex<VecDeque<u16>>>,
+    }
+
+    impl Receiver {
+        async fn serve(&self) -> SocketAddr {
+            let router = Router::new()
+                .route(
+                    "/hook",
+                    post(
+                        |State(receiver): State<Receiver>, headers: HeaderMap, body: Bytes| async move {
+                            receiver.requests.lock().unwrap().push((headers, body));
+                            let status = receiver.statuses.lock().unwrap().pop_front();
+                            StatusCode::from_u16(status.unwrap_or(200)).unwrap()
+                        },
+                    ),
+                )
+                .with_state(self.clone());
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+            addr
+        }
+
+        fn fail_next(&self, status: u16, times: usize) {
+            self.statuses
+                .lock()
+                .unwrap()
+                .extend(std::iter::repeat_n(status, times));
+        }
+
+        fn requests(&self) -> Vec<(HeaderMap, Bytes)> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn service() -> Arc<WebhookService> {
+        let mut service = WebhookService::new(Arc::new(HttpSender::new(true)));
+        service.initial_backoff = Duration::from_millis(10);
+        Arc::new(service)
+    }
+
+    fn message(content: &str) -> RoomEvent {
+        RoomEvent {
+            id: Uuid::new_v4(),
+            from: Sid::new(),
+            timestamp: Utc::now(),
+            data: RoomEventData::Message(TextMessageEvent {
+                content: content.to_string(),
+                edited: false,
+                deleted: false,
+                reply_to: None,
+                expires_at: None,
+                mentions: Vec::new(),
+                mentions_room: false,
+                formatted: None,
+                link_previews: Vec::new(),
+                emote: false,
+            }),
+            sender_key: None,
+        }
+    }
+
+    async fn wait_until(mut done: impl FnMut() -> bool) {
+        for _ in 0..500 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out waiting for webhook deliveries");
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_requests() {
+        let receiver = Receiver::default();
+        let addr = receiver.serve().await;
+        let service = service();
+        let room_id = Uuid::new_v4();
+        let webhook = service
+            .register(room_id, &format!("http://{addr}/hook"))
+            .unwrap();
+        let event = message("hello");
+
+        service.dispatch(room_id, &event);
+        wait_until(|| !service.deliveries(room_id, None).is_empty()).await;
+
+        let requests = receiver.requests();
+        assert_eq!(requests.len(), 1);
+        let (headers, body) = &requests[0];
+
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign(&webhook.secret, timestamp, body)
+        );
+        assert_ne!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign("whsec_other", timestamp, body)
+        );
+
+        let payload: WebhookPayload = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload.webhook_id, webhook.id);
+        assert_eq!(payload.room_id, room_id);
+        assert_eq!(payload.event.id, event.id);
+
+        let attempts = service.deliveries(room_id, Some(webhook.id));
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].outcome, DeliveryOutcome::Delivered);
+        assert_eq!(attempts[0].status_code, Some(200));
+        assert_eq!(
+            headers[DELIVERY_HEADER].to_str().unwrap(),
+            attempts[0].delivery_id.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries() {
+        let receiver = Receiver::default();
+        let addr = receiver.serve().await;
+        receiver.fail_next(500, 2);
+        let service = service();
+        let room_id = Uuid::new_v4();
+        service
+            .register(room_id, &format!("http://{addr}/hook"))
+            .unwrap();
+
+        service.dispatch(room_id, &message("hello"));
+        wait_until(|| {
+            service
+                .deliveries(room_id, None)
+                .iter()
+                .any(|attempt| attempt.outcome == DeliveryOutcome::Delivered)
+        })
+        .await;
+
+        let mut attempts = service.deliveries(room_id, None);
+        attempts.sort_by_key(|attempt| attempt.attempt);
+        let outcomes: Vec<_> = attempts
+            .iter()
+            .map(|attempt| (attempt.attempt, attempt.outcome, attempt.status_code))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                (1, DeliveryOutcome::Retrying, Some(500)),
+                (2, DeliveryOutcome::Retrying, Some(500)),
+                (3, DeliveryOutcome::Delivered, Some(200)),
+            ]
+        );
+
+        // Every attempt is the same delivery, so receivers can deduplicate.
+        let requests = receiver.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|(headers, body)| {
+            headers[DELIVERY_HEADER] == requests[0].0[DELIVERY_HEADER] && *body == requests[0].1
+        }));
+        assert!(service.dead_letters(room_id).is_empty());
+    }
+
+    #[tokio::test]
+    async fn dead_letters_and_redelivers() {
+        let receiver = Receiver::default();
+        let addr = receiver.serve().await;
+        receiver.fail_next(503, MAX_ATTEMPTS as usize);
+        let service = service();
+        let room_id = Uuid::new_v4();
+        let webhook = service
+            .register(room_id, &format!("http://{addr}/hook"))
+            .unwrap();
+        let event = message("hello");
+
+        service.dispatch(room_id, &event);
+        wait_until(|| !service.dead_letters(room_id).is_empty()).await;
+
+        let letters = service.dead_letters(room_id);
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].webhook_id, webhook.id);
+        assert_eq!(letters[0].attempts, MAX_ATTEMPTS);
+        assert_eq!(letters[0].last_error, "status 503");
+        assert_eq!(letters[0].payload.event.id, event.id);
+        assert_eq!(receiver.requests().len(), MAX_ATTEMPTS as usize);
+
+        let last = service
+            .deliveries(room_id, None)
+            .into_iter()
+            .max_by_key(|attempt| attempt.attempt)
+            .unwrap();
+        assert_eq!(last.outcome, DeliveryOutcome::DeadLettered);
+
+        assert!(service.redeliver(room_id, letters[0].delivery_id).is_some());
+        assert!(service.dead_letters(room_id).is_empty());
+        wait_until(|| receiver.requests().len() == MAX_ATTEMPTS as usize + 1).await;
+        wait_until(|| {
+            service
+                .deliveries(room_id, None)
+                .iter()
+                .any(|attempt| attempt.outcome == DeliveryOutcome::Delivered)
+        })
+        .await;
+        assert!(service.redeliver(room_id, letters[0].delivery_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn refuses_private_receivers() {
+        let receiver = Receiver::default();
+        let addr = receiver.serve().await;
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            room_id: Uuid::new_v4(),
+            url: Url::parse(&format!("http://{addr}/hook")).unwrap(),
+            secret: generate_secret(),
+            created_at: Utc::now(),
+        };
+        let request = SignedRequest {
+            url: webhook.url.clone(),
+            delivery_id: Uuid::new_v4(),
+            timestamp: 0,
+            signature: sign(&webhook.secret, 0, b"{}"),
+            body: b"{}".to_vec(),
+        };
+
+        assert!(HttpSender::new(false).send(&request).await.is_err());
+        assert!(receiver.requests().is_empty());
+    }
+}